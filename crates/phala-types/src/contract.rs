@@ -46,18 +46,36 @@ pub mod messaging {
     use alloc::vec::Vec;
     use codec::{Decode, Encode};
 
-    use super::{ContractClusterId, ContractInfo};
-    use crate::WorkerIdentity;
+    use super::{CodeIndex, ContractClusterId, ContractId, ContractInfo};
+    use crate::{WorkerIdentity, WorkerPublicKey};
     use phala_mq::bind_topic;
 
     bind_topic!(ContractEvent<CodeHash, AccountId>, b"phala/contract/event");
     #[derive(Encode, Decode, Debug)]
     pub enum ContractEvent<CodeHash, AccountId> {
-        // TODO.shelven: enable add and remove workers
         InstantiateCode {
             contract_info: ContractInfo<CodeHash, AccountId>,
             deploy_workers: Vec<WorkerIdentity>,
         },
+        AddClusterWorker {
+            cluster_id: ContractClusterId,
+            worker: WorkerIdentity,
+        },
+        /// Only the pubkey is sent, as the worker may have been removed from the registry
+        RemoveClusterWorker {
+            cluster_id: ContractClusterId,
+            worker: WorkerPublicKey,
+        },
+        /// Replaces the code of the contract, keeping its id and storage
+        UpgradeContract {
+            cluster_id: ContractClusterId,
+            contract_id: ContractId,
+            code_index: CodeIndex<CodeHash>,
+        },
+        TerminateContract {
+            cluster_id: ContractClusterId,
+            contract_id: ContractId,
+        },
     }
 
     impl<CodeHash, AccountId> ContractEvent<CodeHash, AccountId> {
@@ -70,6 +88,36 @@ pub mod messaging {
                 deploy_workers,
             }
         }
+
+        pub fn add_cluster_worker(cluster_id: ContractClusterId, worker: WorkerIdentity) -> Self {
+            ContractEvent::AddClusterWorker { cluster_id, worker }
+        }
+
+        pub fn remove_cluster_worker(
+            cluster_id: ContractClusterId,
+            worker: WorkerPublicKey,
+        ) -> Self {
+            ContractEvent::RemoveClusterWorker { cluster_id, worker }
+        }
+
+        pub fn upgrade_contract(
+            cluster_id: ContractClusterId,
+            contract_id: ContractId,
+            code_index: CodeIndex<CodeHash>,
+        ) -> Self {
+            ContractEvent::UpgradeContract {
+                cluster_id,
+                contract_id,
+                code_index,
+            }
+        }
+
+        pub fn terminate_contract(cluster_id: ContractClusterId, contract_id: ContractId) -> Self {
+            ContractEvent::TerminateContract {
+                cluster_id,
+                contract_id,
+            }
+        }
     }
 
     bind_topic!(ContractOperation<AccountId>, b"phala/contract/op");
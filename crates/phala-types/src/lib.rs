@@ -22,7 +22,7 @@ pub mod messaging {
     #[cfg(feature = "enable_serde")]
     use serde::{Deserialize, Serialize};
 
-    use super::{EcdhPublicKey, MasterPublicKey, WorkerPublicKey};
+    use super::{EcdhPublicKey, MasterPublicKey, TeeType, WorkerPublicKey};
     use crate::contract::ContractInfo;
     pub use phala_mq::bind_topic;
     pub use phala_mq::types::*;
@@ -275,6 +275,8 @@ pub mod messaging {
     #[derive(Encode, Decode, Debug, TypeInfo)]
     pub struct WorkerInfo {
         pub confidence_level: u8,
+        /// Lets the gatekeeper apply the confidence scores of the TEE type
+        pub tee_type: TeeType,
     }
 
     #[derive(Encode, Decode, Debug, TypeInfo)]
@@ -309,6 +311,15 @@ pub mod messaging {
         ///  When a miner recovered to MiningIdle state from Unresponsive, push this message to the worker to
         ///  resume the subsequent heartbeat responses.
         MiningExitUnresponsive,
+        /// pallet-mining --> worker
+        ///  When a mining worker runs a pRuntime older than the minimum version set by the
+        ///  governance, push this message to warn it to upgrade before the deadline (in sec),
+        ///  after which the miner will be stopped.
+        PRuntimeUpgradeRequired { min_version: u32, deadline: u64 },
+        /// pallet-registry --> worker
+        ///  When a worker is deregistered by its operator, push this message so that it can stop
+        ///  serving on chain.
+        Deregistered,
     }
 
     bind_topic!(SystemEvent, b"phala/system/event");
@@ -391,6 +402,8 @@ pub mod messaging {
     pub enum GatekeeperLaunch {
         FirstGatekeeper(NewGatekeeperEvent),
         MasterPubkeyOnChain(MasterPubkeyEvent),
+        RotateMasterKey(RotateMasterKeyEvent),
+        MasterPubkeyRotated(MasterPubkeyRotatedEvent),
     }
 
     impl GatekeeperLaunch {
@@ -407,6 +420,20 @@ pub mod messaging {
         pub fn master_pubkey_on_chain(master_pubkey: MasterPublicKey) -> GatekeeperLaunch {
             GatekeeperLaunch::MasterPubkeyOnChain(MasterPubkeyEvent { master_pubkey })
         }
+
+        pub fn rotate_master_key(rotation_id: u64) -> GatekeeperLaunch {
+            GatekeeperLaunch::RotateMasterKey(RotateMasterKeyEvent { rotation_id })
+        }
+
+        pub fn master_pubkey_rotated(
+            rotation_id: u64,
+            master_pubkey: MasterPublicKey,
+        ) -> GatekeeperLaunch {
+            GatekeeperLaunch::MasterPubkeyRotated(MasterPubkeyRotatedEvent {
+                rotation_id,
+                master_pubkey,
+            })
+        }
     }
 
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
@@ -422,11 +449,24 @@ pub mod messaging {
         pub master_pubkey: MasterPublicKey,
     }
 
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct RotateMasterKeyEvent {
+        /// The id of the new master key epoch
+        pub rotation_id: u64,
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct MasterPubkeyRotatedEvent {
+        pub rotation_id: u64,
+        pub master_pubkey: MasterPublicKey,
+    }
+
     // Messages: Gatekeeper change
     bind_topic!(GatekeeperChange, b"phala/gatekeeper/change");
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
     pub enum GatekeeperChange {
         GatekeeperRegistered(NewGatekeeperEvent),
+        GatekeeperUnregistered(RemoveGatekeeperEvent),
     }
 
     impl GatekeeperChange {
@@ -439,6 +479,37 @@ pub mod messaging {
                 ecdh_pubkey,
             })
         }
+
+        pub fn gatekeeper_unregistered(pubkey: WorkerPublicKey) -> GatekeeperChange {
+            GatekeeperChange::GatekeeperUnregistered(RemoveGatekeeperEvent { pubkey })
+        }
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct RemoveGatekeeperEvent {
+        /// The public key of removed gatekeeper
+        pub pubkey: WorkerPublicKey,
+    }
+
+    /// The payload a gatekeeper signs to unregister itself.
+    ///
+    /// `last_updated` is the `last_updated` field of the on-chain worker info, so a signature
+    /// can't be replayed once the worker has registered again. `generation` is bumped each time
+    /// the gatekeeper is added or removed, so it can't be replayed after the gatekeeper is
+    /// re-added either. `genesis_hash` binds the signature to a single chain.
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct GatekeeperUnregistration {
+        pub pubkey: WorkerPublicKey,
+        pub last_updated: u64,
+        pub generation: u32,
+        pub genesis_hash: sp_core::H256,
+    }
+
+    impl GatekeeperUnregistration {
+        /// The bytes to be signed by the gatekeeper's worker key
+        pub fn signing_payload(&self) -> Vec<u8> {
+            (b"phala/gatekeeper/unregister", self).encode()
+        }
     }
 
     // Messages: Distribution of master key and contract keys
@@ -513,6 +584,11 @@ pub mod messaging {
         NewRandomNumber(RandomNumberEvent),
         TokenomicParametersChanged(TokenomicParameters),
         RepairV,
+        /// The confidence scores of level 1 to 5
+        ConfidenceScoresChanged(Vec<U64F64Bits>),
+        /// The confidence scores of level 1 to 5 for a TEE type, overriding the default ones.
+        /// `None` restores the default.
+        TeeConfidenceScoresChanged(TeeType, Option<Vec<U64F64Bits>>),
     }
 
     impl GatekeeperEvent {
@@ -643,6 +719,23 @@ pub struct WorkerIdentity {
     pub ecdh_pubkey: EcdhPublicKey,
 }
 
+/// The type of the TEE a worker runs in
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, TypeInfo)]
+pub enum TeeType {
+    /// Intel SGX
+    Sgx,
+    /// Intel TDX
+    Tdx,
+    /// AMD SEV-SNP
+    SevSnp,
+}
+
+impl Default for TeeType {
+    fn default() -> Self {
+        TeeType::Sgx
+    }
+}
+
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, TypeInfo)]
 pub struct WorkerRegistrationInfo<AccountId> {
     pub version: u32,
@@ -667,6 +760,17 @@ pub struct StashWorkerStats<Balance> {
     pub online_received: Balance,
 }
 
+/// The mining subsidy accounting in a halving period
+#[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq, TypeInfo)]
+pub struct SubsidyPeriodStats<Balance> {
+    /// The total payout settled to the miners
+    pub payout: Balance,
+    /// The total treasury share withdrawn from the subsidy pool
+    pub treasury: Balance,
+    /// The balance of the subsidy pool at the last settlement
+    pub pool_balance: Balance,
+}
+
 #[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq, TypeInfo)]
 pub struct RoundStats {
     pub round: u32,
@@ -260,6 +260,7 @@ fn genesis(
             },
         ),
         phala_mining: Default::default(),
+        phala_fat_contracts: Default::default(),
         polkadot_xcm: rhala_parachain_runtime::PolkadotXcmConfig {
             safe_xcm_version: Some(2),
         },
@@ -0,0 +1,15 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use phala_types::SubsidyPeriodStats;
+use sp_std::vec::Vec;
+
+sp_api::decl_runtime_apis! {
+	pub trait MiningApi<AccountId, Balance> where AccountId: codec::Codec, Balance: codec::Codec {
+		/// The recent `p_instant` samples of a miner, oldest first.
+		fn performance_history(miner: AccountId) -> Vec<u32>;
+		/// The index of the current halving period.
+		fn halving_period() -> u32;
+		/// The subsidy accounting of a halving period.
+		fn subsidy_stats(period: u32) -> SubsidyPeriodStats<Balance>;
+	}
+}
@@ -4,19 +4,30 @@ pub use self::pallet::*;
 #[frame_support::pallet]
 pub mod pallet {
 	use codec::Encode;
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, ReservableCurrency, StorageVersion},
+	};
 	use frame_system::pallet_prelude::*;
+	use scale_info::TypeInfo;
 	use sp_core::H256;
-	use sp_runtime::traits::Hash;
+	use sp_runtime::traits::{Hash, Saturating};
 	use sp_std::prelude::*;
 
-	use crate::{mq::MessageOriginInfo, registry};
+	use crate::{
+		mq::{IntoH256, MessageOriginInfo},
+		registry,
+	};
 	// Re-export
 	pub use crate::attestation::{Attestation, IasValidator};
 
 	use phala_types::{
 		contract::messaging::{ContractEvent, ContractOperation},
-		contract::{CodeIndex, ContractClusterId, ContractId, ContractInfo, DeployTarget},
+		contract::{
+			contract_id_preimage, CodeIndex, ContractClusterId, ContractId, ContractInfo,
+			DeployTarget,
+		},
 		messaging::{bind_topic, DecodedMessage, MessageOrigin, WorkerContractReport},
 		ContractPublicKey, WorkerIdentity, WorkerPublicKey,
 	};
@@ -30,9 +41,55 @@ pub mod pallet {
 		},
 	}
 
+	/// The default cap of the uploaded code size in bytes
+	pub const DEFAULT_MAX_CODE_SIZE: u32 = 2 * 1024 * 1024;
+	/// The weight to hash and store each byte of the uploaded code
+	pub const CODE_WEIGHT_PER_BYTE: Weight = 2_000;
+	/// The max number of entries in the code history of a contract, the instantiated code included
+	pub const MAX_CONTRACT_CODE_HISTORY: usize = 64;
+
+	/// Deposit and usage of an uploaded code
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct CodeInfo<AccountId, Balance> {
+		/// The account uploaded the code
+		pub owner: AccountId,
+		/// The deposit reserved from the owner
+		pub deposit: Balance,
+		/// The number of contracts instantiated from the code
+		pub refcount: u32,
+	}
+
+	/// Who can upload code and deploy contracts to a cluster, in addition to the owner
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub enum ClusterPermission<AccountId> {
+		Public,
+		OnlyAllowed(Vec<AccountId>),
+	}
+
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct ClusterInfo<AccountId> {
+		pub owner: AccountId,
+		pub permission: ClusterPermission<AccountId>,
+	}
+
+	#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+	pub enum ContractStatus {
+		/// Waiting for the cluster workers to report the instantiation result
+		Instantiating,
+		Instantiated,
+		/// Failed to instantiate, the contract is cleaned up and can be deployed again
+		Failed,
+		/// Terminated, the contract id can't be deployed again
+		Terminated,
+	}
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
 		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+		type Currency: ReservableCurrency<Self::AccountId>;
+		/// The deposit reserved for each byte of the uploaded code
+		type CodeDepositPerByte: Get<BalanceOf<Self>>;
+		type GovernanceOrigin: EnsureOrigin<Self::Origin>;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
@@ -47,6 +104,15 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type Code<T: Config> = StorageMap<_, Twox64Concat, CodeHash<T>, Vec<u8>>;
 
+	/// The deposit and the reference count of the uploaded code
+	#[pallet::storage]
+	pub type CodeInfos<T: Config> =
+		StorageMap<_, Twox64Concat, CodeHash<T>, CodeInfo<T::AccountId, BalanceOf<T>>>;
+
+	/// The cap of the uploaded code size (`DEFAULT_MAX_CODE_SIZE` if not set)
+	#[pallet::storage]
+	pub type MaxCodeSize<T> = StorageValue<_, u32>;
+
 	/// The contract cluster counter, it always equals to the latest cluster id.
 	#[pallet::storage]
 	pub type ClusterCounter<T> = StorageValue<_, u64, ValueQuery>;
@@ -59,16 +125,66 @@ pub mod pallet {
 	pub type Contracts<T: Config> =
 		StorageMap<_, Twox64Concat, ContractId, ContractInfo<CodeHash<T>, T::AccountId>>;
 
+	/// The instantiation status of the contracts
+	#[pallet::storage]
+	pub type ContractStatuses<T> = StorageMap<_, Twox64Concat, ContractId, ContractStatus>;
+
+	/// The instantiation results reported by the cluster workers, before a quorum is reached
+	#[pallet::storage]
+	pub type InstantiationReports<T> =
+		StorageMap<_, Twox64Concat, ContractId, Vec<(WorkerPublicKey, bool)>, ValueQuery>;
+
+	/// The codes the contracts are instantiated with and upgraded to, with the block numbers
+	///
+	/// The first entry is the instantiated code and the last entry is the running code. It's kept
+	/// after the contract is terminated, and holds at most `MAX_CONTRACT_CODE_HISTORY` entries.
+	#[pallet::storage]
+	pub type ContractCodeHistory<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		ContractId,
+		Vec<(CodeIndex<CodeHash<T>>, T::BlockNumber)>,
+		ValueQuery,
+	>;
+
 	#[pallet::storage]
 	pub type ClusterWorkers<T> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<WorkerPublicKey>>;
 
+	/// The owner and the permission of the clusters
+	///
+	/// Clusters created before the ownership was introduced have no entry and stay public.
+	#[pallet::storage]
+	pub type ClusterInfos<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, ClusterInfo<T::AccountId>>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
 		CodeUploaded {
 			hash: CodeHash<T>,
 		},
+		CodeRemoved {
+			hash: CodeHash<T>,
+		},
+		MaxCodeSizeChanged {
+			max_size: u32,
+		},
+		ClusterCreated {
+			cluster: ContractClusterId,
+			owner: T::AccountId,
+		},
+		ClusterWorkerAdded {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		},
+		ClusterWorkerRemoved {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		},
+		ClusterPermissionChanged {
+			cluster: ContractClusterId,
+		},
 		PubkeyAvailable {
 			contract: ContractId,
 			pubkey: ContractPublicKey,
@@ -88,6 +204,17 @@ pub mod pallet {
 			cluster: ContractClusterId,
 			deployer: H256,
 		},
+		ContractUpgraded {
+			contract: ContractId,
+			code_index: CodeIndex<CodeHash<T>>,
+		},
+		ContractTerminated {
+			contract: ContractId,
+		},
+		ClusterOwnerChanged {
+			cluster: ContractClusterId,
+			owner: T::AccountId,
+		},
 	}
 
 	#[pallet::error]
@@ -99,33 +226,105 @@ pub mod pallet {
 		NoWorkerSpecified,
 		InvalidSender,
 		WorkerNotFound,
+		CodeTooLarge,
+		CodeAlreadyExists,
+		CodeInUse,
+		NotCodeOwner,
+		InvalidMaxCodeSize,
+		NotClusterOwner,
+		ClusterPermissionDenied,
+		DuplicatedClusterWorker,
+		WorkerNotInCluster,
+		CannotRemoveLastClusterWorker,
+		ContractNotFound,
+		ContractClusterMismatch,
+		NotContractDeployer,
+		ContractNotInstantiated,
+		ContractNotInstantiating,
+		ContractTerminated,
+		ContractCountUnderestimated,
+		ContractCodeHistoryFull,
 	}
 
 	type CodeHash<T> = <T as frame_system::Config>::Hash;
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 
 	#[pallet::call]
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config + crate::registry::Config,
-		T::AccountId: AsRef<[u8]>,
+		T::AccountId: IntoH256,
 	{
-		#[pallet::weight(0)]
+		#[pallet::weight(
+			T::DbWeight::get().reads_writes(2, 3)
+				+ (code.len() as Weight).saturating_mul(CODE_WEIGHT_PER_BYTE)
+		)]
 		pub fn upload_code(origin: OriginFor<T>, code: Vec<u8>) -> DispatchResult {
-			ensure_signed(origin)?;
+			let owner = ensure_signed(origin)?;
+			Self::ensure_code_size(&code)?;
 			let hash = T::Hashing::hash(&code);
+			ensure!(
+				!Code::<T>::contains_key(&hash),
+				Error::<T>::CodeAlreadyExists
+			);
+			let deposit = T::CodeDepositPerByte::get().saturating_mul((code.len() as u32).into());
+			T::Currency::reserve(&owner, deposit)?;
 			Code::<T>::insert(&hash, &code);
+			CodeInfos::<T>::insert(
+				&hash,
+				CodeInfo {
+					owner,
+					deposit,
+					refcount: 0,
+				},
+			);
 			Self::deposit_event(Event::CodeUploaded { hash });
 			Ok(())
 		}
 
+		/// Removes an uploaded code not used by any contract and refunds the deposit
+		///
+		/// Can only be called by the uploader of the code.
+		#[pallet::weight(0)]
+		pub fn remove_code(origin: OriginFor<T>, code_hash: CodeHash<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let info = CodeInfos::<T>::get(&code_hash).ok_or(Error::<T>::CodeNotFound)?;
+			ensure!(info.owner == who, Error::<T>::NotCodeOwner);
+			ensure!(info.refcount == 0, Error::<T>::CodeInUse);
+			Code::<T>::remove(&code_hash);
+			CodeInfos::<T>::remove(&code_hash);
+			T::Currency::unreserve(&info.owner, info.deposit);
+			Self::deposit_event(Event::CodeRemoved { hash: code_hash });
+			Ok(())
+		}
+
+		/// Sets the cap of the uploaded code size
+		///
+		/// Resets to `DEFAULT_MAX_CODE_SIZE` if `None` is given.
 		#[pallet::weight(0)]
+		pub fn set_max_code_size(origin: OriginFor<T>, max_size: Option<u32>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(max_size != Some(0), Error::<T>::InvalidMaxCodeSize);
+			MaxCodeSize::<T>::set(max_size);
+			Self::deposit_event(Event::MaxCodeSizeChanged {
+				max_size: max_size.unwrap_or(DEFAULT_MAX_CODE_SIZE),
+			});
+			Ok(())
+		}
+
+		#[pallet::weight(
+			T::DbWeight::get().reads_writes(3, 1)
+				+ (code.len() as Weight).saturating_mul(CODE_WEIGHT_PER_BYTE)
+		)]
 		pub fn upload_code_to_cluster(
 			origin: OriginFor<T>,
 			code: Vec<u8>,
 			cluster_id: ContractClusterId,
 		) -> DispatchResult {
 			let origin: T::AccountId = ensure_signed(origin)?;
-			// TODO.shelven: check permission?
+			Self::ensure_code_size(&code)?;
+			Self::ensure_cluster_permission(cluster_id, &origin)?;
 			Self::push_message(ContractOperation::UploadCodeToCluster {
 				origin,
 				code,
@@ -144,11 +343,8 @@ pub mod pallet {
 		) -> DispatchResult {
 			let deployer = ensure_signed(origin)?;
 
-			match code_index {
-				CodeIndex::NativeCode(_) => {}
-				CodeIndex::WasmCode(code_hash) => {
-					ensure!(Code::<T>::contains_key(code_hash), Error::<T>::CodeNotFound);
-				}
+			if let CodeIndex::WasmCode(code_hash) = &code_index {
+				ensure!(Code::<T>::contains_key(code_hash), Error::<T>::CodeNotFound);
 			}
 
 			let mut new_cluster = false;
@@ -156,6 +352,7 @@ pub mod pallet {
 				DeployTarget::Cluster(cluster_id) => {
 					let workers = ClusterWorkers::<T>::get(cluster_id)
 						.ok_or(Error::<T>::ContractClusterNotFound)?;
+					Self::ensure_cluster_permission(cluster_id, &deployer)?;
 					(cluster_id, workers)
 				}
 				DeployTarget::NewGroup(deploy_workers) => {
@@ -173,15 +370,21 @@ pub mod pallet {
 
 			let mut workers = Vec::new();
 			for worker in &deploy_workers {
-				let worker_info =
-					registry::Workers::<T>::try_get(worker).or(Err(Error::<T>::WorkerNotFound))?;
-				workers.push(WorkerIdentity {
-					pubkey: worker_info.pubkey,
-					ecdh_pubkey: worker_info.ecdh_pubkey,
-				});
+				workers.push(Self::worker_identity(worker)?);
 			}
 			if new_cluster {
 				ClusterWorkers::<T>::insert(&cluster_id, deploy_workers);
+				ClusterInfos::<T>::insert(
+					&cluster_id,
+					ClusterInfo {
+						owner: deployer.clone(),
+						permission: ClusterPermission::OnlyAllowed(Vec::new()),
+					},
+				);
+				Self::deposit_event(Event::ClusterCreated {
+					cluster: cluster_id,
+					owner: deployer.clone(),
+				});
 			}
 
 			// We send code index instead of raw code here to reduce message size
@@ -192,13 +395,24 @@ pub mod pallet {
 				cluster_id,
 				instantiate_data: data,
 			};
-			let contract_id = contract_info.contract_id(Box::new(crate::hashing::blake2_256));
+			let contract_id = Self::contract_id_of(&contract_info);
 			ensure!(
 				!Contracts::<T>::contains_key(contract_id),
 				Error::<T>::DuplicatedContract
 			);
+			ensure!(
+				ContractStatuses::<T>::get(contract_id) != Some(ContractStatus::Terminated),
+				Error::<T>::ContractTerminated
+			);
 			Contracts::<T>::insert(&contract_id, &contract_info);
 			Clusters::<T>::append(cluster_id, contract_id);
+			ContractStatuses::<T>::insert(&contract_id, ContractStatus::Instantiating);
+			let now = frame_system::Pallet::<T>::block_number();
+			ContractCodeHistory::<T>::insert(
+				&contract_id,
+				vec![(contract_info.code_index.clone(), now)],
+			);
+			Self::retain_code(&contract_info.code_index);
 
 			Self::push_message(ContractEvent::instantiate_code(
 				contract_info.clone(),
@@ -212,12 +426,386 @@ pub mod pallet {
 
 			Ok(())
 		}
+
+		/// Adds a worker to the cluster
+		///
+		/// Can be called by the cluster owner or the governance.
+		#[pallet::weight(0)]
+		pub fn add_cluster_worker(
+			origin: OriginFor<T>,
+			cluster_id: ContractClusterId,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			Self::ensure_cluster_admin(origin, cluster_id)?;
+			let mut workers =
+				ClusterWorkers::<T>::get(cluster_id).ok_or(Error::<T>::ContractClusterNotFound)?;
+			ensure!(
+				!workers.contains(&worker),
+				Error::<T>::DuplicatedClusterWorker
+			);
+			let identity = Self::worker_identity(&worker)?;
+			workers.push(worker.clone());
+			ClusterWorkers::<T>::insert(cluster_id, workers);
+			Self::push_message(
+				ContractEvent::<CodeHash<T>, T::AccountId>::add_cluster_worker(
+					cluster_id, identity,
+				),
+			);
+			Self::deposit_event(Event::ClusterWorkerAdded {
+				cluster: cluster_id,
+				worker,
+			});
+			Ok(())
+		}
+
+		/// Removes a worker from the cluster, even if it's no longer registered
+		///
+		/// Can be called by the cluster owner or the governance. The last worker of a cluster
+		/// can't be removed. The quorum of the pending instantiations is checked again, so
+		/// `contract_count` must be at least the number of contracts in the cluster.
+		#[pallet::weight(T::DbWeight::get().reads_writes(
+			3 + 3 * *contract_count as Weight,
+			2 + 7 * *contract_count as Weight,
+		))]
+		pub fn remove_cluster_worker(
+			origin: OriginFor<T>,
+			cluster_id: ContractClusterId,
+			worker: WorkerPublicKey,
+			contract_count: u32,
+		) -> DispatchResult {
+			Self::ensure_cluster_admin(origin, cluster_id)?;
+			let mut workers =
+				ClusterWorkers::<T>::get(cluster_id).ok_or(Error::<T>::ContractClusterNotFound)?;
+			let pos = workers
+				.iter()
+				.position(|w| *w == worker)
+				.ok_or(Error::<T>::WorkerNotInCluster)?;
+			ensure!(workers.len() > 1, Error::<T>::CannotRemoveLastClusterWorker);
+			let contracts = Clusters::<T>::get(cluster_id);
+			ensure!(
+				contracts.len() <= contract_count as usize,
+				Error::<T>::ContractCountUnderestimated
+			);
+			workers.remove(pos);
+			// The removed worker no longer counts toward the pending instantiations
+			for contract_id in contracts {
+				if ContractStatuses::<T>::get(contract_id) != Some(ContractStatus::Instantiating) {
+					continue;
+				}
+				if let Some(contract_info) = Contracts::<T>::get(contract_id) {
+					let reports = InstantiationReports::<T>::get(contract_id);
+					Self::check_instantiation_quorum(
+						contract_id,
+						&contract_info,
+						&workers,
+						reports,
+					);
+				}
+			}
+			ClusterWorkers::<T>::insert(cluster_id, workers);
+			Self::push_message(
+				ContractEvent::<CodeHash<T>, T::AccountId>::remove_cluster_worker(
+					cluster_id, worker,
+				),
+			);
+			Self::deposit_event(Event::ClusterWorkerRemoved {
+				cluster: cluster_id,
+				worker,
+			});
+			Ok(())
+		}
+
+		/// Sets who can upload code and deploy contracts to the cluster
+		///
+		/// Can be called by the cluster owner or the governance. A cluster without an owner must
+		/// be given one by `set_cluster_owner` first.
+		#[pallet::weight(0)]
+		pub fn set_cluster_permission(
+			origin: OriginFor<T>,
+			cluster_id: ContractClusterId,
+			permission: ClusterPermission<T::AccountId>,
+		) -> DispatchResult {
+			let mut info = Self::ensure_cluster_admin(origin, cluster_id)?
+				.ok_or(Error::<T>::NotClusterOwner)?;
+			info.permission = permission;
+			ClusterInfos::<T>::insert(cluster_id, info);
+			Self::deposit_event(Event::ClusterPermissionChanged {
+				cluster: cluster_id,
+			});
+			Ok(())
+		}
+
+		/// Hands the cluster over to a new owner
+		///
+		/// Can be called by the cluster owner or the governance. The governance can also adopt the
+		/// clusters created without an owner, which are then only open to the owner.
+		#[pallet::weight(0)]
+		pub fn set_cluster_owner(
+			origin: OriginFor<T>,
+			cluster_id: ContractClusterId,
+			owner: T::AccountId,
+		) -> DispatchResult {
+			let info = Self::ensure_cluster_admin(origin, cluster_id)?;
+			ensure!(
+				ClusterWorkers::<T>::contains_key(cluster_id),
+				Error::<T>::ContractClusterNotFound
+			);
+			let permission = info
+				.map(|info| info.permission)
+				.unwrap_or_else(|| ClusterPermission::OnlyAllowed(Vec::new()));
+			ClusterInfos::<T>::insert(
+				cluster_id,
+				ClusterInfo {
+					owner: owner.clone(),
+					permission,
+				},
+			);
+			Self::deposit_event(Event::ClusterOwnerChanged {
+				cluster: cluster_id,
+				owner,
+			});
+			Ok(())
+		}
+
+		/// Upgrades the contract to a new code, keeping the contract id
+		///
+		/// Can be called by the deployer of the contract or the governance.
+		#[pallet::weight(0)]
+		pub fn upgrade_contract(
+			origin: OriginFor<T>,
+			contract_id: ContractId,
+			new_code_index: CodeIndex<CodeHash<T>>,
+		) -> DispatchResult {
+			let contract_info = Self::ensure_contract_admin(origin, contract_id)?;
+			// Contracts deployed before the status tracking have no status
+			ensure!(
+				ContractStatuses::<T>::get(contract_id).unwrap_or(ContractStatus::Instantiated)
+					== ContractStatus::Instantiated,
+				Error::<T>::ContractNotInstantiated
+			);
+			if let CodeIndex::WasmCode(code_hash) = &new_code_index {
+				ensure!(Code::<T>::contains_key(code_hash), Error::<T>::CodeNotFound);
+			}
+			ensure!(
+				ContractCodeHistory::<T>::decode_len(contract_id).unwrap_or(0)
+					< MAX_CONTRACT_CODE_HISTORY,
+				Error::<T>::ContractCodeHistoryFull
+			);
+			Self::release_code(&Self::contract_code(contract_id, &contract_info));
+			Self::retain_code(&new_code_index);
+			let now = frame_system::Pallet::<T>::block_number();
+			ContractCodeHistory::<T>::append(contract_id, (new_code_index.clone(), now));
+			Self::push_message(
+				ContractEvent::<CodeHash<T>, T::AccountId>::upgrade_contract(
+					contract_info.cluster_id,
+					contract_id,
+					new_code_index.clone(),
+				),
+			);
+			Self::deposit_event(Event::ContractUpgraded {
+				contract: contract_id,
+				code_index: new_code_index,
+			});
+			Ok(())
+		}
+
+		/// Gives up a contract stuck in instantiation, so that it can be deployed again
+		///
+		/// Can be called by the deployer of the contract or the governance. The cluster is asked
+		/// to destroy the contract in case it was instantiated.
+		#[pallet::weight(0)]
+		pub fn cancel_instantiation(
+			origin: OriginFor<T>,
+			contract_id: ContractId,
+		) -> DispatchResult {
+			let contract_info = Self::ensure_contract_admin(origin, contract_id)?;
+			ensure!(
+				ContractStatuses::<T>::get(contract_id) == Some(ContractStatus::Instantiating),
+				Error::<T>::ContractNotInstantiating
+			);
+			Self::cleanup_failed_contract(contract_id);
+			Self::push_message(
+				ContractEvent::<CodeHash<T>, T::AccountId>::terminate_contract(
+					contract_info.cluster_id,
+					contract_id,
+				),
+			);
+			Self::deposit_event(Event::InstantiationFailed {
+				contract: contract_id,
+				cluster: contract_info.cluster_id,
+				deployer: contract_info.deployer.into_h256(),
+			});
+			Ok(())
+		}
+
+		/// Destroys the contract on the cluster and removes it from the registry
+		///
+		/// Can be called by the deployer of the contract or the governance. The status and the
+		/// code history are kept, so that the contract id is never reused.
+		#[pallet::weight(0)]
+		pub fn terminate_contract(origin: OriginFor<T>, contract_id: ContractId) -> DispatchResult {
+			let contract_info = Self::ensure_contract_admin(origin, contract_id)?;
+			Self::remove_contract(contract_id);
+			ContractStatuses::<T>::insert(contract_id, ContractStatus::Terminated);
+			Self::push_message(
+				ContractEvent::<CodeHash<T>, T::AccountId>::terminate_contract(
+					contract_info.cluster_id,
+					contract_id,
+				),
+			);
+			Self::deposit_event(Event::ContractTerminated {
+				contract: contract_id,
+			});
+			Ok(())
+		}
 	}
 
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config + crate::registry::Config,
+		T::AccountId: IntoH256,
 	{
+		/// Computes the contract id like `ContractInfo::contract_id`, with the deployer account
+		/// converted to H256 (identical bytes for `AccountId32`)
+		pub fn contract_id_of(
+			contract_info: &ContractInfo<CodeHash<T>, T::AccountId>,
+		) -> ContractId {
+			let deployer = contract_info.deployer.clone().into_h256();
+			let preimage = contract_id_preimage(
+				deployer.as_ref(),
+				contract_info.code_index.code_hash().as_ref(),
+				contract_info.cluster_id.as_ref(),
+				contract_info.salt.as_ref(),
+			);
+			ContractId::from(crate::hashing::blake2_256(&preimage))
+		}
+
+		fn worker_identity(worker: &WorkerPublicKey) -> Result<WorkerIdentity, Error<T>> {
+			let worker_info =
+				registry::Workers::<T>::try_get(worker).or(Err(Error::<T>::WorkerNotFound))?;
+			Ok(WorkerIdentity {
+				pubkey: worker_info.pubkey,
+				ecdh_pubkey: worker_info.ecdh_pubkey,
+			})
+		}
+
+		fn ensure_cluster_owner(
+			cluster_id: ContractClusterId,
+			who: &T::AccountId,
+		) -> Result<ClusterInfo<T::AccountId>, Error<T>> {
+			let info = ClusterInfos::<T>::get(cluster_id).ok_or(Error::<T>::NotClusterOwner)?;
+			ensure!(info.owner == *who, Error::<T>::NotClusterOwner);
+			Ok(info)
+		}
+
+		/// Checks if the origin is the governance or the cluster owner, and returns the cluster info
+		///
+		/// The info is `None` only if the governance manages a cluster without an owner.
+		fn ensure_cluster_admin(
+			origin: OriginFor<T>,
+			cluster_id: ContractClusterId,
+		) -> Result<Option<ClusterInfo<T::AccountId>>, DispatchError> {
+			if let Err(origin) = T::GovernanceOrigin::try_origin(origin) {
+				let who = ensure_signed(origin)?;
+				return Ok(Some(Self::ensure_cluster_owner(cluster_id, &who)?));
+			}
+			Ok(ClusterInfos::<T>::get(cluster_id))
+		}
+
+		/// Checks if the account can upload code and deploy contracts to the cluster
+		fn ensure_cluster_permission(
+			cluster_id: ContractClusterId,
+			who: &T::AccountId,
+		) -> DispatchResult {
+			ensure!(
+				ClusterWorkers::<T>::contains_key(cluster_id),
+				Error::<T>::ContractClusterNotFound
+			);
+			if let Some(info) = ClusterInfos::<T>::get(cluster_id) {
+				let allowed = info.owner == *who
+					|| match &info.permission {
+						ClusterPermission::Public => true,
+						ClusterPermission::OnlyAllowed(accounts) => accounts.contains(who),
+					};
+				ensure!(allowed, Error::<T>::ClusterPermissionDenied);
+			}
+			Ok(())
+		}
+
+		fn ensure_code_size(code: &[u8]) -> DispatchResult {
+			let max_size = MaxCodeSize::<T>::get().unwrap_or(DEFAULT_MAX_CODE_SIZE);
+			ensure!(code.len() <= max_size as usize, Error::<T>::CodeTooLarge);
+			Ok(())
+		}
+
+		/// Counts a new contract referencing the code
+		fn retain_code(code_index: &CodeIndex<CodeHash<T>>) {
+			if let CodeIndex::WasmCode(code_hash) = code_index {
+				CodeInfos::<T>::mutate(code_hash, |info| {
+					if let Some(info) = info {
+						info.refcount += 1;
+					}
+				});
+			}
+		}
+
+		/// Drops a contract reference to the code, so that it can be removed when unused
+		fn release_code(code_index: &CodeIndex<CodeHash<T>>) {
+			if let CodeIndex::WasmCode(code_hash) = code_index {
+				CodeInfos::<T>::mutate(code_hash, |info| {
+					if let Some(info) = info {
+						info.refcount = info.refcount.saturating_sub(1);
+					}
+				});
+			}
+		}
+
+		/// Returns the running code of the contract
+		pub fn contract_code(
+			contract_id: ContractId,
+			contract_info: &ContractInfo<CodeHash<T>, T::AccountId>,
+		) -> CodeIndex<CodeHash<T>> {
+			match ContractCodeHistory::<T>::get(contract_id).pop() {
+				Some((code_index, _)) => code_index,
+				None => contract_info.code_index.clone(),
+			}
+		}
+
+		fn ensure_contract_admin(
+			origin: OriginFor<T>,
+			contract_id: ContractId,
+		) -> Result<ContractInfo<CodeHash<T>, T::AccountId>, DispatchError> {
+			let contract_info =
+				Contracts::<T>::get(contract_id).ok_or(Error::<T>::ContractNotFound)?;
+			if let Err(origin) = T::GovernanceOrigin::try_origin(origin) {
+				let who = ensure_signed(origin)?;
+				ensure!(
+					contract_info.deployer == who,
+					Error::<T>::NotContractDeployer
+				);
+			}
+			Ok(contract_info)
+		}
+
+		/// Removes the contract with its code reference and public key
+		fn remove_contract(contract_id: ContractId) {
+			if let Some(info) = Contracts::<T>::take(contract_id) {
+				Clusters::<T>::mutate(info.cluster_id, |contracts| {
+					contracts.retain(|id| *id != contract_id)
+				});
+				Self::release_code(&Self::contract_code(contract_id, &info));
+			}
+			registry::ContractKeys::<T>::remove(contract_id);
+			InstantiationReports::<T>::remove(contract_id);
+		}
+
+		/// Removes a contract failed to instantiate, so that it can be deployed again
+		fn cleanup_failed_contract(contract_id: ContractId) {
+			Self::remove_contract(contract_id);
+			ContractCodeHistory::<T>::remove(contract_id);
+			ContractStatuses::<T>::insert(contract_id, ContractStatus::Failed);
+		}
+
 		pub fn on_contract_message_received(
 			message: DecodedMessage<ContractRegistryEvent>,
 		) -> DispatchResult {
@@ -237,41 +825,791 @@ pub mod pallet {
 		pub fn on_worker_contract_message_received(
 			message: DecodedMessage<WorkerContractReport>,
 		) -> DispatchResult {
-			let _worker_pubkey = match &message.sender {
-				MessageOrigin::Worker(worker_pubkey) => worker_pubkey,
+			let worker_pubkey = match &message.sender {
+				MessageOrigin::Worker(worker_pubkey) => worker_pubkey.clone(),
 				_ => return Err(Error::<T>::InvalidSender.into()),
 			};
-			match message.payload {
-				WorkerContractReport::ContractInstantiated {
-					id,
-					cluster_id,
-					deployer,
-					pubkey: _,
-				} => {
-					Self::deposit_event(Event::Instantiated {
-						contract: id,
-						cluster: cluster_id,
-						deployer,
-					});
+			// The deployer is taken from the registry rather than trusting the report
+			let (id, cluster_id, succeeded) = match message.payload {
+				WorkerContractReport::ContractInstantiated { id, cluster_id, .. } => {
+					(id, cluster_id, true)
 				}
-				WorkerContractReport::ContractInstantiationFailed {
-					id,
-					cluster_id,
-					deployer,
-				} => {
-					Self::deposit_event(Event::InstantiationFailed {
-						contract: id,
-						cluster: cluster_id,
-						deployer,
-					});
-					// TODO.shelven: some cleanup?
+				WorkerContractReport::ContractInstantiationFailed { id, cluster_id, .. } => {
+					(id, cluster_id, false)
 				}
+			};
+			// Reports arriving after the quorum is reached are ignored
+			if ContractStatuses::<T>::get(id) != Some(ContractStatus::Instantiating) {
+				return Ok(());
+			}
+			let contract_info = Contracts::<T>::get(id).ok_or(Error::<T>::ContractNotFound)?;
+			ensure!(
+				contract_info.cluster_id == cluster_id,
+				Error::<T>::ContractClusterMismatch
+			);
+			let workers =
+				ClusterWorkers::<T>::get(cluster_id).ok_or(Error::<T>::ContractClusterNotFound)?;
+			ensure!(
+				workers.contains(&worker_pubkey),
+				Error::<T>::WorkerNotInCluster
+			);
+
+			let mut reports = InstantiationReports::<T>::get(id);
+			match reports
+				.iter_mut()
+				.find(|(worker, _)| *worker == worker_pubkey)
+			{
+				Some(report) => report.1 = succeeded,
+				None => reports.push((worker_pubkey, succeeded)),
 			}
+			Self::check_instantiation_quorum(id, &contract_info, &workers, reports);
 			Ok(())
 		}
+
+		/// Settles the instantiation once the majority of the cluster workers agree on it
+		fn check_instantiation_quorum(
+			contract_id: ContractId,
+			contract_info: &ContractInfo<CodeHash<T>, T::AccountId>,
+			workers: &[WorkerPublicKey],
+			mut reports: Vec<(WorkerPublicKey, bool)>,
+		) {
+			// Only the current cluster workers count
+			reports.retain(|(worker, _)| workers.contains(worker));
+			let num_succeeded = reports.iter().filter(|(_, succeeded)| *succeeded).count();
+			let num_pending = workers.len() - reports.len();
+			let deployer = contract_info.deployer.clone().into_h256();
+			if num_succeeded * 2 > workers.len() {
+				InstantiationReports::<T>::remove(contract_id);
+				ContractStatuses::<T>::insert(contract_id, ContractStatus::Instantiated);
+				Self::deposit_event(Event::Instantiated {
+					contract: contract_id,
+					cluster: contract_info.cluster_id,
+					deployer,
+				});
+			} else if (num_succeeded + num_pending) * 2 <= workers.len() {
+				// The majority can't be reached anymore
+				Self::cleanup_failed_contract(contract_id);
+				Self::deposit_event(Event::InstantiationFailed {
+					contract: contract_id,
+					cluster: contract_info.cluster_id,
+					deployer,
+				});
+			} else {
+				InstantiationReports::<T>::insert(contract_id, reports);
+			}
+		}
+	}
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig {
+		/// The cap of the uploaded code size (`DEFAULT_MAX_CODE_SIZE` if `None`)
+		pub max_code_size: Option<u32>,
+	}
+
+	#[cfg(feature = "std")]
+	impl Default for GenesisConfig {
+		fn default() -> Self {
+			Self {
+				max_code_size: None,
+			}
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig {
+		fn build(&self) {
+			assert!(self.max_code_size != Some(0), "Invalid max code size");
+			MaxCodeSize::<T>::set(self.max_code_size);
+		}
 	}
 
 	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
 		type Config = T;
 	}
+
+	#[cfg(test)]
+	mod test {
+		use frame_support::{assert_noop, assert_ok};
+		use sp_core::Pair;
+
+		use super::*;
+		use crate::mock::{
+			ecdh_pubkey, new_test_ext, set_block_1, setup_workers, take_events, take_messages,
+			worker_pubkey, Event as TestEvent, Origin, Test, CENTS,
+		};
+		// Pallets
+		use crate::mock::{Balances, PhalaFatContracts, PhalaRegistry};
+		use phala_types::messaging::{Message, SignedMessage, Topic};
+
+		#[test]
+		fn test_contract_deployment_flow() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				let code = vec![0u8; 16];
+				let code_hash = <Test as frame_system::Config>::Hashing::hash(&code);
+				assert_ok!(PhalaFatContracts::upload_code(Origin::signed(1), code));
+				assert!(Code::<Test>::contains_key(&code_hash));
+
+				// Deploy to a new cluster
+				assert_ok!(PhalaFatContracts::instantiate_contract(
+					Origin::signed(1),
+					CodeIndex::WasmCode(code_hash),
+					vec![],
+					b"salt".to_vec(),
+					DeployTarget::NewGroup(vec![worker_pubkey(1)]),
+				));
+				let cluster_id = ContractClusterId::from_low_u64_be(1);
+				let contract_id = Clusters::<Test>::get(cluster_id)[0];
+				assert_eq!(
+					ClusterWorkers::<Test>::get(cluster_id),
+					Some(vec![worker_pubkey(1)])
+				);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert!(matches!(
+					messages[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::InstantiateCode { deploy_workers, .. })
+						if deploy_workers == vec![WorkerIdentity {
+							pubkey: worker_pubkey(1),
+							ecdh_pubkey: ecdh_pubkey(1),
+						}]
+				));
+				// The same salt in the same cluster is rejected
+				assert_noop!(
+					PhalaFatContracts::instantiate_contract(
+						Origin::signed(1),
+						CodeIndex::WasmCode(code_hash),
+						vec![],
+						b"salt".to_vec(),
+						DeployTarget::Cluster(cluster_id),
+					),
+					Error::<Test>::DuplicatedContract
+				);
+
+				// The contract key is only accepted from the gatekeeper
+				let contract_key = sp_core::sr25519::Pair::from_seed(&[9u8; 32]);
+				let pubkey_available = |sender: MessageOrigin| {
+					PhalaFatContracts::on_contract_message_received(DecodedMessage {
+						sender,
+						destination: Topic::new(*b"^phala/registry/contract"),
+						payload: ContractRegistryEvent::PubkeyAvailable {
+							contract: contract_id,
+							pubkey: contract_key.public(),
+						},
+					})
+				};
+				assert_noop!(
+					pubkey_available(MessageOrigin::Worker(worker_pubkey(1))),
+					Error::<Test>::InvalidSender
+				);
+				let signed_by = |pair: &sp_core::sr25519::Pair| {
+					let mut message = SignedMessage {
+						message: Message::new(
+							MessageOrigin::Contract(contract_id),
+							b"test".to_vec(),
+							vec![],
+						),
+						sequence: 0,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				assert_noop!(
+					PhalaRegistry::check_message(&signed_by(&contract_key)),
+					registry::Error::<Test>::UnknownContract
+				);
+				take_events();
+				assert_ok!(pubkey_available(MessageOrigin::Gatekeeper));
+				assert_eq!(
+					registry::ContractKeys::<Test>::get(contract_id),
+					Some(contract_key.public())
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::PubkeyAvailable {
+						contract: contract_id,
+						pubkey: contract_key.public(),
+					})]
+				);
+				// Messages signed by the contract can be synced now
+				assert_ok!(PhalaRegistry::check_message(&signed_by(&contract_key)));
+				let other = sp_core::sr25519::Pair::from_seed(&[10u8; 32]);
+				assert_noop!(
+					PhalaRegistry::check_message(&signed_by(&other)),
+					registry::Error::<Test>::InvalidSignature
+				);
+			});
+		}
+
+		#[test]
+		fn test_code_deposit_and_removal() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_noop!(
+					PhalaFatContracts::set_max_code_size(Origin::root(), Some(0)),
+					Error::<Test>::InvalidMaxCodeSize
+				);
+				assert_ok!(PhalaFatContracts::set_max_code_size(
+					Origin::root(),
+					Some(10)
+				));
+				assert_noop!(
+					PhalaFatContracts::upload_code(Origin::signed(1), vec![0u8; 11]),
+					Error::<Test>::CodeTooLarge
+				);
+
+				// The deposit is reserved per byte
+				let code = vec![1u8; 8];
+				let code_hash = <Test as frame_system::Config>::Hashing::hash(&code);
+				assert_ok!(PhalaFatContracts::upload_code(
+					Origin::signed(1),
+					code.clone()
+				));
+				assert_eq!(Balances::reserved_balance(1), 8 * CENTS);
+				assert_noop!(
+					PhalaFatContracts::upload_code(Origin::signed(2), code),
+					Error::<Test>::CodeAlreadyExists
+				);
+				assert_noop!(
+					PhalaFatContracts::remove_code(Origin::signed(2), code_hash),
+					Error::<Test>::NotCodeOwner
+				);
+
+				// Referenced by a contract
+				assert_ok!(PhalaFatContracts::instantiate_contract(
+					Origin::signed(2),
+					CodeIndex::WasmCode(code_hash),
+					vec![],
+					vec![],
+					DeployTarget::NewGroup(vec![worker_pubkey(1)]),
+				));
+				assert_eq!(CodeInfos::<Test>::get(code_hash).unwrap().refcount, 1);
+				assert_noop!(
+					PhalaFatContracts::remove_code(Origin::signed(1), code_hash),
+					Error::<Test>::CodeInUse
+				);
+
+				// Unused code is removed with the deposit refunded
+				let code = vec![2u8; 4];
+				let code_hash = <Test as frame_system::Config>::Hashing::hash(&code);
+				assert_ok!(PhalaFatContracts::upload_code(Origin::signed(1), code));
+				assert_eq!(Balances::reserved_balance(1), 12 * CENTS);
+				take_events();
+				assert_ok!(PhalaFatContracts::remove_code(Origin::signed(1), code_hash));
+				assert_eq!(Balances::reserved_balance(1), 8 * CENTS);
+				assert!(!Code::<Test>::contains_key(code_hash));
+				assert!(take_events().contains(&TestEvent::PhalaFatContracts(
+					Event::CodeRemoved { hash: code_hash }
+				)));
+			});
+		}
+
+		#[test]
+		fn test_cluster_management() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(3);
+				assert_ok!(PhalaFatContracts::instantiate_contract(
+					Origin::signed(1),
+					CodeIndex::NativeCode(0),
+					vec![],
+					vec![],
+					DeployTarget::NewGroup(vec![worker_pubkey(1)]),
+				));
+				let cluster_id = ContractClusterId::from_low_u64_be(1);
+				assert_eq!(
+					ClusterInfos::<Test>::get(cluster_id),
+					Some(ClusterInfo {
+						owner: 1,
+						permission: ClusterPermission::OnlyAllowed(vec![]),
+					})
+				);
+				take_messages();
+
+				// Only the owner and the allowed accounts can use the cluster
+				assert_noop!(
+					PhalaFatContracts::instantiate_contract(
+						Origin::signed(2),
+						CodeIndex::NativeCode(0),
+						vec![],
+						vec![],
+						DeployTarget::Cluster(cluster_id),
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFatContracts::upload_code_to_cluster(
+						Origin::signed(2),
+						vec![0u8; 4],
+						cluster_id
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFatContracts::set_cluster_permission(
+						Origin::signed(2),
+						cluster_id,
+						ClusterPermission::Public
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaFatContracts::set_cluster_permission(
+					Origin::signed(1),
+					cluster_id,
+					ClusterPermission::OnlyAllowed(vec![2])
+				));
+				assert_ok!(PhalaFatContracts::upload_code_to_cluster(
+					Origin::signed(2),
+					vec![0u8; 4],
+					cluster_id
+				));
+				take_messages();
+
+				// Membership changes
+				assert_noop!(
+					PhalaFatContracts::add_cluster_worker(
+						Origin::signed(2),
+						cluster_id,
+						worker_pubkey(2)
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_noop!(
+					PhalaFatContracts::add_cluster_worker(
+						Origin::signed(1),
+						cluster_id,
+						worker_pubkey(1)
+					),
+					Error::<Test>::DuplicatedClusterWorker
+				);
+				assert_ok!(PhalaFatContracts::add_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(2)
+				));
+				assert_eq!(
+					ClusterWorkers::<Test>::get(cluster_id),
+					Some(vec![worker_pubkey(1), worker_pubkey(2)])
+				);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert!(matches!(
+					messages[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::AddClusterWorker { cluster_id: id, worker })
+						if id == cluster_id && worker.pubkey == worker_pubkey(2)
+				));
+				assert_noop!(
+					PhalaFatContracts::remove_cluster_worker(
+						Origin::signed(1),
+						cluster_id,
+						worker_pubkey(3),
+						1
+					),
+					Error::<Test>::WorkerNotInCluster
+				);
+				assert_ok!(PhalaFatContracts::remove_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(1),
+					1
+				));
+				assert!(matches!(
+					take_messages()[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::RemoveClusterWorker { worker, .. })
+						if worker == worker_pubkey(1)
+				));
+				assert_noop!(
+					PhalaFatContracts::remove_cluster_worker(
+						Origin::signed(1),
+						cluster_id,
+						worker_pubkey(2),
+						1
+					),
+					Error::<Test>::CannotRemoveLastClusterWorker
+				);
+
+				// A worker no longer registered can still be removed
+				assert_ok!(PhalaFatContracts::add_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(3)
+				));
+				registry::Workers::<Test>::remove(worker_pubkey(3));
+				assert_ok!(PhalaFatContracts::remove_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(3),
+					1
+				));
+
+				// The governance can manage the clusters without an owner
+				ClusterInfos::<Test>::remove(cluster_id);
+				assert_noop!(
+					PhalaFatContracts::add_cluster_worker(
+						Origin::signed(1),
+						cluster_id,
+						worker_pubkey(1)
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaFatContracts::add_cluster_worker(
+					Origin::root(),
+					cluster_id,
+					worker_pubkey(1)
+				));
+				assert_noop!(
+					PhalaFatContracts::set_cluster_permission(
+						Origin::root(),
+						cluster_id,
+						ClusterPermission::Public
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaFatContracts::set_cluster_owner(
+					Origin::root(),
+					cluster_id,
+					2
+				));
+				assert_eq!(
+					ClusterInfos::<Test>::get(cluster_id),
+					Some(ClusterInfo {
+						owner: 2,
+						permission: ClusterPermission::OnlyAllowed(vec![]),
+					})
+				);
+				assert_ok!(PhalaFatContracts::set_cluster_permission(
+					Origin::signed(2),
+					cluster_id,
+					ClusterPermission::Public
+				));
+				assert_noop!(
+					PhalaFatContracts::set_cluster_owner(
+						Origin::root(),
+						ContractClusterId::from_low_u64_be(9),
+						2
+					),
+					Error::<Test>::ContractClusterNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn test_instantiation_status() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(4);
+				let code = vec![1u8; 8];
+				let code_hash = <Test as frame_system::Config>::Hashing::hash(&code);
+				assert_ok!(PhalaFatContracts::upload_code(Origin::signed(1), code));
+				let deploy = |salt: &[u8], deploy_to: DeployTarget| {
+					PhalaFatContracts::instantiate_contract(
+						Origin::signed(1),
+						CodeIndex::WasmCode(code_hash),
+						vec![],
+						salt.to_vec(),
+						deploy_to,
+					)
+				};
+				assert_ok!(deploy(
+					b"a",
+					DeployTarget::NewGroup(vec![
+						worker_pubkey(1),
+						worker_pubkey(2),
+						worker_pubkey(3)
+					])
+				));
+				let cluster_id = ContractClusterId::from_low_u64_be(1);
+				assert_ok!(deploy(b"b", DeployTarget::Cluster(cluster_id)));
+				let contracts = Clusters::<Test>::get(cluster_id);
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[0]),
+					Some(ContractStatus::Instantiating)
+				);
+				// The reported deployer is ignored
+				let report = |worker: u8, id: ContractId, succeeded: bool| {
+					let payload = if succeeded {
+						WorkerContractReport::ContractInstantiated {
+							id,
+							cluster_id,
+							deployer: H256::zero(),
+							pubkey: ecdh_pubkey(1),
+						}
+					} else {
+						WorkerContractReport::ContractInstantiationFailed {
+							id,
+							cluster_id,
+							deployer: H256::zero(),
+						}
+					};
+					PhalaFatContracts::on_worker_contract_message_received(DecodedMessage {
+						sender: MessageOrigin::Worker(worker_pubkey(worker)),
+						destination: Topic::new(*b"phala/contract/worker/report"),
+						payload,
+					})
+				};
+
+				// A majority of the cluster workers is required
+				assert_noop!(
+					report(4, contracts[0], true),
+					Error::<Test>::WorkerNotInCluster
+				);
+				assert_ok!(report(1, contracts[0], true));
+				assert_ok!(report(2, contracts[0], false));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[0]),
+					Some(ContractStatus::Instantiating)
+				);
+				take_events();
+				assert_ok!(report(2, contracts[0], true));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[0]),
+					Some(ContractStatus::Instantiated)
+				);
+				assert!(!InstantiationReports::<Test>::contains_key(contracts[0]));
+				assert!(take_events().contains(&TestEvent::PhalaFatContracts(
+					Event::Instantiated {
+						contract: contracts[0],
+						cluster: cluster_id,
+						deployer: H256::from_low_u64_be(1),
+					}
+				)));
+
+				// Failed deployments are cleaned up and can be retried
+				assert_eq!(CodeInfos::<Test>::get(code_hash).unwrap().refcount, 2);
+				assert_ok!(report(1, contracts[1], false));
+				assert_ok!(report(3, contracts[1], false));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[1]),
+					Some(ContractStatus::Failed)
+				);
+				assert!(!Contracts::<Test>::contains_key(contracts[1]));
+				assert_eq!(Clusters::<Test>::get(cluster_id), vec![contracts[0]]);
+				assert_eq!(CodeInfos::<Test>::get(code_hash).unwrap().refcount, 1);
+				// Late reports are ignored
+				assert_ok!(report(2, contracts[1], true));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[1]),
+					Some(ContractStatus::Failed)
+				);
+				assert_ok!(deploy(b"b", DeployTarget::Cluster(cluster_id)));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[1]),
+					Some(ContractStatus::Instantiating)
+				);
+
+				// The quorum is checked again when the cluster workers are removed
+				assert_ok!(report(1, contracts[1], true));
+				assert_noop!(
+					PhalaFatContracts::remove_cluster_worker(
+						Origin::signed(1),
+						cluster_id,
+						worker_pubkey(3),
+						1
+					),
+					Error::<Test>::ContractCountUnderestimated
+				);
+				assert_ok!(PhalaFatContracts::remove_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(3),
+					2
+				));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[1]),
+					Some(ContractStatus::Instantiating)
+				);
+				assert_ok!(PhalaFatContracts::remove_cluster_worker(
+					Origin::signed(1),
+					cluster_id,
+					worker_pubkey(2),
+					2
+				));
+				assert_eq!(
+					ContractStatuses::<Test>::get(contracts[1]),
+					Some(ContractStatus::Instantiated)
+				);
+
+				// A stuck instantiation can be cancelled by the deployer
+				assert_ok!(deploy(b"c", DeployTarget::Cluster(cluster_id)));
+				let stuck = Clusters::<Test>::get(cluster_id)[2];
+				assert_noop!(
+					PhalaFatContracts::cancel_instantiation(Origin::signed(2), stuck),
+					Error::<Test>::NotContractDeployer
+				);
+				assert_noop!(
+					PhalaFatContracts::cancel_instantiation(Origin::signed(1), contracts[0]),
+					Error::<Test>::ContractNotInstantiating
+				);
+				take_messages();
+				assert_ok!(PhalaFatContracts::cancel_instantiation(
+					Origin::signed(1),
+					stuck
+				));
+				assert_eq!(
+					ContractStatuses::<Test>::get(stuck),
+					Some(ContractStatus::Failed)
+				);
+				assert!(!Contracts::<Test>::contains_key(stuck));
+				assert!(matches!(
+					take_messages()[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::TerminateContract { contract_id, .. })
+						if contract_id == stuck
+				));
+				// Reports of the cancelled instantiation are ignored
+				assert_ok!(report(1, stuck, true));
+				assert_eq!(
+					ContractStatuses::<Test>::get(stuck),
+					Some(ContractStatus::Failed)
+				);
+			});
+		}
+
+		#[test]
+		fn test_contract_upgrade_and_termination() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				let code_v1 = vec![1u8; 8];
+				let code_v2 = vec![2u8; 8];
+				let hash_v1 = <Test as frame_system::Config>::Hashing::hash(&code_v1);
+				let hash_v2 = <Test as frame_system::Config>::Hashing::hash(&code_v2);
+				assert_ok!(PhalaFatContracts::upload_code(Origin::signed(1), code_v1));
+				assert_ok!(PhalaFatContracts::upload_code(Origin::signed(1), code_v2));
+				assert_ok!(PhalaFatContracts::instantiate_contract(
+					Origin::signed(1),
+					CodeIndex::WasmCode(hash_v1),
+					vec![],
+					vec![],
+					DeployTarget::NewGroup(vec![worker_pubkey(1)]),
+				));
+				let cluster_id = ContractClusterId::from_low_u64_be(1);
+				let contract_id = Clusters::<Test>::get(cluster_id)[0];
+				assert_eq!(
+					ContractCodeHistory::<Test>::get(contract_id),
+					vec![(CodeIndex::WasmCode(hash_v1), 1)]
+				);
+				take_messages();
+
+				// Only instantiated contracts can be upgraded
+				assert_noop!(
+					PhalaFatContracts::upgrade_contract(
+						Origin::signed(1),
+						contract_id,
+						CodeIndex::WasmCode(hash_v2)
+					),
+					Error::<Test>::ContractNotInstantiated
+				);
+				ContractStatuses::<Test>::insert(contract_id, ContractStatus::Instantiated);
+				assert_noop!(
+					PhalaFatContracts::upgrade_contract(
+						Origin::signed(2),
+						contract_id,
+						CodeIndex::WasmCode(hash_v2)
+					),
+					Error::<Test>::NotContractDeployer
+				);
+				// The code history is bounded
+				let history = ContractCodeHistory::<Test>::get(contract_id);
+				ContractCodeHistory::<Test>::insert(
+					contract_id,
+					vec![(CodeIndex::WasmCode(hash_v1), 1); MAX_CONTRACT_CODE_HISTORY],
+				);
+				assert_noop!(
+					PhalaFatContracts::upgrade_contract(
+						Origin::signed(1),
+						contract_id,
+						CodeIndex::WasmCode(hash_v2)
+					),
+					Error::<Test>::ContractCodeHistoryFull
+				);
+				ContractCodeHistory::<Test>::insert(contract_id, history);
+				assert_ok!(PhalaFatContracts::upgrade_contract(
+					Origin::signed(1),
+					contract_id,
+					CodeIndex::WasmCode(hash_v2)
+				));
+				assert_eq!(
+					ContractCodeHistory::<Test>::get(contract_id),
+					vec![
+						(CodeIndex::WasmCode(hash_v1), 1),
+						(CodeIndex::WasmCode(hash_v2), 1)
+					]
+				);
+				assert_eq!(CodeInfos::<Test>::get(hash_v1).unwrap().refcount, 0);
+				assert_eq!(CodeInfos::<Test>::get(hash_v2).unwrap().refcount, 1);
+				assert!(matches!(
+					take_messages()[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::UpgradeContract { contract_id: id, code_index, .. })
+						if id == contract_id && code_index == CodeIndex::WasmCode(hash_v2)
+				));
+
+				// Terminated by the governance
+				let contract_key = sp_core::sr25519::Pair::from_seed(&[9u8; 32]);
+				registry::ContractKeys::<Test>::insert(contract_id, contract_key.public());
+				assert_ok!(PhalaFatContracts::terminate_contract(
+					Origin::root(),
+					contract_id
+				));
+				assert!(!Contracts::<Test>::contains_key(contract_id));
+				assert!(!registry::ContractKeys::<Test>::contains_key(contract_id));
+				assert_eq!(
+					ContractCodeHistory::<Test>::get(contract_id),
+					vec![
+						(CodeIndex::WasmCode(hash_v1), 1),
+						(CodeIndex::WasmCode(hash_v2), 1)
+					]
+				);
+				assert_eq!(
+					ContractStatuses::<Test>::get(contract_id),
+					Some(ContractStatus::Terminated)
+				);
+				assert!(Clusters::<Test>::get(cluster_id).is_empty());
+				assert_eq!(CodeInfos::<Test>::get(hash_v2).unwrap().refcount, 0);
+				assert!(matches!(
+					take_messages()[0].decode_payload::<ContractEvent<H256, u64>>(),
+					Some(ContractEvent::TerminateContract { contract_id: id, .. })
+						if id == contract_id
+				));
+				assert_noop!(
+					PhalaFatContracts::terminate_contract(Origin::root(), contract_id),
+					Error::<Test>::ContractNotFound
+				);
+				// The terminated contract id can't be reused
+				assert_noop!(
+					PhalaFatContracts::instantiate_contract(
+						Origin::signed(1),
+						CodeIndex::WasmCode(hash_v1),
+						vec![],
+						vec![],
+						DeployTarget::Cluster(cluster_id),
+					),
+					Error::<Test>::ContractTerminated
+				);
+			});
+		}
+
+		#[test]
+		fn test_genesis_config() {
+			let mut t = frame_system::GenesisConfig::default()
+				.build_storage::<Test>()
+				.unwrap();
+			GenesisBuild::<Test>::assimilate_storage(
+				&GenesisConfig {
+					max_code_size: Some(16),
+				},
+				&mut t,
+			)
+			.unwrap();
+			sp_io::TestExternalities::new(t).execute_with(|| {
+				assert_eq!(MaxCodeSize::<Test>::get(), Some(16));
+				assert_noop!(
+					PhalaFatContracts::upload_code(Origin::signed(1), vec![0u8; 17]),
+					Error::<Test>::CodeTooLarge
+				);
+			});
+		}
+	}
 }
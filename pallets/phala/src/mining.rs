@@ -22,15 +22,15 @@ pub mod pallet {
 			MiningInfoUpdateEvent, MiningReportEvent, SettleInfo, SystemEvent,
 			TokenomicParameters as TokenomicParams, WorkerEvent,
 		},
-		WorkerPublicKey,
+		SubsidyPeriodStats, TeeType, WorkerPublicKey,
 	};
 	use scale_info::TypeInfo;
 	use sp_core::U256;
 	use sp_runtime::{
-		traits::{AccountIdConversion, One, Zero},
-		SaturatedConversion,
+		traits::{AccountIdConversion, One, Saturating, Zero},
+		Permill, SaturatedConversion,
 	};
-	use sp_std::cmp;
+	use sp_std::{cmp, prelude::*};
 
 	use crate::balance_convert::FixedPointConvert;
 	use fixed::types::U64F64 as FixedPoint;
@@ -38,6 +38,15 @@ pub mod pallet {
 	use fixed_sqrt::FixedSqrt;
 
 	const DEFAULT_EXPECTED_HEARTBEAT_COUNT: u32 = 20;
+	/// Warn if the subsidy pool can't cover the budget of the next 7 days (12s block)
+	const DEFAULT_SUBSIDY_WARNING_BLOCKS: u32 = 7 * 24 * 300;
+	/// The bounds of the adaptive heartbeat factor
+	const MIN_HEARTBEAT_FACTOR: FixedPoint = fp!(0.1);
+	const MAX_HEARTBEAT_FACTOR: FixedPoint = fp!(1);
+	/// The default confidence scores of level 1 to 5
+	const DEFAULT_CONFIDENCE_SCORES: [FixedPoint; 5] = [fp!(1), fp!(1), fp!(1), fp!(0.8), fp!(0.7)];
+	/// The number of `p_instant` samples kept in the performance history of a miner
+	const PERFORMANCE_WINDOW_SIZE: usize = 10;
 	const MINING_PALLETID: PalletId = PalletId(*b"phala/pp");
 
 	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
@@ -53,21 +62,26 @@ pub mod pallet {
 		fn can_unbind(&self) -> bool {
 			matches!(self, MinerState::Ready | MinerState::MiningCoolingDown)
 		}
-		fn can_settle(&self) -> bool {
-			// TODO(hangyin):
-			//
-			// We don't allow a settlement in MiningCoolingDown or Ready. After a miner is stopped,
-			// it's released immediately and the slash is pre-settled (to make sure the force
-			// withdrawal can be processed correctly).
-			//
-			// We have to either figure out how to allow settlement in CoolingDown state, or
-			// complete disable it as we do now. Note that when CoolingDown settle is not allowed,
-			// we still have to make sure the slashed V is periodically updated on the blockchain.
+		fn is_online(&self) -> bool {
 			matches!(
 				self,
 				MinerState::MiningIdle | MinerState::MiningActive | MinerState::MiningUnresponsive
 			)
 		}
+		fn can_settle(&self) -> bool {
+			// After a miner is stopped, its stake is released immediately and the slash is
+			// pre-settled (to make sure the force withdrawal can be processed correctly). However
+			// the gatekeeper may still deliver a late settlement for a miner in CoolingDown. In
+			// that case we accept the new V, and notify the subscribers about the corrected final
+			// slash via `OnStopped::on_slash_updated()`. A settlement in Ready is not allowed.
+			matches!(
+				self,
+				MinerState::MiningIdle
+					| MinerState::MiningActive
+					| MinerState::MiningUnresponsive
+					| MinerState::MiningCoolingDown
+			)
+		}
 		fn is_mining(&self) -> bool {
 			matches!(
 				self,
@@ -83,17 +97,44 @@ pub mod pallet {
 		iterations: u64,
 		mining_start_time: u64,
 		challenge_time_last: u64,
+		/// The mining session the benchmark belongs to
+		///
+		/// `None` if the miner has never started mining, or it was started before the session
+		/// was tracked.
+		session_id: Option<u32>,
+	}
+
+	/// The reason why a heartbeat is rejected
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub enum HeartbeatRejectReason {
+		/// The heartbeat belongs to an earlier mining session
+		SessionMismatch,
+		/// The heartbeat arrived late or was replayed
+		Stale,
 	}
 
 	impl Benchmark {
 		/// Records the latest benchmark status snapshot and updates `p_instant`
 		///
 		/// Note: `now` and `challenge_time` are in seconds.
-		fn update(&mut self, now: u64, iterations: u64, challenge_time: u64) -> Result<(), ()> {
+		fn update(
+			&mut self,
+			now: u64,
+			session_id: u32,
+			iterations: u64,
+			challenge_time: u64,
+		) -> Result<(), HeartbeatRejectReason> {
+			// Ignore the heartbeats from an earlier mining session. The iterations are counted
+			// per session, so they are not comparable with the current counter.
+			if let Some(current_session) = self.session_id {
+				if session_id != current_session {
+					return Err(HeartbeatRejectReason::SessionMismatch);
+				}
+			}
 			// `now` must be larger than `challenge_time_last` because it's impossible to report
 			// the heartbeat at the same block with the challenge.
 			if now <= self.challenge_time_last {
-				return Err(());
+				return Err(HeartbeatRejectReason::Stale);
 			}
 			// Lower iteration indicates the worker has been restarted. This is acceptable, but we
 			// have to reset the on-chain counter as well (causing a temporary zero p-instant).
@@ -113,6 +154,23 @@ pub mod pallet {
 		}
 	}
 
+	/// The reason why a miner is stopped
+	#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+	pub enum StopReason {
+		/// Stopped by the owner of the miner
+		Voluntary,
+		/// The worker was force unbound from the miner
+		Unbound,
+		/// Stopped by the stake pool to enforce expired withdrawal requests
+		ForceWithdrawal,
+		/// Stopped by the governance (e.g. an emergency pRuntime rollback)
+		Governance,
+		/// The worker didn't upgrade its pRuntime to the minimum version before the deadline
+		OutdatedPRuntime,
+		/// The worker didn't refresh its stale attestation within the grace period
+		StaleAttestation,
+	}
+
 	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
 	pub struct MinerInfo {
 		pub state: MinerState,
@@ -124,11 +182,19 @@ pub mod pallet {
 		benchmark: Benchmark,
 		cool_down_start: u64,
 		stats: MinerStats,
+		/// The reason of the last stop, reset when the miner starts mining again
+		pub stop_reason: Option<StopReason>,
+		/// The max slash ratio of the current cool down, taken from `SlashCaps` at the stop
+		max_slash: Option<Permill>,
+		/// The confidence level counted in `OnlineMinersByLevel`, taken at the start of mining
+		confidence_level: u8,
 	}
 
 	impl MinerInfo {
 		/// Calculates the final final returned and slashed stake
-		fn calc_final_stake<Balance>(&self, orig_stake: Balance) -> (Balance, Balance)
+		///
+		/// The slash is capped by `max_slash` if the miner was stopped with a capped reason.
+		pub fn calc_final_stake<Balance>(&self, orig_stake: Balance) -> (Balance, Balance)
 		where
 			Balance: sp_runtime::traits::AtLeast32BitUnsigned + Copy + FixedPointConvert,
 		{
@@ -141,9 +207,12 @@ pub mod pallet {
 			// 	let returned = return_rate * orig_stake.to_fixed() * tokenomic.kappa();
 			let returned = return_rate * orig_stake.to_fixed();
 			// Convert to Balance
-			let returned = FixedPointConvert::from_fixed(&returned);
-			let slashed = orig_stake - returned;
-			(returned, slashed)
+			let returned: Balance = FixedPointConvert::from_fixed(&returned);
+			let mut slashed = orig_stake - returned;
+			if let Some(max_slash) = self.max_slash {
+				slashed = slashed.min(max_slash * orig_stake);
+			}
+			(orig_stake - slashed, slashed)
 		}
 	}
 
@@ -161,8 +230,52 @@ pub mod pallet {
 	pub trait OnStopped<Balance> {
 		/// Called with a miner is stopped and can already calculate the final slash and stake.
 		///
-		/// It guarantees the number will be the same as the return value of `reclaim()`
-		fn on_stopped(worker: &WorkerPublicKey, orig_stake: Balance, slashed: Balance) {}
+		/// It guarantees the number will be the same as the return value of `reclaim()`, unless
+		/// it's corrected later by `on_slash_updated()`.
+		fn on_stopped(
+			worker: &WorkerPublicKey,
+			orig_stake: Balance,
+			slashed: Balance,
+			reason: StopReason,
+		) {
+		}
+
+		/// Called when a late settlement changes the final slash of a cooling down miner.
+		///
+		/// `prev_slashed` is the slash reported earlier (by `on_stopped()` or a previous call to
+		/// this function), and `slashed` is the corrected one, which will be returned by
+		/// `reclaim()`.
+		fn on_slash_updated(
+			worker: &WorkerPublicKey,
+			orig_stake: Balance,
+			prev_slashed: Balance,
+			slashed: Balance,
+		) {
+		}
+	}
+
+	impl<T: Config> registry::IsWorkerBound for Pallet<T>
+	where
+		BalanceOf<T>: FixedPointConvert,
+	{
+		fn is_worker_bound(worker: &WorkerPublicKey) -> bool {
+			WorkerBindings::<T>::contains_key(worker)
+		}
+	}
+
+	pub trait OnReclaimed<AccountId, Balance> {
+		/// Returns if the miner can be reclaimed automatically when its cool down ends.
+		///
+		/// The miners not accepted are left to be reclaimed manually.
+		fn can_auto_reclaim(miner: &AccountId) -> bool {
+			false
+		}
+
+		/// Called when a miner is reclaimed automatically, with the same return value of
+		/// `reclaim()`. Returns the weight consumed.
+		fn on_reclaimed(miner: &AccountId, orig_stake: Balance, slashed: Balance) -> Weight {
+			0
+		}
 	}
 
 	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Default, RuntimeDebug)]
@@ -188,6 +301,7 @@ pub mod pallet {
 		type OnReward: OnReward;
 		type OnUnbound: OnUnbound;
 		type OnStopped: OnStopped<BalanceOf<Self>>;
+		type OnReclaimed: OnReclaimed<Self::AccountId, BalanceOf<Self>>;
 		type OnTreasurySettled: OnUnbalanced<NegativeImbalanceOf<Self>>;
 		// Let the StakePool to take over the slash events.
 
@@ -195,7 +309,7 @@ pub mod pallet {
 		type UpdateTokenomicOrigin: EnsureOrigin<Self::Origin>;
 	}
 
-	const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(8);
 
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
@@ -218,10 +332,36 @@ pub mod pallet {
 	#[pallet::getter(fn online_miners)]
 	pub type OnlineMiners<T> = StorageValue<_, u32, ValueQuery>;
 
+	/// The number of online miners by the confidence level of their workers
+	#[pallet::storage]
+	pub type OnlineMinersByLevel<T> = StorageMap<_, Twox64Concat, u8, u32, ValueQuery>;
+
 	/// The expected heartbeat count (default: 20)
 	#[pallet::storage]
 	pub type ExpectedHeartbeatCount<T> = StorageValue<_, u32>;
 
+	/// The expected heartbeat count contributed by the online miners of a confidence level
+	///
+	/// Levels without an override contribute their share of `ExpectedHeartbeatCount`.
+	#[pallet::storage]
+	pub type ExpectedHeartbeatCountByLevel<T> = StorageMap<_, Twox64Concat, u8, u32>;
+
+	/// Whether to adapt the heartbeat challenge target to the observed heartbeats
+	#[pallet::storage]
+	pub type AdaptiveHeartbeat<T> = StorageValue<_, bool, ValueQuery>;
+
+	/// The number of heartbeats received in the current block
+	#[pallet::storage]
+	pub type HeartbeatsInBlock<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// The moving average of the heartbeats received per block, in U64F64 bits
+	#[pallet::storage]
+	pub type ObservedHeartbeatRate<T> = StorageValue<_, u128>;
+
+	/// The factor applied to the expected heartbeat count in adaptive mode, in U64F64 bits
+	#[pallet::storage]
+	pub type HeartbeatTargetFactor<T> = StorageValue<_, u128>;
+
 	/// The miner state.
 	///
 	/// The miner state is created when a miner is bounded with a worker, but it will be kept even
@@ -238,11 +378,96 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type WorkerBindings<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, T::AccountId>;
 
+	/// The number of rejected heartbeats of a miner
+	///
+	/// Increased when a heartbeat is stale, or belongs to an earlier mining session.
+	#[pallet::storage]
+	#[pallet::getter(fn rejected_heartbeats)]
+	pub type RejectedHeartbeats<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, u32, ValueQuery>;
+
+	/// The minimum pRuntime version a mining worker was last warned about
+	#[pallet::storage]
+	pub type PRuntimeUpgradeWarnings<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, u32>;
+
+	/// The recent `p_instant` samples of a miner, oldest first
+	///
+	/// At most `PERFORMANCE_WINDOW_SIZE` samples are kept. Reset when the miner starts mining.
+	#[pallet::storage]
+	#[pallet::getter(fn performance_history)]
+	pub type PerformanceHistory<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, Vec<u32>, ValueQuery>;
+
+	/// The fraction of `p_init` below which the windowed average performance is alerted
+	///
+	/// No alert is emitted if it's not set.
+	#[pallet::storage]
+	pub type PerformanceAlertThreshold<T> = StorageValue<_, Permill>;
+
 	/// The cool down period (in sec)
 	#[pallet::storage]
 	#[pallet::getter(fn cool_down_period)]
 	pub type CoolDownPeriod<T> = StorageValue<_, u64, ValueQuery>;
 
+	/// The confidence scores of level 1 to 5, in U64F64 bits
+	///
+	/// Falls back to `DEFAULT_CONFIDENCE_SCORES` if not set.
+	#[pallet::storage]
+	#[pallet::getter(fn confidence_scores)]
+	pub type ConfidenceScores<T> = StorageValue<_, Vec<u128>>;
+
+	/// The confidence scores of level 1 to 5 of a TEE type (in U64F64 bits)
+	///
+	/// Falls back to `ConfidenceScores` if not set.
+	#[pallet::storage]
+	#[pallet::getter(fn tee_confidence_scores)]
+	pub type TeeConfidenceScores<T> = StorageMap<_, Twox64Concat, TeeType, Vec<u128>>;
+
+	/// The max slash ratio applied to the miners stopped for the given reason
+	///
+	/// Set by the governance to waive (zero) or cap the slash of the network-initiated stops. The
+	/// cap is taken at the stop, so later changes don't affect the miners already cooling down.
+	#[pallet::storage]
+	#[pallet::getter(fn slash_caps)]
+	pub type SlashCaps<T> = StorageMap<_, Twox64Concat, StopReason, Permill>;
+
+	/// The index of the current halving period, starting from 0
+	#[pallet::storage]
+	#[pallet::getter(fn halving_period)]
+	pub type HalvingPeriod<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// The subsidy accounting of each halving period
+	#[pallet::storage]
+	#[pallet::getter(fn subsidy_stats)]
+	pub type SubsidyStats<T: Config> =
+		StorageMap<_, Twox64Concat, u32, SubsidyPeriodStats<BalanceOf<T>>, ValueQuery>;
+
+	/// The number of blocks of the budget the subsidy pool is expected to cover
+	/// (default: `DEFAULT_SUBSIDY_WARNING_BLOCKS`)
+	#[pallet::storage]
+	pub type SubsidyWarningBlocks<T> = StorageValue<_, u32>;
+
+	/// Whether the subsidy pool is below the projected budget
+	#[pallet::storage]
+	pub type SubsidyPoolLow<T> = StorageValue<_, bool, ValueQuery>;
+
+	/// The queue of the miners in cool down as `(miner, cool_down_start)`, indexed by the position
+	///
+	/// The miners are pushed when they are stopped, so the queue is ordered by the end of the cool
+	/// down. Entries can be outdated if the miner was reclaimed manually.
+	#[pallet::storage]
+	pub type CoolDownQueue<T: Config> = StorageMap<_, Twox64Concat, u64, (T::AccountId, u64)>;
+
+	/// The range `[head, tail)` of `CoolDownQueue`
+	#[pallet::storage]
+	pub type CoolDownQueueRange<T> = StorageValue<_, (u64, u64), ValueQuery>;
+
+	/// The raw `Miners` key of the last miner checked by the `on_idle` sweep
+	///
+	/// The next sweep continues after it. `None` to start over from the first miner.
+	#[pallet::storage]
+	pub type MinerSweepCursor<T> = StorageValue<_, Vec<u8>>;
+
 	/// The next id to assign to a mining session
 	#[pallet::storage]
 	pub type NextSessionId<T> = StorageValue<_, u32, ValueQuery>;
@@ -291,6 +516,30 @@ pub mod pallet {
 		InternalErrorWrongHalvingConfigured,
 		/// Tokenomic parameter changed.
 		TokenomicParametersChanged,
+		/// A heartbeat is rejected and ignored. \[miner, worker, reason\]
+		MinerHeartbeatRejected(T::AccountId, WorkerPublicKey, HeartbeatRejectReason),
+		/// The windowed average performance of a miner dropped below the alert threshold.
+		/// \[miner, average_p_instant, p_init\]
+		MinerPerformanceDegraded(T::AccountId, u32, u32),
+		/// Performance alert threshold changed. \[threshold\]
+		PerformanceAlertThresholdChanged(Option<Permill>),
+		/// Expected heartbeat count changed. \[confidence_level, count\]
+		ExpectedHeartbeatCountChanged(Option<u8>, Option<u32>),
+		/// Adaptive heartbeat mode switched. \[enabled\]
+		AdaptiveHeartbeatChanged(bool),
+		/// The subsidy pool balance fell below the projected budget.
+		/// \[balance, projected_budget\]
+		SubsidyPoolInsufficient(BalanceOf<T>, BalanceOf<T>),
+		/// Confidence scores changed.
+		ConfidenceScoresChanged,
+		/// Confidence scores of a TEE type changed. \[tee_type\]
+		TeeConfidenceScoresChanged(TeeType),
+		/// The slash cap of a stop reason changed. \[reason, max_slash\]
+		SlashCapChanged(StopReason, Option<Permill>),
+		/// A mining worker is warned to upgrade its pRuntime. \[miner, worker, min_version\]
+		MinerPRuntimeOutdated(T::AccountId, WorkerPublicKey, u32),
+		/// The number of blocks the subsidy pool is expected to cover changed. \[blocks\]
+		SubsidyWarningBlocksChanged(Option<u32>),
 	}
 
 	#[pallet::error]
@@ -314,6 +563,16 @@ pub mod pallet {
 		/// Indicating the initial benchmark score is too low to start mining.
 		BenchmarkTooLow,
 		InternalErrorCannotStartWithExistingStake,
+		/// The confidence level is out of 1 to 5.
+		InvalidConfidenceLevel,
+		/// The confidence scores must be 5 values within `(0, 1]`.
+		InvalidConfidenceScores,
+		/// The slash of voluntary stops can't be capped.
+		CannotCapVoluntaryStop,
+		/// The pRuntime of the worker is below the minimum version after the deadline.
+		PRuntimeVersionTooLow,
+		/// The attestation of the worker is older than the max attestation age.
+		AttestationStale,
 	}
 
 	type BalanceOf<T> =
@@ -352,6 +611,30 @@ pub mod pallet {
 			Self::unbind_miner(&miner, true)
 		}
 
+		/// Requests the worker to redo the benchmark, e.g. after a hardware upgrade.
+		///
+		/// Must be called by the operator of the worker. If the worker is bound, the miner must be
+		/// in `Ready` state, so that the new `initial_score` only takes effect at the next
+		/// `start_mining`. Rate-limited by [`registry::MIN_REBENCHMARK_INTERVAL`].
+		#[pallet::weight(0)]
+		pub fn request_rebenchmark(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let worker_info =
+				registry::Workers::<T>::get(&worker).ok_or(Error::<T>::WorkerNotRegistered)?;
+			ensure!(worker_info.operator == Some(who), Error::<T>::BadSender);
+			if let Some(miner) = WorkerBindings::<T>::get(&worker) {
+				let miner_info = Miners::<T>::get(&miner).ok_or(Error::<T>::MinerNotFound)?;
+				ensure!(
+					miner_info.state == MinerState::Ready,
+					Error::<T>::MinerNotReady
+				);
+			}
+			registry::Pallet::<T>::request_benchmark(&worker)
+		}
+
 		/// Triggers a force heartbeat request to all workers by sending a MAX pow target
 		///
 		/// Only for integration test.
@@ -381,11 +664,31 @@ pub mod pallet {
 
 		/// Stop mining
 		///
-		/// Only for integration test.
+		/// The miner is stopped with `StopReason::Governance`, so its slash is subject to the
+		/// cap of the reason.
 		#[pallet::weight(1)]
 		pub fn force_stop_mining(origin: OriginFor<T>, miner: T::AccountId) -> DispatchResult {
 			ensure_root(origin)?;
-			Self::stop_mining(miner)?;
+			Self::stop_mining(miner, StopReason::Governance)?;
+			Ok(())
+		}
+
+		/// Sets the max slash ratio of the miners stopped for `reason`
+		///
+		/// Zero waives the slash completely. `None` removes the cap.
+		#[pallet::weight(0)]
+		pub fn set_slash_cap(
+			origin: OriginFor<T>,
+			reason: StopReason,
+			max_slash: Option<Permill>,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			ensure!(
+				reason != StopReason::Voluntary,
+				Error::<T>::CannotCapVoluntaryStop
+			);
+			SlashCaps::<T>::set(reason, max_slash);
+			Self::deposit_event(Event::<T>::SlashCapChanged(reason, max_slash));
 			Ok(())
 		}
 
@@ -399,6 +702,116 @@ pub mod pallet {
 			ScheduledTokenomicUpdate::<T>::put(new_params);
 			Ok(())
 		}
+
+		/// Sets the fraction of `p_init` to alert a degraded miner performance
+		///
+		/// Setting it to `None` disables the alert.
+		#[pallet::weight(0)]
+		pub fn set_performance_alert_threshold(
+			origin: OriginFor<T>,
+			threshold: Option<Permill>,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			PerformanceAlertThreshold::<T>::set(threshold);
+			Self::deposit_event(Event::<T>::PerformanceAlertThresholdChanged(threshold));
+			Ok(())
+		}
+
+		/// Sets the expected heartbeat count per block
+		///
+		/// Sets the global count if `confidence_level` is `None`, otherwise overrides the count
+		/// contributed by the miners at that level. Setting `count` to `None` restores the
+		/// default. Note that the challenge target is shared by all the workers, so the per-level
+		/// counts only change the total number of expected heartbeats.
+		#[pallet::weight(0)]
+		pub fn set_expected_heartbeat_count(
+			origin: OriginFor<T>,
+			confidence_level: Option<u8>,
+			count: Option<u32>,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			match confidence_level {
+				None => ExpectedHeartbeatCount::<T>::set(count),
+				Some(level) => {
+					ensure!((1..=5).contains(&level), Error::<T>::InvalidConfidenceLevel);
+					ExpectedHeartbeatCountByLevel::<T>::set(level, count);
+				}
+			}
+			Self::deposit_event(Event::<T>::ExpectedHeartbeatCountChanged(
+				confidence_level,
+				count,
+			));
+			Ok(())
+		}
+
+		/// Enables or disables the adaptive heartbeat challenge target
+		///
+		/// In adaptive mode, the expected heartbeat count is scaled down when more heartbeats
+		/// than expected are observed per block, and recovers when the surge is over.
+		#[pallet::weight(0)]
+		pub fn set_adaptive_heartbeat(origin: OriginFor<T>, enabled: bool) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			AdaptiveHeartbeat::<T>::put(enabled);
+			HeartbeatsInBlock::<T>::kill();
+			ObservedHeartbeatRate::<T>::kill();
+			HeartbeatTargetFactor::<T>::kill();
+			Self::deposit_event(Event::<T>::AdaptiveHeartbeatChanged(enabled));
+			Ok(())
+		}
+
+		/// Sets the confidence scores of level 1 to 5 (in U64F64 bits)
+		///
+		/// The scores are forwarded to the gatekeeper. They only apply to the miners starting
+		/// afterwards, as the initial V (`ve`) is computed in `start_mining`.
+		#[pallet::weight(0)]
+		pub fn set_confidence_scores(origin: OriginFor<T>, scores: Vec<u128>) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			ensure!(
+				Self::is_valid_confidence_scores(&scores),
+				Error::<T>::InvalidConfidenceScores
+			);
+			ConfidenceScores::<T>::put(scores.clone());
+			Self::push_message(GatekeeperEvent::ConfidenceScoresChanged(scores));
+			Self::deposit_event(Event::<T>::ConfidenceScoresChanged);
+			Ok(())
+		}
+
+		/// Sets the confidence scores of level 1 to 5 (in U64F64 bits) for the workers of a TEE
+		/// type, or restores the default ones with `None`
+		#[pallet::weight(0)]
+		pub fn set_tee_confidence_scores(
+			origin: OriginFor<T>,
+			tee_type: TeeType,
+			scores: Option<Vec<u128>>,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			if let Some(scores) = &scores {
+				ensure!(
+					Self::is_valid_confidence_scores(scores),
+					Error::<T>::InvalidConfidenceScores
+				);
+			}
+			TeeConfidenceScores::<T>::set(tee_type, scores.clone());
+			Self::push_message(GatekeeperEvent::TeeConfidenceScoresChanged(
+				tee_type, scores,
+			));
+			Self::deposit_event(Event::<T>::TeeConfidenceScoresChanged(tee_type));
+			Ok(())
+		}
+
+		/// Sets the number of blocks of the budget the subsidy pool is expected to cover
+		///
+		/// Setting it to `None` restores the default.
+		#[pallet::weight(0)]
+		pub fn set_subsidy_warning_blocks(
+			origin: OriginFor<T>,
+			blocks: Option<u32>,
+		) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			SubsidyWarningBlocks::<T>::set(blocks);
+			Self::deposit_event(Event::<T>::SubsidyWarningBlocksChanged(blocks));
+			Ok(())
+		}
 	}
 
 	#[pallet::hooks]
@@ -406,6 +819,11 @@ pub mod pallet {
 	where
 		BalanceOf<T>: FixedPointConvert,
 	{
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			// Reserved for `check_subsidy_pool()` in `on_finalize()`
+			Self::check_subsidy_pool_weight()
+		}
+
 		fn on_finalize(n: T::BlockNumber) {
 			Self::heartbeat_challenge();
 			// Apply tokenomic update if possible
@@ -423,6 +841,7 @@ pub mod pallet {
 					}
 				}
 			}
+			Self::check_subsidy_pool();
 		}
 
 		fn on_runtime_upgrade() -> Weight {
@@ -434,18 +853,54 @@ pub mod pallet {
 				w += migrations::initialize::<T>();
 				STORAGE_VERSION.put::<super::Pallet<T>>();
 				w += T::DbWeight::get().writes(1);
-			} else if old == 2 {
+			}
+			if old == 2 {
 				// Triggers GK RepairV event (again) to rescue the slashed miners due to
 				// incorrectly applied tokenomic.
 				w += migrations::repair_v::<T>();
 				// Khala-only halving parameters
 				MiningStartBlock::<T>::put(T::BlockNumber::from(414189u32));
 				MiningHalvingInterval::<T>::put(T::BlockNumber::from(324000u32));
+				w += T::DbWeight::get().writes(2);
+			}
+			// Convert `MinerInfo` to the latest layout before any migration iterating `Miners`,
+			// otherwise the entries in the old layout fail to decode and are skipped.
+			// `migrate_benchmark_session_id()` already writes the latest `MinerInfo`.
+			if old == 2 || old == 3 {
+				w += migrations::migrate_benchmark_session_id::<T>();
+			}
+			if (4..=6).contains(&old) {
+				w += migrations::migrate_stop_reason::<T>();
+			}
+			if old == 7 {
+				w += migrations::migrate_miner_confidence_level::<T>();
+			}
+			// Recount as the counters may have drifted with the level changes of the workers
+			if (2..=7).contains(&old) {
+				w += migrations::count_online_miners_by_level::<T>();
+			}
+			if (2..=5).contains(&old) {
+				w += migrations::enqueue_cooling_down_miners::<T>();
+			}
+			if (2..=7).contains(&old) {
+				w += migrations::initialize_halving_period::<T>();
+			}
+			if (2..=7).contains(&old) {
 				STORAGE_VERSION.put::<super::Pallet<T>>();
-				w += T::DbWeight::get().writes(3);
+				w += T::DbWeight::get().writes(1);
 			}
 			w
 		}
+
+		fn on_idle(_n: T::BlockNumber, remaining_weight: Weight) -> Weight {
+			let used = Self::process_cool_down_queue(remaining_weight);
+			used + Self::sweep_online_miners(remaining_weight.saturating_sub(used))
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade() -> Result<(), &'static str> {
+			Self::check_invariants()
+		}
 	}
 
 	impl<T: Config> Pallet<T>
@@ -456,14 +911,30 @@ pub mod pallet {
 			MINING_PALLETID.into_account()
 		}
 
+		/// Returns if the scores are 5 values within `(0, 1]`
+		fn is_valid_confidence_scores(scores: &[u128]) -> bool {
+			scores.len() == DEFAULT_CONFIDENCE_SCORES.len()
+				&& scores.iter().all(|s| {
+					let s = FixedPoint::from_bits(*s);
+					s > fp!(0) && s <= fp!(1)
+				})
+		}
+
 		fn heartbeat_challenge() {
 			// Random seed for the heartbeat challenge
 			let seed_hash = T::Randomness::random(crate::constants::RANDOMNESS_SUBJECT).0;
 			let seed: U256 = AsRef::<[u8]>::as_ref(&seed_hash).into();
 			// PoW target for the random sampling
 			let online_miners = OnlineMiners::<T>::get();
-			let num_tx =
-				ExpectedHeartbeatCount::<T>::get().unwrap_or(DEFAULT_EXPECTED_HEARTBEAT_COUNT);
+			let mut num_tx = Self::expected_heartbeat_count(online_miners);
+			if AdaptiveHeartbeat::<T>::get() {
+				let observed = HeartbeatsInBlock::<T>::take();
+				let factor = Self::update_heartbeat_factor(num_tx, observed);
+				num_tx = (FixedPoint::from_num(num_tx) * factor)
+					.round()
+					.to_num::<u32>()
+					.max(1);
+			}
 			let online_target = pow_target(num_tx, online_miners, T::ExpectedBlockTimeSec::get());
 			let seed_info = HeartbeatChallenge {
 				seed,
@@ -472,11 +943,66 @@ pub mod pallet {
 			Self::push_message(SystemEvent::HeartbeatChallenge(seed_info));
 		}
 
+		/// Returns the total number of heartbeats expected per block
+		///
+		/// The confidence levels with an override contribute the overridden count. The rest of the
+		/// online miners contribute their share of the global expected count.
+		fn expected_heartbeat_count(online_miners: u32) -> u32 {
+			let global =
+				ExpectedHeartbeatCount::<T>::get().unwrap_or(DEFAULT_EXPECTED_HEARTBEAT_COUNT);
+			if online_miners == 0 {
+				return global;
+			}
+			let mut total: u64 = 0;
+			let mut overridden_miners: u32 = 0;
+			for level in 1..=5u8 {
+				if let Some(count) = ExpectedHeartbeatCountByLevel::<T>::get(level) {
+					let level_miners = OnlineMinersByLevel::<T>::get(level);
+					if level_miners > 0 {
+						total += count as u64;
+						overridden_miners += level_miners;
+					}
+				}
+			}
+			let other_miners = online_miners.saturating_sub(overridden_miners);
+			total += global as u64 * other_miners as u64 / online_miners as u64;
+			total.saturated_into()
+		}
+
+		/// Updates the adaptive heartbeat factor with the heartbeats observed in this block
+		///
+		/// The observed rate is smoothed by a moving average. The factor moves by at most 10% per
+		/// block towards `expected / observed`, and is bounded by `MIN_HEARTBEAT_FACTOR` and
+		/// `MAX_HEARTBEAT_FACTOR`.
+		fn update_heartbeat_factor(expected: u32, observed: u32) -> FixedPoint {
+			let expected = FixedPoint::from_num(expected);
+			let observed = FixedPoint::from_num(observed);
+			let rate = ObservedHeartbeatRate::<T>::get()
+				.map(FixedPoint::from_bits)
+				.unwrap_or(expected);
+			let rate = if observed >= rate {
+				rate + (observed - rate) / 8
+			} else {
+				rate - (rate - observed) / 8
+			};
+			let mut factor = HeartbeatTargetFactor::<T>::get()
+				.map(FixedPoint::from_bits)
+				.unwrap_or(MAX_HEARTBEAT_FACTOR);
+			if rate > fp!(0) {
+				let step = (expected / rate).clamp(fp!(0.9), fp!(1.1));
+				factor = (factor * step).clamp(MIN_HEARTBEAT_FACTOR, MAX_HEARTBEAT_FACTOR);
+			}
+			ObservedHeartbeatRate::<T>::put(rate.to_bits());
+			HeartbeatTargetFactor::<T>::put(factor.to_bits());
+			factor
+		}
+
 		fn trigger_subsidy_halving() -> Result<(), ()> {
 			let mut tokenomic = TokenomicParameters::<T>::get().ok_or(())?;
 			let budget_per_block = FixedPoint::from_bits(tokenomic.budget_per_block);
 			let new_budget = budget_per_block * fp!(0.75);
 			tokenomic.budget_per_block = new_budget.to_bits();
+			HalvingPeriod::<T>::mutate(|period| *period += 1);
 			Self::deposit_event(Event::<T>::SubsidyBudgetHalved);
 			Self::update_tokenomic_parameters(tokenomic);
 			Ok(())
@@ -488,6 +1014,7 @@ pub mod pallet {
 			if let MessageOrigin::Worker(worker) = message.sender {
 				match message.payload {
 					MiningReportEvent::Heartbeat {
+						session_id,
 						iterations,
 						challenge_time,
 						..
@@ -502,24 +1029,137 @@ pub mod pallet {
 						//
 						// So we call `ensure_worker_bound` here, and return an error if the worker
 						// is not bound. However if the worker is indeed bound, the rest of the
-						// code assumes the Miners, Workers, and worker score must exist.
+						// code assumes the Miners must exist.
+						//
+						// A heartbeat can also be stale or replayed, or come from an earlier
+						// mining session. It's not fatal. We just count and ignore it.
 						let miner = Self::ensure_worker_bound(&worker)?;
+						// Only counted for the adaptive challenge target
+						if AdaptiveHeartbeat::<T>::get() {
+							HeartbeatsInBlock::<T>::mutate(|n| *n = n.saturating_add(1));
+						}
 						let mut miner_info = Self::miners(&miner).expect("Bound miner; qed.");
-						let worker =
-							registry::Workers::<T>::get(&worker).expect("Bound worker; qed.");
 						let now = Self::now_sec();
 						let challenge_time_sec = challenge_time / 1000;
-						miner_info
-							.benchmark
-							.update(now, iterations, challenge_time_sec)
-							.expect("Benchmark report must be valid; qed.");
-						Miners::<T>::insert(&miner, miner_info);
+						match miner_info.benchmark.update(
+							now,
+							session_id,
+							iterations,
+							challenge_time_sec,
+						) {
+							Ok(()) => {
+								Self::record_performance(&miner, &miner_info.benchmark);
+								Miners::<T>::insert(&miner, miner_info);
+								Self::enforce_pruntime_version(&miner, &worker, now)?;
+								Self::enforce_attestation_freshness(&miner, &worker, now)?;
+							}
+							Err(reason) => {
+								RejectedHeartbeats::<T>::mutate(&miner, |n| {
+									*n = n.saturating_add(1)
+								});
+								Self::deposit_event(Event::<T>::MinerHeartbeatRejected(
+									miner, worker, reason,
+								));
+							}
+						}
 					}
 				};
 			}
 			Ok(())
 		}
 
+		/// Warns or stops a mining worker running a pRuntime below the minimum version
+		///
+		/// The worker is warned once per minimum version before the deadline. After the deadline,
+		/// the miner is stopped with `StopReason::OutdatedPRuntime`.
+		fn enforce_pruntime_version(
+			miner: &T::AccountId,
+			worker: &WorkerPublicKey,
+			now: u64,
+		) -> DispatchResult {
+			let (min_version, deadline) = match registry::MinimumPRuntimeVersion::<T>::get() {
+				Some(v) => v,
+				None => return Ok(()),
+			};
+			let worker_info =
+				registry::Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotRegistered)?;
+			if worker_info.runtime_version >= min_version {
+				return Ok(());
+			}
+			if now >= deadline {
+				PRuntimeUpgradeWarnings::<T>::remove(worker);
+				return Self::stop_mining(miner.clone(), StopReason::OutdatedPRuntime);
+			}
+			if PRuntimeUpgradeWarnings::<T>::get(worker) != Some(min_version) {
+				PRuntimeUpgradeWarnings::<T>::insert(worker, min_version);
+				Self::push_message(SystemEvent::new_worker_event(
+					*worker,
+					WorkerEvent::PRuntimeUpgradeRequired {
+						min_version,
+						deadline,
+					},
+				));
+				Self::deposit_event(Event::<T>::MinerPRuntimeOutdated(
+					miner.clone(),
+					*worker,
+					min_version,
+				));
+			}
+			Ok(())
+		}
+
+		/// Marks a mining worker with an expired attestation stale, and stops the miner after the
+		/// grace period with `StopReason::StaleAttestation`
+		fn enforce_attestation_freshness(
+			miner: &T::AccountId,
+			worker: &WorkerPublicKey,
+			now: u64,
+		) -> DispatchResult {
+			// Already stopped by other checks
+			if Miners::<T>::get(miner).map(|info| info.state) == Some(MinerState::MiningCoolingDown)
+			{
+				return Ok(());
+			}
+			let expired_at = match registry::Pallet::<T>::update_stale_status(worker, now)? {
+				Some(expired_at) => expired_at,
+				None => return Ok(()),
+			};
+			let (_, grace) = registry::AttestationRefreshPolicy::<T>::get().unwrap_or_default();
+			if now >= expired_at.saturating_add(grace) {
+				Self::stop_mining(miner.clone(), StopReason::StaleAttestation)?;
+			}
+			Ok(())
+		}
+
+		/// Pushes the latest `p_instant` to the performance window of a miner
+		///
+		/// Emits `MinerPerformanceDegraded` when the average of a full window drops below the
+		/// alert threshold. Only the crossing is alerted, not every degraded sample.
+		fn record_performance(miner: &T::AccountId, benchmark: &Benchmark) {
+			let mut history = PerformanceHistory::<T>::get(miner);
+			let prev_average = window_average(&history);
+			if history.len() >= PERFORMANCE_WINDOW_SIZE {
+				history.remove(0);
+			}
+			history.push(benchmark.p_instant);
+			let average = window_average(&history);
+			PerformanceHistory::<T>::insert(miner, history);
+
+			if let (Some(threshold), Some(average)) =
+				(PerformanceAlertThreshold::<T>::get(), average)
+			{
+				let min_p = threshold * benchmark.p_init;
+				let was_degraded = matches!(prev_average, Some(prev) if prev < min_p);
+				if average < min_p && !was_degraded {
+					Self::deposit_event(Event::<T>::MinerPerformanceDegraded(
+						miner.clone(),
+						average,
+						benchmark.p_init,
+					));
+				}
+			}
+		}
+
 		pub fn on_gk_message_received(
 			message: DecodedMessage<MiningInfoUpdateEvent<T::BlockNumber>>,
 		) -> DispatchResult {
@@ -593,20 +1233,80 @@ pub mod pallet {
 		fn try_handle_settle(info: &SettleInfo, now: u64) -> DispatchResult {
 			if let Some(account) = WorkerBindings::<T>::get(&info.pubkey) {
 				let mut miner_info = Self::miners(&account).ok_or(Error::<T>::MinerNotFound)?;
-				debug_assert!(miner_info.state.can_settle(), "Miner cannot settle now");
+				ensure!(miner_info.state.can_settle(), Error::<T>::MinerNotMining);
+				// The slash was pre-settled at `stop_mining()`. Capture it before applying the new V
+				// so that we can correct it for a late settlement.
+				let cooling_down = miner_info.state == MinerState::MiningCoolingDown;
+				let orig_stake = Stakes::<T>::get(&account).unwrap_or_default();
+				let (_, prev_slashed) = miner_info.calc_final_stake(orig_stake);
 				miner_info.v = info.v; // in bits
 				miner_info.v_updated_at = now;
 				miner_info.stats.on_reward(info.payout);
 				Miners::<T>::insert(&account, &miner_info);
+				if cooling_down {
+					let (_, slashed) = miner_info.calc_final_stake(orig_stake);
+					if slashed != prev_slashed {
+						T::OnStopped::on_slash_updated(
+							&info.pubkey,
+							orig_stake,
+							prev_slashed,
+							slashed,
+						);
+					}
+				}
 				// Handle treasury deposit
 				let treasury_deposit = FixedPointConvert::from_bits(info.treasury);
 				let imbalance = Self::withdraw_imbalance_from_subsidy_pool(treasury_deposit)?;
 				T::OnTreasurySettled::on_unbalanced(imbalance);
+				Self::record_settlement(
+					FixedPointConvert::from_bits(info.payout),
+					treasury_deposit,
+				);
 				Self::deposit_event(Event::<T>::MinerSettled(account, info.v, info.payout));
 			}
 			Ok(())
 		}
 
+		/// Accumulates a settlement to the subsidy accounting of the current halving period
+		fn record_settlement(payout: BalanceOf<T>, treasury: BalanceOf<T>) {
+			let pool_balance = <T as Config>::Currency::free_balance(&Self::account_id());
+			SubsidyStats::<T>::mutate(HalvingPeriod::<T>::get(), |stats| {
+				stats.payout.saturating_accrue(payout);
+				stats.treasury.saturating_accrue(treasury);
+				stats.pool_balance = pool_balance;
+			});
+		}
+
+		/// The weight of `check_subsidy_pool()`: the tokenomic parameters, the warning blocks, the
+		/// pool balance and the low flag, which may be updated
+		fn check_subsidy_pool_weight() -> Weight {
+			T::DbWeight::get().reads_writes(4, 1)
+		}
+
+		/// Emits `SubsidyPoolInsufficient` when the subsidy pool balance falls below the budget of
+		/// the next `SubsidyWarningBlocks` blocks
+		///
+		/// Only the transition is alerted. It's alerted again if the pool is refilled and then
+		/// falls below the budget again.
+		fn check_subsidy_pool() {
+			let params = match TokenomicParameters::<T>::get() {
+				Some(params) => params,
+				None => return,
+			};
+			let budget_per_block: BalanceOf<T> =
+				FixedPointConvert::from_bits(params.budget_per_block);
+			let blocks = SubsidyWarningBlocks::<T>::get().unwrap_or(DEFAULT_SUBSIDY_WARNING_BLOCKS);
+			let projected = budget_per_block.saturating_mul(blocks.into());
+			let balance = <T as Config>::Currency::free_balance(&Self::account_id());
+			let low = balance < projected;
+			if low != SubsidyPoolLow::<T>::get() {
+				SubsidyPoolLow::<T>::put(low);
+				if low {
+					Self::deposit_event(Event::<T>::SubsidyPoolInsufficient(balance, projected));
+				}
+			}
+		}
+
 		fn can_reclaim(miner_info: &MinerInfo) -> bool {
 			if miner_info.state != MinerState::MiningCoolingDown {
 				return false;
@@ -633,6 +1333,111 @@ pub mod pallet {
 			Ok((orig_stake, slashed))
 		}
 
+		/// The weight to reclaim a miner from the cool down queue, excluding the `OnReclaimed`
+		/// handler which reports its own weight
+		fn auto_reclaim_weight() -> Weight {
+			T::DbWeight::get().reads_writes(5, 3)
+		}
+
+		/// Reclaims the miners whose cool down has ended, in the order of the cool down end
+		///
+		/// Stops when the queue head is still cooling down or `max_weight` is not enough for one
+		/// more miner. The `OnReclaimed` weight is only known after the call, so the last miner
+		/// may exceed `max_weight` by it. Returns the consumed weight.
+		fn process_cool_down_queue(max_weight: Weight) -> Weight {
+			let item_weight = Self::auto_reclaim_weight();
+			let mut used = T::DbWeight::get().reads_writes(3, 1);
+			if used + item_weight > max_weight {
+				return 0;
+			}
+			let (mut head, tail) = CoolDownQueueRange::<T>::get();
+			if head == tail {
+				return T::DbWeight::get().reads(1);
+			}
+			let now = Self::now_sec();
+			let period = Self::cool_down_period();
+			while head < tail && used + item_weight <= max_weight {
+				let (miner, cool_down_start) = match CoolDownQueue::<T>::get(head) {
+					Some(entry) => entry,
+					None => {
+						head += 1;
+						continue;
+					}
+				};
+				if now.saturating_sub(cool_down_start) < period {
+					break;
+				}
+				used += item_weight;
+				CoolDownQueue::<T>::remove(head);
+				head += 1;
+				// Skip the outdated entries (already reclaimed, or stopped again later)
+				let in_queue = matches!(
+					Miners::<T>::get(&miner),
+					Some(info) if info.state == MinerState::MiningCoolingDown
+						&& info.cool_down_start == cool_down_start
+				);
+				if !in_queue || !T::OnReclaimed::can_auto_reclaim(&miner) {
+					continue;
+				}
+				if let Ok((orig_stake, slashed)) = Self::reclaim(miner.clone()) {
+					used += T::OnReclaimed::on_reclaimed(&miner, orig_stake, slashed);
+				}
+			}
+			CoolDownQueueRange::<T>::put((head, tail));
+			used
+		}
+
+		/// Checks the online miners against the minimum pRuntime version and the attestation
+		/// refresh policy
+		///
+		/// The heartbeats only check the workers selected by the challenge, and an offline worker
+		/// sends none, so the online miners are also swept here. The sweep continues from
+		/// `MinerSweepCursor` in the next block when `max_weight` is not enough for one more
+		/// miner. Returns the consumed weight.
+		fn sweep_online_miners(max_weight: Weight) -> Weight {
+			let item_weight = T::DbWeight::get().reads(1);
+			// Enforcing may stop the miner
+			let check_weight = T::DbWeight::get().reads_writes(10, 10);
+			let mut used = T::DbWeight::get().reads_writes(2, 1);
+			if used + item_weight + check_weight > max_weight {
+				return 0;
+			}
+			if registry::MinimumPRuntimeVersion::<T>::get().is_none()
+				&& registry::AttestationRefreshPolicy::<T>::get().is_none()
+			{
+				return T::DbWeight::get().reads(2);
+			}
+			let now = Self::now_sec();
+			let mut iter = match MinerSweepCursor::<T>::get() {
+				Some(cursor) => Miners::<T>::iter_from(cursor),
+				None => Miners::<T>::iter(),
+			};
+			let mut last = None;
+			while used + item_weight + check_weight <= max_weight {
+				let (miner, miner_info) = match iter.next() {
+					Some(entry) => entry,
+					None => {
+						MinerSweepCursor::<T>::kill();
+						return used;
+					}
+				};
+				used += item_weight;
+				if miner_info.state.is_online() {
+					used += check_weight;
+					if let Some(worker) = MinerBindings::<T>::get(&miner) {
+						// Not fatal to the sweep
+						let _ = Self::enforce_pruntime_version(&miner, &worker, now);
+						let _ = Self::enforce_attestation_freshness(&miner, &worker, now);
+					}
+				}
+				last = Some(miner);
+			}
+			if let Some(miner) = last {
+				MinerSweepCursor::<T>::put(Miners::<T>::hashed_key_for(&miner));
+			}
+			used
+		}
+
 		/// Binds a miner to a worker
 		///
 		/// This will bind the miner account to the worker, and then create a `Miners` entry to
@@ -681,9 +1486,13 @@ pub mod pallet {
 						iterations: 0u64,
 						mining_start_time: now,
 						challenge_time_last: 0u64,
+						session_id: None,
 					},
 					cool_down_start: 0u64,
 					stats: Default::default(),
+					stop_reason: None,
+					max_slash: None,
+					confidence_level: 0,
 				},
 			);
 
@@ -706,7 +1515,7 @@ pub mod pallet {
 			if force {
 				// Force unbinding. Stop the miner first.
 				// Note that `stop_mining` will notify the subscribers with the slashed value.
-				Self::stop_mining(miner.clone())?;
+				Self::stop_mining(miner.clone(), StopReason::Unbound)?;
 				// TODO: consider the final state sync (could cause slash) when stopping mining
 			}
 			MinerBindings::<T>::remove(miner);
@@ -737,7 +1546,16 @@ pub mod pallet {
 
 			let worker_info =
 				registry::Workers::<T>::get(&worker).expect("Bounded worker must exist; qed.");
-			let p = worker_info
+			ensure!(
+				!registry::Pallet::<T>::is_pruntime_outdated(&worker_info, Self::now_sec()),
+				Error::<T>::PRuntimeVersionTooLow
+			);
+			ensure!(
+				registry::Pallet::<T>::attestation_expired_at(&worker_info, Self::now_sec())
+					.is_none(),
+				Error::<T>::AttestationStale
+			);
+			let p = worker_info
 				.initial_score
 				.ok_or(Error::<T>::BenchmarkMissing)?;
 			// Disallow some weird benchmark score.
@@ -747,13 +1565,17 @@ pub mod pallet {
 			let min_stake = tokenomic.minimal_stake(p);
 			ensure!(stake >= min_stake, Error::<T>::InsufficientStake);
 
-			let ve = tokenomic.ve(stake, p, worker_info.confidence_level);
+			let tee_type = registry::Pallet::<T>::worker_tee_type(&worker);
+			let ve = tokenomic.ve(stake, p, tee_type, worker_info.confidence_level);
 			let v_max = tokenomic.v_max();
 			ensure!(ve <= v_max, Error::<T>::TooMuchStake);
 
 			let now = Self::now_sec();
+			let session_id = NextSessionId::<T>::get();
+			NextSessionId::<T>::put(session_id + 1);
 
 			Stakes::<T>::insert(&miner, stake);
+			PerformanceHistory::<T>::remove(&miner);
 			Miners::<T>::mutate(&miner, |info| {
 				if let Some(info) = info {
 					info.state = MinerState::MiningIdle;
@@ -761,12 +1583,15 @@ pub mod pallet {
 					info.v = ve.to_bits();
 					info.v_updated_at = now;
 					info.benchmark.p_init = p;
+					info.benchmark.session_id = Some(session_id);
+					info.stop_reason = None;
+					info.max_slash = None;
+					info.confidence_level = worker_info.confidence_level;
 				}
 			});
 			OnlineMiners::<T>::mutate(|v| *v += 1);
+			OnlineMinersByLevel::<T>::mutate(worker_info.confidence_level, |v| *v += 1);
 
-			let session_id = NextSessionId::<T>::get();
-			NextSessionId::<T>::put(session_id + 1);
 			Self::push_message(SystemEvent::new_worker_event(
 				worker,
 				WorkerEvent::MiningStart {
@@ -781,9 +1606,12 @@ pub mod pallet {
 
 		/// Stops mining, entering cool down state
 		///
+		/// The `reason` is recorded in `MinerInfo`, and the slash cap of the reason (if any) is
+		/// applied to the final stake.
+		///
 		/// Requires:
 		/// 1. The miner is in Idle, MiningActive, or MiningUnresponsive state
-		pub fn stop_mining(miner: T::AccountId) -> DispatchResult {
+		pub fn stop_mining(miner: T::AccountId, reason: StopReason) -> DispatchResult {
 			let worker = MinerBindings::<T>::get(&miner).ok_or(Error::<T>::MinerNotBound)?;
 			let mut miner_info = Miners::<T>::get(&miner).ok_or(Error::<T>::MinerNotFound)?;
 
@@ -796,14 +1624,22 @@ pub mod pallet {
 			let now = Self::now_sec();
 			miner_info.state = MinerState::MiningCoolingDown;
 			miner_info.cool_down_start = now;
+			miner_info.stop_reason = Some(reason);
+			miner_info.max_slash = SlashCaps::<T>::get(reason);
 			Miners::<T>::insert(&miner, &miner_info);
+			Self::enqueue_cool_down(&miner, now);
 			OnlineMiners::<T>::mutate(|v| *v -= 1); // v cannot be 0
 
+			// The worker may have re-registered with another level, so use the counted one
+			OnlineMinersByLevel::<T>::mutate(miner_info.confidence_level, |v| {
+				*v = v.saturating_sub(1)
+			});
+
 			// Calculate remaining stake (assume there's no more slash after calling `stop_mining`)
 			let orig_stake = Stakes::<T>::get(&miner).unwrap_or_default();
 			let (_returned, slashed) = miner_info.calc_final_stake(orig_stake);
 			// Notify the subscriber with the slash prediction
-			T::OnStopped::on_stopped(&worker, orig_stake, slashed);
+			T::OnStopped::on_stopped(&worker, orig_stake, slashed, reason);
 
 			Self::push_message(SystemEvent::new_worker_event(
 				worker,
@@ -813,6 +1649,12 @@ pub mod pallet {
 			Ok(())
 		}
 
+		fn enqueue_cool_down(miner: &T::AccountId, cool_down_start: u64) {
+			let (head, tail) = CoolDownQueueRange::<T>::get();
+			CoolDownQueue::<T>::insert(tail, (miner.clone(), cool_down_start));
+			CoolDownQueueRange::<T>::put((head, tail + 1));
+		}
+
 		/// Returns if the worker is already bounded to a miner
 		pub fn ensure_worker_bound(pubkey: &WorkerPublicKey) -> Result<T::AccountId, Error<T>> {
 			WorkerBindings::<T>::get(&pubkey).ok_or(Error::<T>::WorkerNotBound)
@@ -823,6 +1665,68 @@ pub mod pallet {
 			MinerBindings::<T>::get(&miner).ok_or(Error::<T>::MinerNotBound)
 		}
 
+		/// Checks the consistency of the miner states, the bindings and the stakes
+		///
+		/// Iterates over all the miners. Only for try-runtime and tests.
+		#[cfg(any(feature = "try-runtime", test))]
+		pub fn check_invariants() -> Result<(), &'static str> {
+			let mut online_miners = 0u32;
+			let mut online_by_level = sp_std::collections::btree_map::BTreeMap::<u8, u32>::new();
+			for (miner, info) in Miners::<T>::iter() {
+				if info.state.is_online() {
+					online_miners += 1;
+					*online_by_level.entry(info.confidence_level).or_default() += 1;
+					ensure!(
+						MinerBindings::<T>::contains_key(&miner),
+						"Online miner is not bound"
+					);
+				}
+				ensure!(
+					Stakes::<T>::contains_key(&miner) == (info.state != MinerState::Ready),
+					"Stake exists iff the miner is not ready"
+				);
+			}
+			ensure!(
+				OnlineMiners::<T>::get() == online_miners,
+				"OnlineMiners mismatches the online miners"
+			);
+			for (level, count) in OnlineMinersByLevel::<T>::iter() {
+				ensure!(
+					count == online_by_level.get(&level).cloned().unwrap_or(0),
+					"OnlineMinersByLevel mismatches the online miners"
+				);
+			}
+			let counted_by_level: u32 = OnlineMinersByLevel::<T>::iter_values().sum();
+			ensure!(
+				counted_by_level == online_miners,
+				"OnlineMinersByLevel mismatches the online miners"
+			);
+			for (miner, worker) in MinerBindings::<T>::iter() {
+				ensure!(
+					WorkerBindings::<T>::get(&worker).as_ref() == Some(&miner),
+					"MinerBindings mismatches WorkerBindings"
+				);
+				ensure!(
+					Miners::<T>::contains_key(&miner),
+					"Bound miner doesn't exist"
+				);
+				ensure!(
+					registry::Workers::<T>::contains_key(&worker),
+					"Bound worker isn't registered"
+				);
+			}
+			for (worker, miner) in WorkerBindings::<T>::iter() {
+				ensure!(
+					MinerBindings::<T>::get(&miner) == Some(worker),
+					"WorkerBindings mismatches MinerBindings"
+				);
+			}
+			for miner in Stakes::<T>::iter_keys() {
+				ensure!(Miners::<T>::contains_key(&miner), "Stake without a miner");
+			}
+			Ok(())
+		}
+
 		fn update_tokenomic_parameters(params: TokenomicParams) {
 			TokenomicParameters::<T>::put(params.clone());
 			Self::push_message(GatekeeperEvent::TokenomicParametersChanged(params));
@@ -885,9 +1789,15 @@ pub mod pallet {
 		}
 
 		/// Calculate the initial Ve
-		fn ve(&self, s: BalanceOf<T>, p: u32, confidence_level: u8) -> FixedPoint {
+		fn ve(
+			&self,
+			s: BalanceOf<T>,
+			p: u32,
+			tee_type: TeeType,
+			confidence_level: u8,
+		) -> FixedPoint {
 			let f1 = FixedPoint::from_num(1);
-			let score = Self::confidence_score(confidence_level);
+			let score = Self::confidence_score(tee_type, confidence_level);
 			let re = FixedPoint::from_bits(self.params.re);
 			let tweaked_re = (re - f1) * score + f1;
 			let s = s.to_fixed();
@@ -920,13 +1830,20 @@ pub mod pallet {
 		}
 
 		/// Converts confidence level to score
-		fn confidence_score(confidence_level: u8) -> FixedPoint {
-			use fixed_macro::types::U64F64 as fp;
-			const SCORES: [FixedPoint; 5] = [fp!(1), fp!(1), fp!(1), fp!(0.8), fp!(0.7)];
-			if 1 <= confidence_level && confidence_level <= 5 {
-				SCORES[confidence_level as usize - 1]
+		///
+		/// The scores of the TEE type are used if set. Unknown levels are treated as level 1.
+		fn confidence_score(tee_type: TeeType, confidence_level: u8) -> FixedPoint {
+			let index = if 1 <= confidence_level && confidence_level <= 5 {
+				confidence_level as usize - 1
 			} else {
-				SCORES[0]
+				0
+			};
+			match TeeConfidenceScores::<T>::get(tee_type).or_else(ConfidenceScores::<T>::get) {
+				Some(scores) => scores
+					.get(index)
+					.map(|s| FixedPoint::from_bits(*s))
+					.unwrap_or(DEFAULT_CONFIDENCE_SCORES[index]),
+				None => DEFAULT_CONFIDENCE_SCORES[index],
 			}
 		}
 
@@ -1001,9 +1918,19 @@ pub mod pallet {
 	}
 
 	mod migrations {
-		use super::{Config, CoolDownPeriod, Pallet, TokenomicParameters};
+		use super::{
+			Benchmark, Config, CoolDownPeriod, CoolDownQueue, CoolDownQueueRange, HalvingPeriod,
+			MinerBindings, MinerInfo, MinerState, MinerStats, Miners, MiningHalvingInterval,
+			MiningStartBlock, OnlineMinersByLevel, Pallet, StopReason, TokenomicParameters,
+		};
+		use crate::registry;
 		use fixed_macro::types::U64F64 as fp;
 		use frame_support::pallet_prelude::*;
+		use sp_runtime::{
+			traits::{Saturating, Zero},
+			Permill, SaturatedConversion,
+		};
+		use sp_std::prelude::*;
 
 		use phala_types::messaging::TokenomicParameters as TokenomicParams;
 
@@ -1055,6 +1982,203 @@ pub mod pallet {
 			Pallet::<T>::queue_message(GatekeeperEvent::RepairV);
 			T::DbWeight::get().writes(1)
 		}
+
+		/// Adds `Benchmark::session_id` to the existing miners
+		///
+		/// The session of the miners started before the upgrade is unknown, so it's left empty.
+		pub fn migrate_benchmark_session_id<T: Config>() -> Weight {
+			#[derive(Decode)]
+			struct OldBenchmark {
+				p_init: u32,
+				p_instant: u32,
+				iterations: u64,
+				mining_start_time: u64,
+				challenge_time_last: u64,
+			}
+			#[derive(Decode)]
+			struct OldMinerInfo {
+				state: MinerState,
+				ve: u128,
+				v: u128,
+				v_updated_at: u64,
+				benchmark: OldBenchmark,
+				cool_down_start: u64,
+				stats: MinerStats,
+			}
+
+			log::info!("phala_pallet::mining: migrate_benchmark_session_id()");
+			let mut count = 0u64;
+			Miners::<T>::translate(|miner, old: OldMinerInfo| {
+				count += 1;
+				Some(MinerInfo {
+					state: old.state,
+					ve: old.ve,
+					v: old.v,
+					v_updated_at: old.v_updated_at,
+					benchmark: Benchmark {
+						p_init: old.benchmark.p_init,
+						p_instant: old.benchmark.p_instant,
+						iterations: old.benchmark.iterations,
+						mining_start_time: old.benchmark.mining_start_time,
+						challenge_time_last: old.benchmark.challenge_time_last,
+						session_id: None,
+					},
+					cool_down_start: old.cool_down_start,
+					stats: old.stats,
+					stop_reason: None,
+					max_slash: None,
+					confidence_level: bound_worker_level::<T>(&miner),
+				})
+			});
+			T::DbWeight::get().reads_writes(count, count)
+		}
+
+		/// Adds `MinerInfo::stop_reason` and `MinerInfo::max_slash` to the existing miners
+		///
+		/// The miners already cooling down are left uncapped, as they were stopped.
+		pub fn migrate_stop_reason<T: Config>() -> Weight {
+			#[derive(Decode)]
+			struct OldMinerInfo {
+				state: MinerState,
+				ve: u128,
+				v: u128,
+				v_updated_at: u64,
+				benchmark: Benchmark,
+				cool_down_start: u64,
+				stats: MinerStats,
+			}
+
+			log::info!("phala_pallet::mining: migrate_stop_reason()");
+			let mut count = 0u64;
+			Miners::<T>::translate(|miner, old: OldMinerInfo| {
+				count += 1;
+				Some(MinerInfo {
+					state: old.state,
+					ve: old.ve,
+					v: old.v,
+					v_updated_at: old.v_updated_at,
+					benchmark: old.benchmark,
+					cool_down_start: old.cool_down_start,
+					stats: old.stats,
+					stop_reason: None,
+					max_slash: None,
+					confidence_level: bound_worker_level::<T>(&miner),
+				})
+			});
+			T::DbWeight::get().reads_writes(count, count)
+		}
+
+		/// Adds `MinerInfo::confidence_level` to the existing miners
+		///
+		/// The level the online miners were counted with is unknown, so the current level of the
+		/// bound worker is taken, and `OnlineMinersByLevel` must be recounted afterwards.
+		pub fn migrate_miner_confidence_level<T: Config>() -> Weight {
+			#[derive(Decode)]
+			struct OldMinerInfo {
+				state: MinerState,
+				ve: u128,
+				v: u128,
+				v_updated_at: u64,
+				benchmark: Benchmark,
+				cool_down_start: u64,
+				stats: MinerStats,
+				stop_reason: Option<StopReason>,
+				max_slash: Option<Permill>,
+			}
+
+			log::info!("phala_pallet::mining: migrate_miner_confidence_level()");
+			let mut count = 0u64;
+			Miners::<T>::translate(|miner, old: OldMinerInfo| {
+				count += 1;
+				Some(MinerInfo {
+					state: old.state,
+					ve: old.ve,
+					v: old.v,
+					v_updated_at: old.v_updated_at,
+					benchmark: old.benchmark,
+					cool_down_start: old.cool_down_start,
+					stats: old.stats,
+					stop_reason: old.stop_reason,
+					max_slash: old.max_slash,
+					confidence_level: bound_worker_level::<T>(&miner),
+				})
+			});
+			T::DbWeight::get().reads_writes(count * 3, count)
+		}
+
+		/// Returns the confidence level of the worker bound to the miner, or 0 if unbound
+		fn bound_worker_level<T: Config>(miner: &T::AccountId) -> u8 {
+			MinerBindings::<T>::get(miner)
+				.and_then(|worker| registry::Workers::<T>::get(&worker))
+				.map(|worker_info| worker_info.confidence_level)
+				.unwrap_or(0)
+		}
+
+		/// Sets `HalvingPeriod` to the number of the halvings already triggered
+		///
+		/// A halving is triggered at the last block of each interval, so all the halvings up to
+		/// the current block are already done.
+		pub fn initialize_halving_period<T: Config>() -> Weight {
+			log::info!("phala_pallet::mining: initialize_halving_period()");
+			let interval = match MiningHalvingInterval::<T>::get() {
+				Some(interval) if !interval.is_zero() => interval,
+				_ => return T::DbWeight::get().reads(1),
+			};
+			let start = MiningStartBlock::<T>::get().unwrap_or_default();
+			let now = frame_system::Pallet::<T>::block_number();
+			let period = (now.saturating_sub(start) / interval).saturated_into::<u32>();
+			HalvingPeriod::<T>::put(period);
+			T::DbWeight::get().reads_writes(3, 1)
+		}
+
+		/// Puts the miners already in cool down to the cool down queue
+		pub fn enqueue_cooling_down_miners<T: Config>() -> Weight {
+			log::info!("phala_pallet::mining: enqueue_cooling_down_miners()");
+			let mut reads = 0u64;
+			let mut cooling_down: Vec<(u64, T::AccountId)> = Vec::new();
+			for (miner, info) in Miners::<T>::iter() {
+				reads += 1;
+				if info.state == MinerState::MiningCoolingDown {
+					cooling_down.push((info.cool_down_start, miner));
+				}
+			}
+			cooling_down.sort_by_key(|(cool_down_start, _)| *cool_down_start);
+			let (head, mut tail) = CoolDownQueueRange::<T>::get();
+			let writes = cooling_down.len() as u64 + 1;
+			for (cool_down_start, miner) in cooling_down {
+				CoolDownQueue::<T>::insert(tail, (miner, cool_down_start));
+				tail += 1;
+			}
+			CoolDownQueueRange::<T>::put((head, tail));
+			T::DbWeight::get().reads_writes(reads + 1, writes)
+		}
+
+		/// Counts the online miners by the confidence level recorded in `MinerInfo`
+		pub fn count_online_miners_by_level<T: Config>() -> Weight {
+			log::info!("phala_pallet::mining: count_online_miners_by_level()");
+			let mut reads = 0u64;
+			let mut writes = 0u64;
+			// At most 6 levels (0 to 5)
+			OnlineMinersByLevel::<T>::remove_all(None);
+			writes += 6;
+			for (_miner, info) in Miners::<T>::iter() {
+				reads += 1;
+				if info.state.is_online() {
+					OnlineMinersByLevel::<T>::mutate(info.confidence_level, |v| *v += 1);
+					writes += 1;
+				}
+			}
+			T::DbWeight::get().reads_writes(reads, writes)
+		}
+	}
+
+	/// Returns the average of a full performance window, or `None` if the window isn't full yet
+	fn window_average(samples: &[u32]) -> Option<u32> {
+		if samples.len() < PERFORMANCE_WINDOW_SIZE {
+			return None;
+		}
+		let sum: u64 = samples.iter().map(|p| *p as u64).sum();
+		Some((sum / samples.len() as u64) as u32)
 	}
 
 	fn pow_target(num_tx: u32, num_workers: u32, secs_per_block: u32) -> U256 {
@@ -1085,11 +2209,12 @@ pub mod pallet {
 	mod test {
 		use super::*;
 		use crate::mock::{
-			elapse_seconds, new_test_ext, set_block_1, setup_workers, take_events, take_messages,
-			worker_pubkey, Event as TestEvent, Origin, Test, DOLLARS,
+			check_invariants, elapse_cool_down, elapse_seconds, new_test_ext, set_block_1,
+			setup_workers, take_events, take_messages, worker_pubkey, Event as TestEvent, Origin,
+			Test, DOLLARS,
 		};
 		// Pallets
-		use crate::mock::{PhalaMining, PhalaRegistry, System};
+		use crate::mock::{Balances, BlockNumber, PhalaMining, PhalaRegistry, System, Timestamp};
 
 		use fixed_macro::types::U64F64 as fp;
 		use frame_support::{assert_noop, assert_ok};
@@ -1147,6 +2272,104 @@ pub mod pallet {
 				assert_eq!(target, U256::from_dec_str(
 					"771946525395830978497002573683960742805751636319313395421818009383503547160"
 				).unwrap());
+				// The online miners are faked for the target only
+				OnlineMiners::<Test>::kill();
+			});
+		}
+
+		#[test]
+		fn test_expected_heartbeat_count() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				for (i, level) in [(1, 1u8), (2, 5u8)] {
+					registry::Workers::<Test>::mutate(worker_pubkey(i), |w| {
+						w.as_mut().unwrap().confidence_level = level;
+					});
+					PhalaRegistry::internal_set_benchmark(&worker_pubkey(i), Some(600));
+					assert_ok!(PhalaMining::bind(i as _, worker_pubkey(i)));
+					assert_ok!(PhalaMining::start_mining(i as _, 3000 * DOLLARS));
+				}
+				assert_eq!(OnlineMinersByLevel::<Test>::get(1), 1);
+				assert_eq!(OnlineMinersByLevel::<Test>::get(5), 1);
+				assert_eq!(PhalaMining::expected_heartbeat_count(2), 20);
+				// Governance only
+				assert_noop!(
+					PhalaMining::set_expected_heartbeat_count(Origin::signed(1), None, Some(30)),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaMining::set_expected_heartbeat_count(
+					Origin::root(),
+					None,
+					Some(30)
+				));
+				assert_eq!(PhalaMining::expected_heartbeat_count(2), 30);
+				// Override level 1, while level 5 still takes half of the global count
+				assert_ok!(PhalaMining::set_expected_heartbeat_count(
+					Origin::root(),
+					Some(1),
+					Some(4)
+				));
+				assert_eq!(PhalaMining::expected_heartbeat_count(2), 4 + 15);
+				assert_noop!(
+					PhalaMining::set_expected_heartbeat_count(Origin::root(), Some(6), Some(4)),
+					Error::<Test>::InvalidConfidenceLevel
+				);
+				// The override doesn't apply without online miners at that level. The miner is
+				// uncounted from the level it started with, even if the worker has re-registered
+				// with another level.
+				registry::Workers::<Test>::mutate(worker_pubkey(1), |w| {
+					w.as_mut().unwrap().confidence_level = 3;
+				});
+				assert_ok!(PhalaMining::stop_mining(1, StopReason::Voluntary));
+				assert_eq!(OnlineMinersByLevel::<Test>::get(1), 0);
+				assert_eq!(OnlineMinersByLevel::<Test>::get(3), 0);
+				assert_eq!(OnlineMinersByLevel::<Test>::get(5), 1);
+				assert_eq!(PhalaMining::expected_heartbeat_count(1), 30);
+				check_invariants();
+			});
+		}
+
+		#[test]
+		fn test_adaptive_heartbeat() {
+			use phala_types::messaging::SystemEvent;
+			fn challenge_target() -> U256 {
+				Pallet::<Test>::heartbeat_challenge();
+				let msgs = take_messages();
+				match msgs[0].decode_payload::<SystemEvent>() {
+					Some(SystemEvent::HeartbeatChallenge(r)) => r.online_target,
+					_ => panic!("Wrong outbound message"),
+				}
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let block_sec = <Test as Config>::ExpectedBlockTimeSec::get();
+				// Enough miners to not hit the per-miner cap in `pow_target`
+				OnlineMiners::<Test>::put(5000);
+				assert_ok!(PhalaMining::set_adaptive_heartbeat(Origin::root(), true));
+				// A surge of heartbeats scales the expected count down to the lower bound
+				for _ in 0..50 {
+					HeartbeatsInBlock::<Test>::put(80);
+					challenge_target();
+				}
+				assert_eq!(
+					HeartbeatTargetFactor::<Test>::get(),
+					Some(MIN_HEARTBEAT_FACTOR.to_bits())
+				);
+				HeartbeatsInBlock::<Test>::put(80);
+				assert_eq!(challenge_target(), pow_target(2, 5000, block_sec));
+				assert_eq!(HeartbeatsInBlock::<Test>::get(), 0);
+				// Recovers when the surge is over
+				for _ in 0..100 {
+					challenge_target();
+				}
+				assert_eq!(
+					HeartbeatTargetFactor::<Test>::get(),
+					Some(MAX_HEARTBEAT_FACTOR.to_bits())
+				);
+				assert_eq!(challenge_target(), pow_target(20, 5000, block_sec));
+				// The online miners are faked for the target only
+				OnlineMiners::<Test>::kill();
 			});
 		}
 
@@ -1227,23 +2450,23 @@ pub mod pallet {
 				assert_eq!(tokenomic.minimal_stake(1000), 3162_277660146355);
 				// Ve for different confidence level
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 1),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 1),
 					fp!(1690.0000000000000000282)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 2),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 2),
 					fp!(1690.0000000000000000282)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 3),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 3),
 					fp!(1690.0000000000000000282)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 4),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 4),
 					fp!(1612.0000000000000000247)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 5),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 5),
 					fp!(1572.9999999999999999877)
 				);
 				// Rig cost estimation
@@ -1292,6 +2515,92 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_subsidy_accounting() {
+			use phala_types::messaging::{DecodedMessage, MessageOrigin, Topic};
+			fn settle(payout: FixedPoint, treasury: FixedPoint) {
+				assert_ok!(PhalaMining::on_gk_message_received(DecodedMessage::<
+					MiningInfoUpdateEvent<BlockNumber>,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/mining/update"),
+					payload: MiningInfoUpdateEvent::<BlockNumber> {
+						block_number: 1,
+						timestamp_ms: 0,
+						offline: vec![],
+						recovered_to_online: vec![],
+						settle: vec![SettleInfo {
+							pubkey: worker_pubkey(1),
+							v: 0,
+							payout: payout.to_bits(),
+							treasury: treasury.to_bits(),
+						}],
+					},
+				}));
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				settle(fp!(10), fp!(2));
+				settle(fp!(5), fp!(1));
+				let pool_balance = Balances::free_balance(PhalaMining::account_id());
+				assert_eq!(pool_balance, 690_000_000 * DOLLARS - 3 * DOLLARS);
+				assert_eq!(
+					PhalaMining::subsidy_stats(0),
+					SubsidyPeriodStats {
+						payout: 15 * DOLLARS,
+						treasury: 3 * DOLLARS,
+						pool_balance,
+					}
+				);
+				// A new halving period starts a new record
+				assert_ok!(PhalaMining::trigger_subsidy_halving());
+				assert_eq!(PhalaMining::halving_period(), 1);
+				settle(fp!(1), fp!(0));
+				assert_eq!(PhalaMining::subsidy_stats(1).payout, 1 * DOLLARS);
+				assert_eq!(PhalaMining::subsidy_stats(1).treasury, 0);
+				assert_eq!(PhalaMining::subsidy_stats(0).payout, 15 * DOLLARS);
+				// The pool covers 75 PHA per block for 9_000_000 blocks, but not 10_000_000 blocks
+				let _ = take_events();
+				assert_ok!(PhalaMining::set_subsidy_warning_blocks(
+					Origin::root(),
+					Some(9_000_000)
+				));
+				PhalaMining::check_subsidy_pool();
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaMining(Event::SubsidyWarningBlocksChanged(
+						Some(9_000_000)
+					))]
+				);
+				assert_ok!(PhalaMining::set_subsidy_warning_blocks(
+					Origin::root(),
+					Some(10_000_000)
+				));
+				PhalaMining::check_subsidy_pool();
+				let balance = Balances::free_balance(PhalaMining::account_id());
+				assert_eq!(
+					take_events(),
+					vec![
+						TestEvent::PhalaMining(Event::SubsidyWarningBlocksChanged(Some(
+							10_000_000
+						))),
+						TestEvent::PhalaMining(Event::SubsidyPoolInsufficient(
+							balance,
+							750_000_000 * DOLLARS
+						))
+					]
+				);
+				// Only alerted once
+				PhalaMining::check_subsidy_pool();
+				assert_eq!(take_events(), vec![]);
+				assert!(SubsidyPoolLow::<Test>::get());
+			});
+		}
+
 		#[test]
 		fn tokenomic_update_is_postponed() {
 			new_test_ext().execute_with(|| {
@@ -1317,32 +2626,95 @@ pub mod pallet {
 				assert_eq!(tokenomic.minimal_stake(1000), 1581_138830073177);
 
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 1),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 1),
 					fp!(2035.71428571428571430895)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 2),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 2),
 					fp!(2035.71428571428571430895)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 3),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 3),
 					fp!(2035.71428571428571430895)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 4),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 4),
 					fp!(1899.99999999999999999225)
 				);
 				assert_eq!(
-					tokenomic.ve(1000 * DOLLARS, 1000, 5),
+					tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 5),
 					fp!(1832.14285714285714283387)
 				);
 				assert_eq!(
-					tokenomic.ve(5000 * DOLLARS, 2000, 4),
+					tokenomic.ve(5000 * DOLLARS, 2000, TeeType::Sgx, 4),
 					fp!(7999.99999999999999991944)
 				);
 			});
 		}
 
+		#[test]
+		fn test_set_confidence_scores() {
+			use phala_types::messaging::GatekeeperEvent;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let params = TokenomicParameters::<Test>::get().unwrap();
+				let tokenomic = Tokenomic::<Test>::new(params);
+				let ve1 = tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 1);
+				assert!(tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 4) < ve1);
+				// Must be 5 scores within (0, 1]
+				for scores in [vec![fp!(1).to_bits(); 4], vec![fp!(1.1).to_bits(); 5]] {
+					assert_noop!(
+						PhalaMining::set_confidence_scores(Origin::root(), scores),
+						Error::<Test>::InvalidConfidenceScores
+					);
+				}
+				assert_noop!(
+					PhalaMining::set_confidence_scores(
+						Origin::signed(1),
+						vec![fp!(1).to_bits(); 5]
+					),
+					DispatchError::BadOrigin
+				);
+				// Level 4 is as good as level 1 now
+				let _ = take_messages();
+				let scores = vec![fp!(1).to_bits(); 5];
+				assert_ok!(PhalaMining::set_confidence_scores(
+					Origin::root(),
+					scores.clone()
+				));
+				assert_eq!(tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 4), ve1);
+				// Forwarded to the gatekeeper
+				let msgs = take_messages();
+				assert_eq!(msgs.len(), 1);
+				assert_eq!(
+					msgs[0].decode_payload::<GatekeeperEvent>(),
+					Some(GatekeeperEvent::ConfidenceScoresChanged(scores))
+				);
+				// Scores of a TEE type override the default ones
+				let tee_scores = vec![fp!(0.5).to_bits(); 5];
+				assert_ok!(PhalaMining::set_tee_confidence_scores(
+					Origin::root(),
+					TeeType::Tdx,
+					Some(tee_scores.clone())
+				));
+				assert!(tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Tdx, 1) < ve1);
+				assert_eq!(tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Sgx, 1), ve1);
+				assert_eq!(
+					take_messages()[0].decode_payload::<GatekeeperEvent>(),
+					Some(GatekeeperEvent::TeeConfidenceScoresChanged(
+						TeeType::Tdx,
+						Some(tee_scores)
+					))
+				);
+				assert_ok!(PhalaMining::set_tee_confidence_scores(
+					Origin::root(),
+					TeeType::Tdx,
+					None
+				));
+				assert_eq!(tokenomic.ve(1000 * DOLLARS, 1000, TeeType::Tdx, 1), ve1);
+			});
+		}
+
 		#[test]
 		fn test_benchmark_report() {
 			use phala_types::messaging::{DecodedMessage, MessageOrigin, MiningReportEvent, Topic};
@@ -1371,6 +2743,8 @@ pub mod pallet {
 						iterations: 11000,
 					},
 				}));
+				// Not counted unless in the adaptive mode
+				assert!(!HeartbeatsInBlock::<Test>::exists());
 				let miner = PhalaMining::miners(1).unwrap();
 				assert_eq!(
 					miner.benchmark,
@@ -1380,10 +2754,12 @@ pub mod pallet {
 						iterations: 11000,
 						mining_start_time: 0,
 						challenge_time_last: 100,
+						session_id: Some(0),
 					}
 				);
 
 				// 150% boost (capped)
+				assert_ok!(PhalaMining::set_adaptive_heartbeat(Origin::root(), true));
 				elapse_seconds(100);
 				assert_ok!(PhalaMining::on_mining_message_received(DecodedMessage::<
 					MiningReportEvent,
@@ -1397,6 +2773,7 @@ pub mod pallet {
 						iterations: 11000 + 15000,
 					},
 				}));
+				assert_eq!(HeartbeatsInBlock::<Test>::get(), 1);
 				let miner = PhalaMining::miners(1).unwrap();
 				assert_eq!(
 					miner.benchmark,
@@ -1406,11 +2783,122 @@ pub mod pallet {
 						iterations: 26000,
 						mining_start_time: 0,
 						challenge_time_last: 200,
+						session_id: Some(0),
 					}
 				);
 			});
 		}
 
+		#[test]
+		fn test_settle_in_ready_is_rejected() {
+			use phala_types::messaging::{
+				DecodedMessage, MessageOrigin, MiningInfoUpdateEvent, SettleInfo, Topic,
+			};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				let _ = take_events();
+				assert_ok!(PhalaMining::on_gk_message_received(DecodedMessage::<
+					MiningInfoUpdateEvent<u64>,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/mining/update"),
+					payload: MiningInfoUpdateEvent::<u64> {
+						block_number: 1,
+						timestamp_ms: 0,
+						offline: vec![],
+						recovered_to_online: vec![],
+						settle: vec![SettleInfo {
+							pubkey: worker_pubkey(1),
+							v: fp!(1).to_bits(),
+							payout: 0,
+							treasury: 0,
+						}],
+					},
+				}));
+				let ev = take_events();
+				assert_eq!(
+					ev[0],
+					TestEvent::PhalaMining(Event::InternalErrorMinerSettleFailed(worker_pubkey(1)))
+				);
+				assert_eq!(PhalaMining::miners(1).unwrap().v, 0);
+			});
+		}
+
+		#[test]
+		fn test_stop_reason_slash_cap() {
+			use phala_types::messaging::{
+				DecodedMessage, MessageOrigin, MiningInfoUpdateEvent, SettleInfo, Topic,
+			};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::bind(2, worker_pubkey(2)));
+				assert_noop!(
+					PhalaMining::set_slash_cap(
+						Origin::root(),
+						StopReason::Voluntary,
+						Some(Permill::zero())
+					),
+					Error::<Test>::CannotCapVoluntaryStop
+				);
+				assert_ok!(PhalaMining::set_slash_cap(
+					Origin::root(),
+					StopReason::Governance,
+					Some(Permill::from_percent(10))
+				));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_ok!(PhalaMining::start_mining(2, 3000 * DOLLARS));
+				// Both miners lose half of their V
+				let settle = [1, 2]
+					.iter()
+					.map(|i| SettleInfo {
+						pubkey: worker_pubkey(*i),
+						v: (FixedPoint::from_bits(PhalaMining::miners(*i as u64).unwrap().ve) / 2)
+							.to_bits(),
+						payout: 0,
+						treasury: 0,
+					})
+					.collect();
+				assert_ok!(PhalaMining::on_gk_message_received(DecodedMessage::<
+					MiningInfoUpdateEvent<u64>,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/mining/update"),
+					payload: MiningInfoUpdateEvent::<u64> {
+						block_number: 1,
+						timestamp_ms: 0,
+						offline: vec![],
+						recovered_to_online: vec![],
+						settle,
+					},
+				}));
+				// The voluntary stop is fully slashed, while the governance one is capped
+				assert_ok!(PhalaMining::stop_mining(1, StopReason::Voluntary));
+				assert_ok!(PhalaMining::force_stop_mining(Origin::root(), 2));
+				assert_eq!(
+					PhalaMining::miners(2).unwrap().stop_reason,
+					Some(StopReason::Governance)
+				);
+				// Changing the cap doesn't affect the miners already stopped
+				assert_ok!(PhalaMining::set_slash_cap(
+					Origin::root(),
+					StopReason::Governance,
+					None
+				));
+				elapse_cool_down();
+				let (_, slashed) = PhalaMining::reclaim(1).unwrap();
+				assert!(slashed >= 1499 * DOLLARS);
+				assert_eq!(PhalaMining::reclaim(2), Ok((3000 * DOLLARS, 300 * DOLLARS)));
+				// Restarting resets the reason
+				assert_ok!(PhalaMining::start_mining(2, 3000 * DOLLARS));
+				assert_eq!(PhalaMining::miners(2).unwrap().stop_reason, None);
+				check_invariants();
+			});
+		}
+
 		#[test]
 		fn test_benchmark_update() {
 			let mut b = Benchmark {
@@ -1419,9 +2907,10 @@ pub mod pallet {
 				iterations: 0,
 				mining_start_time: 0,
 				challenge_time_last: 0,
+				session_id: Some(0),
 			};
 			// Normal
-			assert!(b.update(100, 1000, 90).is_ok());
+			assert!(b.update(100, 0, 1000, 90).is_ok());
 			assert_eq!(
 				b,
 				Benchmark {
@@ -1430,10 +2919,11 @@ pub mod pallet {
 					iterations: 1000,
 					mining_start_time: 0,
 					challenge_time_last: 90,
+					session_id: Some(0),
 				}
 			);
 			// Reset counter
-			assert!(b.update(200, 999, 190).is_ok());
+			assert!(b.update(200, 0, 999, 190).is_ok());
 			assert_eq!(
 				b,
 				Benchmark {
@@ -1442,8 +2932,494 @@ pub mod pallet {
 					iterations: 999,
 					mining_start_time: 0,
 					challenge_time_last: 190,
+					session_id: Some(0),
 				}
 			);
+			// Stale heartbeat
+			assert_eq!(
+				b.update(190, 0, 2000, 185),
+				Err(HeartbeatRejectReason::Stale)
+			);
+			// Heartbeat from an earlier session
+			assert_eq!(
+				b.update(300, 1, 2000, 290),
+				Err(HeartbeatRejectReason::SessionMismatch)
+			);
+			assert_eq!(b.iterations, 999);
+			assert_eq!(b.challenge_time_last, 190);
+		}
+
+		#[test]
+		fn test_performance_degradation_alert() {
+			use phala_types::messaging::{DecodedMessage, MessageOrigin, MiningReportEvent, Topic};
+			fn heartbeat(challenge_time: u64, iterations: u64) {
+				assert_ok!(PhalaMining::on_mining_message_received(DecodedMessage::<
+					MiningReportEvent,
+				> {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"phala/mining/report"),
+					payload: MiningReportEvent::Heartbeat {
+						session_id: 0,
+						challenge_block: 2,
+						challenge_time,
+						iterations,
+					},
+				}));
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_ok!(PhalaMining::set_performance_alert_threshold(
+					Origin::root(),
+					Some(Permill::from_percent(80))
+				));
+				// Full window with p_instant = 600
+				let mut iterations = 0;
+				for _ in 0..PERFORMANCE_WINDOW_SIZE {
+					elapse_seconds(100);
+					iterations += 10000;
+					heartbeat(Timestamp::get(), iterations);
+				}
+				assert_eq!(
+					PhalaMining::performance_history(1),
+					vec![600; PERFORMANCE_WINDOW_SIZE]
+				);
+				let _ = take_events();
+				// Degraded to p_instant = 300. The average drops below 480 at the 5th sample.
+				for i in 1..=6 {
+					elapse_seconds(100);
+					iterations += 5000;
+					heartbeat(Timestamp::get(), iterations);
+					let ev = take_events();
+					if i == 5 {
+						assert_eq!(
+							ev,
+							vec![TestEvent::PhalaMining(Event::MinerPerformanceDegraded(
+								1, 450, 600
+							))]
+						);
+					} else {
+						assert_eq!(ev, vec![]);
+					}
+				}
+				let history = PhalaMining::performance_history(1);
+				assert_eq!(history.len(), PERFORMANCE_WINDOW_SIZE);
+				assert_eq!(history[PERFORMANCE_WINDOW_SIZE - 1], 300);
+				// Restart resets the window
+				assert_ok!(PhalaMining::stop_mining(1, StopReason::Voluntary));
+				elapse_cool_down();
+				assert_ok!(PhalaMining::reclaim(1));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_eq!(PhalaMining::performance_history(1), Vec::<u32>::new());
+				check_invariants();
+			});
+		}
+
+		#[test]
+		fn test_request_rebenchmark() {
+			use crate::registry::{RegistryEvent, MIN_REBENCHMARK_INTERVAL};
+			use phala_types::messaging::{
+				DecodedMessage, MessageOrigin, SystemEvent, Topic, WorkerEvent,
+			};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				// Only the operator can request
+				assert_noop!(
+					PhalaMining::request_rebenchmark(Origin::signed(2), worker_pubkey(1)),
+					Error::<Test>::BadSender
+				);
+				// Not allowed when mining or cooling down
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_noop!(
+					PhalaMining::request_rebenchmark(Origin::signed(1), worker_pubkey(1)),
+					Error::<Test>::MinerNotReady
+				);
+				assert_ok!(PhalaMining::stop_mining(1, StopReason::Voluntary));
+				assert_noop!(
+					PhalaMining::request_rebenchmark(Origin::signed(1), worker_pubkey(1)),
+					Error::<Test>::MinerNotReady
+				);
+				elapse_cool_down();
+				assert_ok!(PhalaMining::reclaim(1));
+				// Request in Ready state
+				let _ = take_messages();
+				assert_ok!(PhalaMining::request_rebenchmark(
+					Origin::signed(1),
+					worker_pubkey(1)
+				));
+				let msgs = take_messages();
+				assert_eq!(msgs.len(), 1);
+				assert!(matches!(
+					msgs[0].decode_payload::<SystemEvent>(),
+					Some(SystemEvent::WorkerEvent(e))
+						if e.pubkey == worker_pubkey(1)
+							&& matches!(e.event, WorkerEvent::BenchStart { .. })
+				));
+				// Rate-limited
+				assert_noop!(
+					PhalaMining::request_rebenchmark(Origin::signed(1), worker_pubkey(1)),
+					registry::Error::<Test>::BenchmarkRequestTooFrequent
+				);
+				elapse_seconds(MIN_REBENCHMARK_INTERVAL);
+				assert_ok!(PhalaMining::request_rebenchmark(
+					Origin::signed(1),
+					worker_pubkey(1)
+				));
+				// The report updates the score and the history
+				let now = Timestamp::get();
+				assert_ok!(PhalaRegistry::on_message_received(DecodedMessage::<
+					RegistryEvent,
+				> {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"phala/registry/event"),
+					payload: RegistryEvent::BenchReport {
+						start_time: now - 10_000,
+						iterations: 5000,
+					},
+				}));
+				let worker = registry::Workers::<Test>::get(worker_pubkey(1)).unwrap();
+				assert_eq!(worker.initial_score, Some(3000));
+				assert_eq!(
+					PhalaRegistry::benchmark_history(worker_pubkey(1)),
+					vec![(now / 1000, 3000)]
+				);
+				check_invariants();
+			});
+		}
+
+		#[test]
+		fn test_rejected_heartbeat() {
+			use phala_types::messaging::{DecodedMessage, MessageOrigin, MiningReportEvent, Topic};
+			fn heartbeat(session_id: u32, challenge_time: u64, iterations: u64) -> DispatchResult {
+				PhalaMining::on_mining_message_received(DecodedMessage::<MiningReportEvent> {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"phala/mining/report"),
+					payload: MiningReportEvent::Heartbeat {
+						session_id,
+						challenge_block: 2,
+						challenge_time,
+						iterations,
+					},
+				})
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				elapse_seconds(100);
+				assert_ok!(heartbeat(0, 100_000, 11000));
+				let _ = take_events();
+				// Replayed in the same block: rejected without panic
+				assert_ok!(heartbeat(0, 100_000, 11000));
+				// From an earlier session
+				elapse_seconds(100);
+				assert_ok!(heartbeat(5, 200_000, 12000));
+				assert_eq!(
+					take_events(),
+					vec![
+						TestEvent::PhalaMining(Event::MinerHeartbeatRejected(
+							1,
+							worker_pubkey(1),
+							HeartbeatRejectReason::Stale
+						)),
+						TestEvent::PhalaMining(Event::MinerHeartbeatRejected(
+							1,
+							worker_pubkey(1),
+							HeartbeatRejectReason::SessionMismatch
+						)),
+					]
+				);
+				assert_eq!(PhalaMining::rejected_heartbeats(1), 2);
+				let miner = PhalaMining::miners(1).unwrap();
+				assert_eq!(miner.benchmark.iterations, 11000);
+				assert_eq!(miner.benchmark.challenge_time_last, 100);
+			});
+		}
+
+		#[test]
+		fn test_minimum_pruntime_version() {
+			use phala_types::messaging::{DecodedMessage, MessageOrigin, MiningReportEvent, Topic};
+			fn heartbeat(challenge_time: u64, iterations: u64) -> DispatchResult {
+				PhalaMining::on_mining_message_received(DecodedMessage::<MiningReportEvent> {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"phala/mining/report"),
+					payload: MiningReportEvent::Heartbeat {
+						session_id: 0,
+						challenge_block: 2,
+						challenge_time,
+						iterations,
+					},
+				})
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(2), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::bind(2, worker_pubkey(2)));
+				// Workers are registered with version 0
+				assert_ok!(PhalaRegistry::set_minimum_pruntime_version(
+					Origin::root(),
+					Some((1, 1000))
+				));
+				// Before the deadline, the outdated worker can still start and is warned once
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				let _ = take_messages();
+				let _ = take_events();
+				elapse_seconds(100);
+				assert_ok!(heartbeat(100_000, 1000));
+				elapse_seconds(100);
+				assert_ok!(heartbeat(200_000, 2000));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaMining(Event::MinerPRuntimeOutdated(
+						1,
+						worker_pubkey(1),
+						1
+					))]
+				);
+				let warnings = take_messages()
+					.iter()
+					.filter(|m| {
+						matches!(
+							m.decode_payload::<SystemEvent>(),
+							Some(SystemEvent::WorkerEvent(e)) if matches!(
+								e.event,
+								WorkerEvent::PRuntimeUpgradeRequired {
+									min_version: 1,
+									deadline: 1000
+								}
+							)
+						)
+					})
+					.count();
+				assert_eq!(warnings, 1);
+				// After the deadline, the outdated miner is stopped and can't start
+				elapse_seconds(1000);
+				assert_noop!(
+					PhalaMining::start_mining(2, 3000 * DOLLARS),
+					Error::<Test>::PRuntimeVersionTooLow
+				);
+				assert_ok!(heartbeat(1_200_000, 3000));
+				let miner = PhalaMining::miners(1).unwrap();
+				assert_eq!(miner.state, MinerState::MiningCoolingDown);
+				assert_eq!(miner.stop_reason, Some(StopReason::OutdatedPRuntime));
+				// Upgraded workers are fine
+				registry::Workers::<Test>::mutate(worker_pubkey(2), |info| {
+					info.as_mut().unwrap().runtime_version = 1;
+				});
+				assert_ok!(PhalaMining::start_mining(2, 3000 * DOLLARS));
+			});
+		}
+
+		#[test]
+		fn test_sweep_outdated_pruntime() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(3);
+				for i in 1..=3 {
+					PhalaRegistry::internal_set_benchmark(&worker_pubkey(i), Some(600));
+					assert_ok!(PhalaMining::bind(i as _, worker_pubkey(i)));
+					assert_ok!(PhalaMining::start_mining(i as _, 3000 * DOLLARS));
+				}
+				// Workers are registered with version 0
+				registry::Workers::<Test>::mutate(worker_pubkey(3), |info| {
+					info.as_mut().unwrap().runtime_version = 1;
+				});
+				// Nothing to check without a minimum version
+				PhalaMining::on_idle(1, Weight::MAX);
+				assert_eq!(MinerSweepCursor::<Test>::get(), None);
+				assert_ok!(PhalaRegistry::set_minimum_pruntime_version(
+					Origin::root(),
+					Some((1, 1000))
+				));
+				let _ = take_events();
+				// Outdated miners are warned even without heartbeats
+				PhalaMining::on_idle(1, Weight::MAX);
+				let mut warned: Vec<_> = take_events()
+					.into_iter()
+					.filter_map(|e| match e {
+						TestEvent::PhalaMining(Event::MinerPRuntimeOutdated(miner, _, 1)) => {
+							Some(miner)
+						}
+						_ => None,
+					})
+					.collect();
+				warned.sort();
+				assert_eq!(warned, vec![1, 2]);
+				// And stopped after the deadline
+				elapse_seconds(1000);
+				PhalaMining::on_idle(1, Weight::MAX);
+				assert_eq!(MinerSweepCursor::<Test>::get(), None);
+				for miner in [1, 2] {
+					let info = PhalaMining::miners(miner).unwrap();
+					assert_eq!(info.state, MinerState::MiningCoolingDown);
+					assert_eq!(info.stop_reason, Some(StopReason::OutdatedPRuntime));
+				}
+				assert_eq!(
+					PhalaMining::miners(3).unwrap().state,
+					MinerState::MiningIdle
+				);
+			});
+		}
+
+		#[test]
+		fn test_attestation_refresh() {
+			use phala_types::messaging::{DecodedMessage, MessageOrigin, MiningReportEvent, Topic};
+			fn heartbeat(challenge_time: u64, iterations: u64) -> DispatchResult {
+				PhalaMining::on_mining_message_received(DecodedMessage::<MiningReportEvent> {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"phala/mining/report"),
+					payload: MiningReportEvent::Heartbeat {
+						session_id: 0,
+						challenge_block: 2,
+						challenge_time,
+						iterations,
+					},
+				})
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(2), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				assert_ok!(PhalaMining::bind(2, worker_pubkey(2)));
+				// Workers are attested at 0
+				assert_ok!(PhalaRegistry::set_attestation_refresh_policy(
+					Origin::root(),
+					Some((1000, 100))
+				));
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_noop!(
+					PhalaRegistry::mark_worker_stale(Origin::signed(1), worker_pubkey(2)),
+					registry::Error::<Test>::AttestationNotStale
+				);
+				// Expired: marked stale but still mining in the grace period
+				elapse_seconds(1000);
+				let _ = take_events();
+				assert_ok!(heartbeat(1_000_000, 1000));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaRegistry(
+						registry::Event::WorkerAttestationStale(worker_pubkey(1), 1000)
+					)]
+				);
+				assert_eq!(PhalaRegistry::stale_workers(worker_pubkey(1)), Some(1000));
+				assert_eq!(
+					PhalaMining::miners(1).unwrap().state,
+					MinerState::MiningIdle
+				);
+				assert_noop!(
+					PhalaMining::start_mining(2, 3000 * DOLLARS),
+					Error::<Test>::AttestationStale
+				);
+				assert_ok!(PhalaRegistry::mark_worker_stale(
+					Origin::signed(1),
+					worker_pubkey(2)
+				));
+				assert_eq!(PhalaRegistry::stale_workers(worker_pubkey(2)), Some(1000));
+				// Stopped after the grace period
+				elapse_seconds(100);
+				assert_ok!(heartbeat(1_100_000, 2000));
+				let miner = PhalaMining::miners(1).unwrap();
+				assert_eq!(miner.state, MinerState::MiningCoolingDown);
+				assert_eq!(miner.stop_reason, Some(StopReason::StaleAttestation));
+				// Also without heartbeats
+				registry::Workers::<Test>::mutate(worker_pubkey(2), |info| {
+					info.as_mut().unwrap().last_updated = 1100;
+				});
+				registry::StaleWorkers::<Test>::remove(worker_pubkey(2));
+				assert_ok!(PhalaMining::start_mining(2, 3000 * DOLLARS));
+				elapse_seconds(1100);
+				PhalaMining::on_idle(1, Weight::MAX);
+				let miner = PhalaMining::miners(2).unwrap();
+				assert_eq!(miner.state, MinerState::MiningCoolingDown);
+				assert_eq!(miner.stop_reason, Some(StopReason::StaleAttestation));
+			});
+		}
+
+		#[test]
+		fn test_migrate_from_v4() {
+			/// `MinerInfo` at storage version 4
+			#[derive(Encode)]
+			struct MinerInfoV4 {
+				state: MinerState,
+				ve: u128,
+				v: u128,
+				v_updated_at: u64,
+				benchmark: Benchmark,
+				cool_down_start: u64,
+				stats: MinerStats,
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				for i in 1..=2 {
+					assert_ok!(PhalaMining::bind(i, worker_pubkey(i as u8)));
+				}
+				let put_v4 = |miner: u64, state: MinerState, cool_down_start: u64| {
+					let info = MinerInfoV4 {
+						state,
+						ve: fp!(1000).to_bits(),
+						v: fp!(1000).to_bits(),
+						v_updated_at: 0,
+						benchmark: Benchmark {
+							p_init: 100,
+							p_instant: 100,
+							iterations: 0,
+							mining_start_time: 0,
+							challenge_time_last: 0,
+							session_id: None,
+						},
+						cool_down_start,
+						stats: Default::default(),
+					};
+					frame_support::storage::unhashed::put(
+						&Miners::<Test>::hashed_key_for(&miner),
+						&info,
+					);
+				};
+				put_v4(1, MinerState::MiningIdle, 0);
+				put_v4(2, MinerState::MiningCoolingDown, 100);
+				StorageVersion::new(4).put::<PhalaMining>();
+
+				PhalaMining::on_runtime_upgrade();
+				assert_eq!(PhalaMining::on_chain_storage_version(), STORAGE_VERSION);
+				// All the miners are converted before being counted and enqueued
+				let miner = PhalaMining::miners(1).expect("Migrated; qed.");
+				assert_eq!(miner.state, MinerState::MiningIdle);
+				assert_eq!(miner.stop_reason, None);
+				let level = registry::Workers::<Test>::get(worker_pubkey(1))
+					.unwrap()
+					.confidence_level;
+				assert_eq!(OnlineMinersByLevel::<Test>::get(level), 1);
+				let (head, tail) = CoolDownQueueRange::<Test>::get();
+				assert_eq!(tail - head, 1);
+				assert_eq!(CoolDownQueue::<Test>::get(head), Some((2, 100)));
+			});
+		}
+
+		#[test]
+		fn test_migrate_halving_period() {
+			new_test_ext().execute_with(|| {
+				MiningStartBlock::<Test>::put(10);
+				MiningHalvingInterval::<Test>::put(100);
+				// Halved at block 109, 209 and 309
+				System::set_block_number(315);
+				StorageVersion::new(7).put::<PhalaMining>();
+				PhalaMining::on_runtime_upgrade();
+				assert_eq!(PhalaMining::halving_period(), 3);
+			});
 		}
 	}
 }
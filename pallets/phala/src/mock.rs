@@ -1,6 +1,9 @@
 use crate::{
-	attestation::{Attestation, AttestationValidator, Error as AttestationError, IasFields},
-	mining, mq, ott, registry, stakepool,
+	attestation::{
+		Attestation, AttestationValidator, ConfidentialReport, Error as AttestationError,
+		TrustStore,
+	},
+	fat, mining, mq, ott, registry, stakepool,
 };
 
 use frame_support::{
@@ -39,6 +42,7 @@ frame_support::construct_runtime!(
 		PhalaMining: mining::{Pallet, Event<T>, Storage, Config},
 		PhalaStakePool: stakepool::{Pallet, Event<T>},
 		PhalaOneshotTransfer: ott::{Pallet, Event<T>},
+		PhalaFatContracts: fat::{Pallet, Event<T>},
 	}
 );
 
@@ -56,6 +60,8 @@ parameter_types! {
 	pub const MaxPoolWorkers: u32 = 10;
 	pub const VerifyPRuntime: bool = false;
 	pub const VerifyRelaychainGenesisBlockHash: bool = true;
+	pub const CodeDepositPerByte: Balance = 1 * CENTS;
+	pub const TopicPrefixDeposit: Balance = 1 * DOLLARS;
 }
 impl system::Config for Test {
 	type BaseCallFilter = frame_support::traits::Everything;
@@ -129,6 +135,16 @@ impl registry::Config for Test {
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
 	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type BoundWorkers = PhalaMining;
+	type ExpectedBlockTimeSec = ExpectedBlockTimeSec;
+	type TopicPrefixDeposit = TopicPrefixDeposit;
+}
+
+impl fat::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type CodeDepositPerByte = CodeDepositPerByte;
+	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
 }
 
 impl mining::Config for Test {
@@ -140,6 +156,7 @@ impl mining::Config for Test {
 	type OnReward = PhalaStakePool;
 	type OnUnbound = PhalaStakePool;
 	type OnStopped = PhalaStakePool;
+	type OnReclaimed = PhalaStakePool;
 	type OnTreasurySettled = ();
 	type UpdateTokenomicOrigin = frame_system::EnsureRoot<Self::AccountId>;
 }
@@ -161,29 +178,52 @@ impl ott::Config for Test {
 	type Currency = Balances;
 }
 
+/// Accepts any SGX attestation. The evidence of a `Generic` attestation is taken as the
+/// measurement, and must be in the allow list passed in.
 pub struct MockValidator;
 impl AttestationValidator for MockValidator {
 	fn validate(
-		_attestation: &Attestation,
+		attestation: &Attestation,
 		_user_data_hash: &[u8; 32],
 		_now: u64,
 		_verify_pruntime: bool,
-		_pruntime_allowlist: Vec<Vec<u8>>,
-	) -> Result<IasFields, AttestationError> {
-		Ok(IasFields {
-			mr_enclave: [0u8; 32],
-			mr_signer: [0u8; 32],
-			isv_prod_id: [0u8; 2],
-			isv_svn: [0u8; 2],
+		pruntime_allowlist: Vec<Vec<u8>>,
+		_trust_store: &TrustStore,
+	) -> Result<ConfidentialReport, AttestationError> {
+		let measurement = match attestation {
+			Attestation::Generic { evidence, .. } => {
+				if !pruntime_allowlist.contains(evidence) {
+					return Err(AttestationError::PRuntimeRejected);
+				}
+				evidence.clone()
+			}
+			_ => Vec::new(),
+		};
+		Ok(ConfidentialReport {
+			tee_type: attestation.tee_type(),
+			measurement,
 			report_data: [0u8; 64],
 			confidence_level: 128u8,
 		})
 	}
 }
 
+/// The test externalities checking the storage invariants after each `execute_with`
+pub struct TestExt(sp_io::TestExternalities);
+
+impl TestExt {
+	pub fn execute_with<R>(&mut self, execute: impl FnOnce() -> R) -> R {
+		self.0.execute_with(|| {
+			let r = execute();
+			check_invariants();
+			r
+		})
+	}
+}
+
 // This function basically just builds a genesis storage key/value store according to
 // our desired mockup.
-pub fn new_test_ext() -> sp_io::TestExternalities {
+pub fn new_test_ext() -> TestExt {
 	let mut t = system::GenesisConfig::default()
 		.build_storage::<Test>()
 		.unwrap();
@@ -210,7 +250,7 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 	.unwrap();
 	GenesisBuild::<Test>::assimilate_storage(&crate::mining::GenesisConfig::default(), &mut t)
 		.unwrap();
-	sp_io::TestExternalities::new(t)
+	TestExt(sp_io::TestExternalities::new(t))
 }
 
 pub fn set_block_1() {
@@ -289,6 +329,12 @@ pub fn setup_workers_linked_operators(n: u8) {
 	}
 }
 
+/// Checks the storage invariants of the mining and stake pool pallets
+pub fn check_invariants() {
+	PhalaMining::check_invariants().expect("Mining invariants broken");
+	PhalaStakePool::check_invariants().expect("Stake pool invariants broken");
+}
+
 pub fn elapse_seconds(sec: u64) {
 	let now = Timestamp::get();
 	Timestamp::set_timestamp(now + sec * 1000);
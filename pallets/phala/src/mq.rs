@@ -49,6 +49,8 @@ pub mod pallet {
 		BadSender,
 		BadSequence,
 		BadDestination,
+		/// The destination is under a topic prefix claimed by others.
+		TopicPrefixNotOwned,
 	}
 
 	#[pallet::call]
@@ -58,7 +60,15 @@ pub mod pallet {
 		T::AccountId: IntoH256,
 	{
 		/// Syncs an unverified offchain message to the message queue
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get().reads_writes(
+					crate::registry::Pallet::<T>::topic_prefix_lookups(
+						signed_message.message.destination.path()
+					),
+					1
+				)
+		)]
 		pub fn sync_offchain_message(
 			origin: OriginFor<T>,
 			signed_message: SignedMessage,
@@ -74,6 +84,13 @@ pub mod pallet {
 				signed_message.message.destination.is_valid(),
 				Error::<T>::BadDestination
 			);
+			ensure!(
+				crate::registry::Pallet::<T>::can_publish(
+					signed_message.message.destination.path(),
+					sender
+				),
+				Error::<T>::TopicPrefixNotOwned
+			);
 
 			// Check ingress sequence
 			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
@@ -92,7 +109,13 @@ pub mod pallet {
 
 		// Messaging API for end user.
 		// TODO.kevin: confirm the weight
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get().reads_writes(
+					crate::registry::Pallet::<T>::topic_prefix_lookups(destination),
+					1
+				)
+		)]
 		pub fn push_message(
 			origin: OriginFor<T>,
 			destination: Vec<u8>,
@@ -100,6 +123,10 @@ pub mod pallet {
 		) -> DispatchResult {
 			let origin = ensure_signed(origin)?;
 			let sender = MessageOrigin::AccountId(origin.into_h256());
+			ensure!(
+				crate::registry::Pallet::<T>::can_publish(&destination, &sender),
+				Error::<T>::TopicPrefixNotOwned
+			);
 			let message = Message::new(sender, destination, payload);
 			Self::dispatch_message(message);
 			Ok(())
@@ -7,24 +7,26 @@ pub mod pallet {
 	use frame_support::{
 		dispatch::DispatchResult,
 		pallet_prelude::*,
-		traits::{Currency, StorageVersion, UnixTime},
+		traits::{Currency, ReservableCurrency, StorageVersion, UnixTime},
 	};
 	use frame_system::pallet_prelude::*;
 	use scale_info::TypeInfo;
 	use sp_core::H256;
-	use sp_runtime::SaturatedConversion;
+	use sp_runtime::{traits::Zero, SaturatedConversion};
 	use sp_std::prelude::*;
 	use sp_std::{convert::TryFrom, vec};
 
-	use crate::attestation::{AttestationValidator, Error as AttestationError};
-	use crate::mq::MessageOriginInfo;
+	use crate::attestation::{self, AttestationValidator, Error as AttestationError, TrustStore};
+	use crate::mq::{IntoH256, MessageOriginInfo};
 	// Re-export
-	pub use crate::attestation::{Attestation, IasValidator};
+	pub use crate::attestation::{
+		Attestation, ConfidentialReport, DcapCollateral, DcapValidator, IasValidator, TeeType,
+	};
 
 	use phala_types::{
 		messaging::{
 			self, bind_topic, ContractId, DecodedMessage, GatekeeperChange, GatekeeperLaunch,
-			MessageOrigin, SignedMessage, SystemEvent, WorkerEvent,
+			GatekeeperUnregistration, MessageOrigin, SignedMessage, SystemEvent, WorkerEvent,
 		},
 		ContractPublicKey, EcdhPublicKey, MasterPublicKey, WorkerPublicKey, WorkerRegistrationInfo,
 	};
@@ -32,8 +34,17 @@ pub mod pallet {
 	bind_topic!(RegistryEvent, b"^phala/registry/event");
 	#[derive(Encode, Decode, TypeInfo, Clone, Debug)]
 	pub enum RegistryEvent {
-		BenchReport { start_time: u64, iterations: u64 },
-		MasterPubkey { master_pubkey: MasterPublicKey },
+		BenchReport {
+			start_time: u64,
+			iterations: u64,
+		},
+		MasterPubkey {
+			master_pubkey: MasterPublicKey,
+		},
+		RotatedMasterPubkey {
+			rotation_id: u64,
+			master_pubkey: MasterPublicKey,
+		},
 	}
 
 	#[pallet::config]
@@ -41,7 +52,7 @@ pub mod pallet {
 		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
 
 		/// The currency in which fees are paid and contract balances are held.
-		type Currency: Currency<Self::AccountId>;
+		type Currency: ReservableCurrency<Self::AccountId>;
 
 		type UnixTime: UnixTime;
 		type AttestationValidator: AttestationValidator;
@@ -56,9 +67,47 @@ pub mod pallet {
 
 		/// Origin used to administer the pallet
 		type GovernanceOrigin: EnsureOrigin<Self::Origin>;
+
+		/// Tells if a worker is still bound to a miner
+		type BoundWorkers: IsWorkerBound;
+
+		/// The expected average block time, to convert `BenchmarkDuration` to sec
+		#[pallet::constant]
+		type ExpectedBlockTimeSec: Get<u32>;
+
+		/// The deposit reserved from the claimer of a topic prefix
+		#[pallet::constant]
+		type TopicPrefixDeposit: Get<BalanceOf<Self>>;
+	}
+
+	pub trait IsWorkerBound {
+		/// Returns if the worker is bound to a miner, so it can't be deregistered
+		fn is_worker_bound(_worker: &WorkerPublicKey) -> bool {
+			false
+		}
 	}
 
-	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+	impl IsWorkerBound for () {}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
+
+	/// The minimal interval between two operator-requested benchmarks of a worker (in sec)
+	pub const MIN_REBENCHMARK_INTERVAL: u64 = 24 * 3600;
+	/// The max number of benchmark scores kept in the history of a worker
+	const MAX_BENCHMARK_HISTORY: usize = 10;
+	/// The default cap of the benchmark score
+	pub const DEFAULT_MAX_BENCHMARK_SCORE: u32 = 6000;
+	/// The default minimal duration (in sec) of a benchmark report
+	pub const DEFAULT_MIN_BENCHMARK_DURATION: u64 = 1;
+	/// The max length of a claimed topic prefix
+	pub const MAX_TOPIC_PREFIX_LEN: usize = 128;
+	/// The max length of the pubkey of a claimed topic prefix (an uncompressed ECDSA pubkey)
+	pub const MAX_TOPIC_PUBKEY_LEN: usize = 65;
+	/// The topic prefix reserved for the system messages
+	const RESERVED_TOPIC_PREFIX: &[u8] = b"phala/";
+	/// How long (in sec) the replaced master key is still accepted after a rotation, unless
+	/// revoked by `revoke_previous_master_pubkey`
+	pub const MASTER_KEY_TRANSITION_WINDOW: u64 = 7 * 24 * 3600;
 
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
@@ -70,10 +119,40 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type Gatekeeper<T: Config> = StorageValue<_, Vec<WorkerPublicKey>, ValueQuery>;
 
+	/// The number of times each gatekeeper has been added or removed
+	///
+	/// Signed into `GatekeeperUnregistration` to prevent replaying the signature.
+	#[pallet::storage]
+	pub type GatekeeperGeneration<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, u32, ValueQuery>;
+
 	/// Gatekeeper master pubkey
 	#[pallet::storage]
 	pub type GatekeeperMasterPubkey<T: Config> = StorageValue<_, MasterPublicKey>;
 
+	/// The id of the last master key rotation
+	#[pallet::storage]
+	pub type MasterKeyRotationId<T: Config> = StorageValue<_, u64, ValueQuery>;
+
+	/// The id of the ongoing master key rotation, if any
+	#[pallet::storage]
+	pub type PendingMasterKeyRotation<T: Config> = StorageValue<_, u64>;
+
+	/// The new master pubkeys reported by the gatekeepers in the ongoing rotation
+	#[pallet::storage]
+	pub type MasterKeyRotationVotes<T: Config> =
+		StorageValue<_, Vec<(WorkerPublicKey, MasterPublicKey)>, ValueQuery>;
+
+	/// The replaced master pubkey and the time (in sec) until which it's still accepted
+	#[pallet::storage]
+	pub type PreviousMasterPubkey<T: Config> = StorageValue<_, (MasterPublicKey, u64)>;
+
+	/// All the master pubkeys ever used as `(rotation_id, pubkey, since_sec)`, oldest first
+	#[pallet::storage]
+	#[pallet::getter(fn master_pubkey_history)]
+	pub type MasterPubkeyHistory<T: Config> =
+		StorageValue<_, Vec<(u64, MasterPublicKey, u64)>, ValueQuery>;
+
 	/// Mapping from worker pubkey to WorkerInfo
 	#[pallet::storage]
 	pub type Workers<T: Config> =
@@ -87,16 +166,73 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type TopicKey<T> = StorageMap<_, Blake2_128Concat, Vec<u8>, Vec<u8>>;
 
+	/// The owners of the claimed topic prefixes
+	///
+	/// Only the owner of the longest claimed prefix of a topic can push messages to it. The pubkey
+	/// of a claimed prefix is kept in `TopicKey`.
+	#[pallet::storage]
+	#[pallet::getter(fn topic_owners)]
+	pub type TopicOwners<T> = StorageMap<_, Blake2_128Concat, Vec<u8>, MessageOrigin>;
+
+	/// The claimers of the topic prefixes and the deposits reserved from them
+	///
+	/// The deposit stays with the claimer after the prefix is transferred, and is returned when
+	/// the prefix is released.
+	#[pallet::storage]
+	pub type TopicPrefixDeposits<T: Config> =
+		StorageMap<_, Blake2_128Concat, Vec<u8>, (T::AccountId, BalanceOf<T>)>;
+
 	#[pallet::storage]
 	pub type BenchmarkDuration<T: Config> = StorageValue<_, u32>;
 
+	/// The last time (in sec) a benchmark was requested by the operator of a worker
+	#[pallet::storage]
+	pub type LastBenchmarkRequest<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, u64>;
+
+	/// The recent benchmark scores of a worker as `(timestamp_sec, score)`, oldest first
+	#[pallet::storage]
+	#[pallet::getter(fn benchmark_history)]
+	pub type BenchmarkHistory<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, Vec<(u64, u32)>, ValueQuery>;
+
+	/// The recent raw benchmark reports of a worker, oldest first
+	///
+	/// Kept to recompute the scores after a change of the scoring.
+	#[pallet::storage]
+	#[pallet::getter(fn benchmark_samples)]
+	pub type BenchmarkSamples<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, Vec<BenchmarkSample>, ValueQuery>;
+
+	/// The cap of the benchmark score (`DEFAULT_MAX_BENCHMARK_SCORE` if not set)
+	#[pallet::storage]
+	pub type MaxBenchmarkScore<T: Config> = StorageValue<_, u32>;
+
+	/// The minimal duration (in sec) of a benchmark report (`DEFAULT_MIN_BENCHMARK_DURATION` if
+	/// not set)
+	///
+	/// It should be kept below the time `BenchmarkDuration` blocks take, or all the reports will
+	/// be rejected.
+	#[pallet::storage]
+	pub type MinBenchmarkDuration<T: Config> = StorageValue<_, u64>;
+
 	/// Allow list of pRuntime binary digest
 	///
-	/// Only pRuntime within the list can register.
+	/// Only pRuntime within the list can register. It's the list of SGX, while the other TEE
+	/// types have their lists in `TeePRuntimeAllowList`.
 	#[pallet::storage]
 	#[pallet::getter(fn pruntime_allowlist)]
 	pub type PRuntimeAllowList<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
 
+	/// Allow list of pRuntime measurements of the non-SGX TEE types
+	#[pallet::storage]
+	#[pallet::getter(fn tee_pruntime_allowlist)]
+	pub type TeePRuntimeAllowList<T: Config> =
+		StorageMap<_, Twox64Concat, TeeType, Vec<Vec<u8>>, ValueQuery>;
+
+	/// The TEE type of the workers (`TeeType::Sgx` if absent)
+	#[pallet::storage]
+	pub type WorkerTeeTypes<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, TeeType>;
+
 	/// Allow list of relaychain genesis
 	///
 	/// Only genesis within the list can do register.
@@ -105,10 +241,108 @@ pub mod pallet {
 	pub type RelaychainGenesisBlockHashAllowList<T: Config> =
 		StorageValue<_, Vec<H256>, ValueQuery>;
 
+	/// The minimum pRuntime version and its deadline (in sec)
+	///
+	/// After the deadline, the workers below the minimum version can't start mining, and the
+	/// running miners are stopped.
+	#[pallet::storage]
+	#[pallet::getter(fn minimum_pruntime_version)]
+	pub type MinimumPRuntimeVersion<T: Config> = StorageValue<_, (u32, u64)>;
+
+	/// The max attestation age and the grace period for running miners `(max_age, grace)` (in sec)
+	///
+	/// A worker whose last attestation (`WorkerInfo::last_updated`) is older than `max_age` is
+	/// stale and can't start mining. A running miner is stopped if it doesn't register again
+	/// within `grace` after its attestation became stale.
+	#[pallet::storage]
+	#[pallet::getter(fn attestation_refresh_policy)]
+	pub type AttestationRefreshPolicy<T: Config> = StorageValue<_, (u64, u64)>;
+
+	/// The operator a worker is being handed over to, waiting for the acceptance
+	#[pallet::storage]
+	#[pallet::getter(fn pending_operator)]
+	pub type PendingOperators<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, T::AccountId>;
+
+	/// The workers marked stale, with the time (in sec) their attestations expired
+	#[pallet::storage]
+	#[pallet::getter(fn stale_workers)]
+	pub type StaleWorkers<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, u64>;
+
+	/// The raw `Workers` key of the last worker checked by the `on_idle` sweep
+	///
+	/// The next sweep continues after it. `None` to start over from the first worker.
+	#[pallet::storage]
+	pub type StaleSweepCursor<T> = StorageValue<_, Vec<u8>>;
+
+	/// DER encoded Intel SGX root CA certificates accepted by DCAP attestations
+	///
+	/// The PCK certificate chains and the collateral signing chains must chain up to one of them.
+	#[pallet::storage]
+	#[pallet::getter(fn dcap_root_certs)]
+	pub type DcapRootCerts<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
+	/// DER encoded root CA certificates of the IAS report signing certificate
+	///
+	/// The built-in roots are used if empty.
+	#[pallet::storage]
+	#[pallet::getter(fn ias_root_certs)]
+	pub type IasRootCerts<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
+	/// DER encoded certificate revocation lists
+	///
+	/// The attestations signed by (or chained through) a revoked certificate are rejected. The
+	/// lists are trusted as set by the governance, so their signatures are not checked.
+	#[pallet::storage]
+	#[pallet::getter(fn cert_revocation_lists)]
+	pub type CertRevocationLists<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
 		GatekeeperAdded(WorkerPublicKey),
+		/// A new benchmark is requested by the operator. \[worker\]
+		BenchmarkRequested(WorkerPublicKey),
+		/// A gatekeeper is removed from the gatekeeper set. \[gatekeeper\]
+		GatekeeperRemoved(WorkerPublicKey),
+		/// A master key rotation is requested. \[rotation_id\]
+		MasterKeyRotationStarted(u64),
+		/// The master pubkey is replaced after the gatekeepers reached a quorum. \[rotation_id, master_pubkey\]
+		MasterPubkeyRotated(u64, MasterPublicKey),
+		/// The minimum pRuntime version changed. \[requirement\]
+		MinimumPRuntimeVersionChanged(Option<(u32, u64)>),
+		/// The attestation refresh policy changed. \[policy\]
+		AttestationRefreshPolicyChanged(Option<(u64, u64)>),
+		/// The attestation of a worker expired. \[worker, expired_at\]
+		WorkerAttestationStale(WorkerPublicKey, u64),
+		/// A stale worker refreshed its attestation. \[worker\]
+		WorkerAttestationRefreshed(WorkerPublicKey),
+		/// The operator proposed to hand the worker over. \[worker, new_operator\]
+		WorkerOperatorTransferProposed(WorkerPublicKey, Option<T::AccountId>),
+		/// The operator of the worker changed. \[worker, operator\]
+		WorkerOperatorChanged(WorkerPublicKey, T::AccountId),
+		/// The worker is deregistered by its operator. \[worker\]
+		WorkerDeregistered(WorkerPublicKey),
+		/// The benchmark scoring changed. \[max_score, min_duration\]
+		BenchmarkScoringChanged(Option<u32>, Option<u64>),
+		/// The score of a worker is recomputed from its last benchmark report. \[worker, score\]
+		BenchmarkRescored(WorkerPublicKey, u32),
+		/// A topic prefix is claimed. \[prefix, owner\]
+		TopicPrefixClaimed(Vec<u8>, MessageOrigin),
+		/// The pubkey of a topic prefix is set or removed. \[prefix\]
+		TopicKeyChanged(Vec<u8>),
+		/// A topic prefix is transferred to a new owner. \[prefix, owner\]
+		TopicPrefixTransferred(Vec<u8>, MessageOrigin),
+		/// A topic prefix is released. \[prefix\]
+		TopicPrefixReleased(Vec<u8>),
+		/// The ongoing master key rotation is cancelled. \[rotation_id\]
+		MasterKeyRotationCancelled(u64),
+		/// The IAS root CA certificates changed. \[num_certs\]
+		IasRootCertsChanged(u32),
+		/// The certificate revocation lists changed. \[num_crls\]
+		CertRevocationListsChanged(u32),
+		/// The replaced master pubkey is no longer accepted. \[master_pubkey\]
+		PreviousMasterPubkeyRevoked(MasterPublicKey),
 	}
 
 	#[pallet::error]
@@ -127,17 +361,42 @@ pub mod pallet {
 		BadIASReport,
 		OutdatedIASReport,
 		UnknownQuoteBodyFormat,
+		// DCAP related
+		UnsupportedAttestationType,
+		UnsupportedQuoteFormat,
+		MalformedQuote,
+		InvalidPckCertChain,
+		InvalidQuoteSignature,
+		InvalidQeReport,
+		InvalidCollateral,
+		OutdatedCollateral,
+		TcbMismatch,
+		TcbRevoked,
+		InvalidRootCert,
+		// Trust store related
+		CertRevoked,
+		InvalidCrl,
 		// Report validation
 		InvalidRuntimeInfoHash,
 		InvalidRuntimeInfo,
 		InvalidInput,
 		InvalidBenchReport,
+		/// The benchmark report covers less than the minimal duration.
+		BenchReportTooShort,
+		InvalidBenchmarkScoring,
+		BenchmarkSampleNotFound,
 		WorkerNotFound,
+		/// The last benchmark request is within `MIN_REBENCHMARK_INTERVAL`.
+		BenchmarkRequestTooFrequent,
 		// Gatekeeper related
 		InvalidGatekeeper,
 		InvalidMasterPubkey,
 		MasterKeyMismatch,
 		MasterKeyUninitialized,
+		MasterKeyRotationInProgress,
+		InvalidMasterKeyRotation,
+		/// The gatekeeper set can't become empty.
+		CannotRemoveLastGatekeeper,
 		// GenesisBlockHash related
 		GenesisBlockHashRejected,
 		GenesisBlockHashAlreadyExists,
@@ -146,16 +405,47 @@ pub mod pallet {
 		PRuntimeRejected,
 		PRuntimeAlreadyExists,
 		PRuntimeNotFound,
+		// Attestation refresh related
+		AttestationNotStale,
+		// Worker management related
+		NotWorkerOperator,
+		NoPendingOperator,
+		WorkerStillBound,
+		CannotDeregisterGatekeeper,
+		// Topic namespace related
+		InvalidTopicPrefix,
+		TopicPrefixClaimed,
+		TopicPrefixNotFound,
+		NotTopicPrefixOwner,
+		InvalidTopicOwner,
+		/// The prefix has a pubkey registered by the governance.
+		TopicKeyRegistered,
+		TopicKeyTooLong,
+		NoPreviousMasterPubkey,
 	}
 
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
 	#[pallet::call]
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config,
+		T::AccountId: IntoH256,
 	{
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		/// Sets the benchmark duration in blocks
+		///
+		/// It can't be shorter than the minimal report duration set by `set_benchmark_scoring`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
 		pub fn force_set_benchmark_duration(origin: OriginFor<T>, value: u32) -> DispatchResult {
 			ensure_root(origin)?;
+			if let Some(min_duration) = MinBenchmarkDuration::<T>::get() {
+				let expected_sec = value as u64 * T::ExpectedBlockTimeSec::get() as u64;
+				ensure!(
+					min_duration <= expected_sec,
+					Error::<T>::InvalidBenchmarkScoring
+				);
+			}
 			BenchmarkDuration::<T>::put(value);
 			Ok(())
 		}
@@ -184,6 +474,7 @@ pub mod pallet {
 				pubkey,
 				WorkerEvent::Registered(messaging::WorkerInfo {
 					confidence_level: worker_info.confidence_level,
+					tee_type: Self::worker_tee_type(&pubkey),
 				}),
 			));
 			Ok(())
@@ -201,6 +492,105 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Claims a topic prefix, so only the caller can push messages to the topics under it
+		///
+		/// The prefix must end with `/`, and can't be under the system namespace or a prefix
+		/// claimed by others. `TopicPrefixDeposit` is reserved from the caller until the prefix is
+		/// released.
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get()
+					.reads_writes(prefix.len().min(MAX_TOPIC_PREFIX_LEN) as u64 + 2, 3)
+		)]
+		pub fn claim_topic_prefix(origin: OriginFor<T>, prefix: Vec<u8>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let owner = MessageOrigin::AccountId(who.clone().into_h256());
+			ensure!(
+				Self::is_valid_topic_prefix(&prefix),
+				Error::<T>::InvalidTopicPrefix
+			);
+			ensure!(
+				!TopicOwners::<T>::contains_key(&prefix),
+				Error::<T>::TopicPrefixClaimed
+			);
+			if let Some(parent_owner) = Self::topic_prefix_owner(&prefix) {
+				ensure!(parent_owner == owner, Error::<T>::TopicPrefixClaimed);
+			}
+			// Not to take over the pubkey set by `force_register_topic_pubkey`
+			ensure!(
+				!TopicKey::<T>::contains_key(&prefix),
+				Error::<T>::TopicKeyRegistered
+			);
+			let deposit = T::TopicPrefixDeposit::get();
+			T::Currency::reserve(&who, deposit)?;
+			TopicPrefixDeposits::<T>::insert(&prefix, (who, deposit));
+			TopicOwners::<T>::insert(&prefix, &owner);
+			Self::deposit_event(Event::<T>::TopicPrefixClaimed(prefix, owner));
+			Ok(())
+		}
+
+		/// Sets or removes the pubkey of a claimed topic prefix
+		#[pallet::weight(0)]
+		pub fn set_topic_prefix_key(
+			origin: OriginFor<T>,
+			prefix: Vec<u8>,
+			pubkey: Option<Vec<u8>>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_topic_prefix_owner(&prefix, who)?;
+			ensure!(
+				pubkey.as_ref().map_or(0, |k| k.len()) <= MAX_TOPIC_PUBKEY_LEN,
+				Error::<T>::TopicKeyTooLong
+			);
+			TopicKey::<T>::set(&prefix, pubkey);
+			Self::deposit_event(Event::<T>::TopicKeyChanged(prefix));
+			Ok(())
+		}
+
+		/// Transfers a claimed topic prefix to another account or a contract
+		#[pallet::weight(0)]
+		pub fn transfer_topic_prefix(
+			origin: OriginFor<T>,
+			prefix: Vec<u8>,
+			new_owner: MessageOrigin,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_topic_prefix_owner(&prefix, who)?;
+			ensure!(
+				matches!(
+					new_owner,
+					MessageOrigin::AccountId(_) | MessageOrigin::Contract(_)
+				),
+				Error::<T>::InvalidTopicOwner
+			);
+			TopicOwners::<T>::insert(&prefix, &new_owner);
+			Self::deposit_event(Event::<T>::TopicPrefixTransferred(prefix, new_owner));
+			Ok(())
+		}
+
+		/// Releases a claimed topic prefix and its pubkey
+		///
+		/// Can be called by the owner, or by the governance to reclaim any prefix (e.g. the ones
+		/// owned by contracts). The deposit is returned to the claimer.
+		#[pallet::weight(0)]
+		pub fn release_topic_prefix(origin: OriginFor<T>, prefix: Vec<u8>) -> DispatchResult {
+			if let Err(origin) = T::GovernanceOrigin::try_origin(origin) {
+				let who = ensure_signed(origin)?;
+				Self::ensure_topic_prefix_owner(&prefix, who)?;
+			}
+			ensure!(
+				TopicOwners::<T>::contains_key(&prefix),
+				Error::<T>::TopicPrefixNotFound
+			);
+			TopicOwners::<T>::remove(&prefix);
+			TopicKey::<T>::remove(&prefix);
+			if let Some((claimer, deposit)) = TopicPrefixDeposits::<T>::take(&prefix) {
+				T::Currency::unreserve(&claimer, deposit);
+			}
+			Self::deposit_event(Event::<T>::TopicPrefixReleased(prefix));
+			Ok(())
+		}
+
 		/// Register a gatekeeper.
 		///
 		/// Must be called by the Root origin.
@@ -225,6 +615,7 @@ pub mod pallet {
 				gatekeepers.push(gatekeeper);
 				let gatekeeper_count = gatekeepers.len() as u32;
 				Gatekeeper::<T>::put(gatekeepers);
+				GatekeeperGeneration::<T>::mutate(gatekeeper, |g| *g = g.wrapping_add(1));
 
 				if gatekeeper_count == 1 {
 					Self::push_message(GatekeeperLaunch::first_gatekeeper(
@@ -244,16 +635,257 @@ pub mod pallet {
 		/// Unregister a gatekeeper, must be called by gatekeeper himself
 		///
 		/// Requirements:
-		//  1. `sig` is the valid signature of specific unregister message
-		#[allow(unused_variables)]
+		//  1. `sig` is the valid signature of the `GatekeeperUnregistration` payload
 		#[pallet::weight(0)]
 		pub fn unregister_gatekeeper(
 			origin: OriginFor<T>,
 			gatekeeper: WorkerPublicKey,
 			sig: [u8; 64],
 		) -> DispatchResult {
-			// TODO.shelven
-			panic!("unimpleneted");
+			ensure_signed(origin)?;
+			let worker_info =
+				Workers::<T>::try_get(&gatekeeper).or(Err(Error::<T>::WorkerNotFound))?;
+			let payload = GatekeeperUnregistration {
+				pubkey: gatekeeper,
+				last_updated: worker_info.last_updated,
+				generation: GatekeeperGeneration::<T>::get(&gatekeeper),
+				genesis_hash: Self::genesis_hash(),
+			};
+			let sig = sp_core::sr25519::Signature::from_raw(sig);
+			ensure!(
+				sp_io::crypto::sr25519_verify(&sig, &payload.signing_payload(), &gatekeeper),
+				Error::<T>::InvalidSignature
+			);
+			Self::remove_gatekeeper(gatekeeper)
+		}
+
+		/// Force remove a gatekeeper with governance permission
+		#[pallet::weight(0)]
+		pub fn force_unregister_gatekeeper(
+			origin: OriginFor<T>,
+			gatekeeper: WorkerPublicKey,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			Self::remove_gatekeeper(gatekeeper)
+		}
+
+		/// Sets the minimum pRuntime version `(version, deadline_sec)` for mining, or lifts it
+		#[pallet::weight(0)]
+		pub fn set_minimum_pruntime_version(
+			origin: OriginFor<T>,
+			requirement: Option<(u32, u64)>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			MinimumPRuntimeVersion::<T>::set(requirement);
+			Self::deposit_event(Event::<T>::MinimumPRuntimeVersionChanged(requirement));
+			Ok(())
+		}
+
+		/// Sets the benchmark score cap and the minimal report duration (in sec)
+		///
+		/// Setting them to `None` restores the defaults. The minimal duration can't exceed the
+		/// expected time `BenchmarkDuration` blocks take.
+		#[pallet::weight(0)]
+		pub fn set_benchmark_scoring(
+			origin: OriginFor<T>,
+			max_score: Option<u32>,
+			min_duration: Option<u64>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				max_score != Some(0) && min_duration != Some(0),
+				Error::<T>::InvalidBenchmarkScoring
+			);
+			if let (Some(min_duration), Some(blocks)) =
+				(min_duration, BenchmarkDuration::<T>::get())
+			{
+				let expected_sec = blocks as u64 * T::ExpectedBlockTimeSec::get() as u64;
+				ensure!(
+					min_duration <= expected_sec,
+					Error::<T>::InvalidBenchmarkScoring
+				);
+			}
+			MaxBenchmarkScore::<T>::set(max_score);
+			MinBenchmarkDuration::<T>::set(min_duration);
+			Self::deposit_event(Event::<T>::BenchmarkScoringChanged(max_score, min_duration));
+			Ok(())
+		}
+
+		/// Recomputes the score of a worker from its last benchmark report
+		#[pallet::weight(0)]
+		pub fn rescore_worker(origin: OriginFor<T>, worker: WorkerPublicKey) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				Workers::<T>::contains_key(&worker),
+				Error::<T>::WorkerNotFound
+			);
+			let sample = BenchmarkSamples::<T>::get(&worker)
+				.pop()
+				.ok_or(Error::<T>::BenchmarkSampleNotFound)?;
+			let score = Self::benchmark_score(&sample)?;
+			Self::internal_set_benchmark(&worker, Some(score));
+			Self::push_message(SystemEvent::new_worker_event(
+				worker,
+				WorkerEvent::BenchScore(score),
+			));
+			Self::deposit_event(Event::<T>::BenchmarkRescored(worker, score));
+			Ok(())
+		}
+
+		/// Sets the max attestation age and the grace period `(max_age, grace)` (in sec), or lifts
+		/// the limit
+		#[pallet::weight(0)]
+		pub fn set_attestation_refresh_policy(
+			origin: OriginFor<T>,
+			policy: Option<(u64, u64)>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			AttestationRefreshPolicy::<T>::set(policy);
+			Self::deposit_event(Event::<T>::AttestationRefreshPolicyChanged(policy));
+			Ok(())
+		}
+
+		/// Marks a worker stale if its attestation is older than the max age (called by anyone)
+		#[pallet::weight(0)]
+		pub fn mark_worker_stale(origin: OriginFor<T>, worker: WorkerPublicKey) -> DispatchResult {
+			ensure_signed(origin)?;
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			ensure!(
+				Self::update_stale_status(&worker, now)?.is_some(),
+				Error::<T>::AttestationNotStale
+			);
+			Ok(())
+		}
+
+		/// Proposes to hand the worker over to `new_operator`, or cancels it with `None`
+		///
+		/// Must be called by the current operator. It takes effect when the new operator calls
+		/// `accept_worker_operator`. A worker bound to a miner can't be handed over.
+		#[pallet::weight(0)]
+		pub fn transfer_worker_operator(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+			new_operator: Option<T::AccountId>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_worker_operator(&worker, &who)?;
+			ensure!(
+				new_operator.is_none() || !T::BoundWorkers::is_worker_bound(&worker),
+				Error::<T>::WorkerStillBound
+			);
+			PendingOperators::<T>::set(worker, new_operator.clone());
+			Self::deposit_event(Event::<T>::WorkerOperatorTransferProposed(
+				worker,
+				new_operator,
+			));
+			Ok(())
+		}
+
+		/// Accepts the worker handed over by `transfer_worker_operator`
+		#[pallet::weight(0)]
+		pub fn accept_worker_operator(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				PendingOperators::<T>::get(&worker) == Some(who.clone()),
+				Error::<T>::NoPendingOperator
+			);
+			// May be bound after the transfer was proposed
+			ensure!(
+				!T::BoundWorkers::is_worker_bound(&worker),
+				Error::<T>::WorkerStillBound
+			);
+			PendingOperators::<T>::remove(&worker);
+			Workers::<T>::try_mutate(&worker, |info| -> DispatchResult {
+				let info = info.as_mut().ok_or(Error::<T>::WorkerNotFound)?;
+				info.operator = Some(who.clone());
+				Ok(())
+			})?;
+			Self::deposit_event(Event::<T>::WorkerOperatorChanged(worker, who));
+			Ok(())
+		}
+
+		/// Removes an unbound worker from the registry
+		///
+		/// Must be called by the current operator. The worker has to register again to come
+		/// back.
+		#[pallet::weight(0)]
+		pub fn deregister_worker(origin: OriginFor<T>, worker: WorkerPublicKey) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_worker_operator(&worker, &who)?;
+			ensure!(
+				!T::BoundWorkers::is_worker_bound(&worker),
+				Error::<T>::WorkerStillBound
+			);
+			ensure!(
+				!Gatekeeper::<T>::get().contains(&worker),
+				Error::<T>::CannotDeregisterGatekeeper
+			);
+			Workers::<T>::remove(&worker);
+			PendingOperators::<T>::remove(&worker);
+			StaleWorkers::<T>::remove(&worker);
+			WorkerTeeTypes::<T>::remove(&worker);
+			LastBenchmarkRequest::<T>::remove(&worker);
+			BenchmarkHistory::<T>::remove(&worker);
+			BenchmarkSamples::<T>::remove(&worker);
+			Self::push_message(SystemEvent::new_worker_event(
+				worker,
+				WorkerEvent::Deregistered,
+			));
+			Self::deposit_event(Event::<T>::WorkerDeregistered(worker));
+			Ok(())
+		}
+
+		/// Starts a new master key epoch
+		///
+		/// The gatekeepers generate a new master key and report its pubkey. The new pubkey takes
+		/// effect once more than half of the gatekeepers agree on it.
+		#[pallet::weight(0)]
+		pub fn rotate_master_key(origin: OriginFor<T>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				GatekeeperMasterPubkey::<T>::get().is_some(),
+				Error::<T>::MasterKeyUninitialized
+			);
+			ensure!(
+				PendingMasterKeyRotation::<T>::get().is_none(),
+				Error::<T>::MasterKeyRotationInProgress
+			);
+			let rotation_id = MasterKeyRotationId::<T>::get() + 1;
+			MasterKeyRotationId::<T>::put(rotation_id);
+			PendingMasterKeyRotation::<T>::put(rotation_id);
+			MasterKeyRotationVotes::<T>::kill();
+			Self::push_message(GatekeeperLaunch::rotate_master_key(rotation_id));
+			Self::deposit_event(Event::<T>::MasterKeyRotationStarted(rotation_id));
+			Ok(())
+		}
+
+		/// Cancels the ongoing master key rotation
+		///
+		/// Used when the gatekeepers can't reach a quorum on the new master pubkey. The votes are
+		/// dropped and the late reports of the cancelled rotation are rejected.
+		#[pallet::weight(0)]
+		pub fn cancel_master_key_rotation(origin: OriginFor<T>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			let rotation_id = PendingMasterKeyRotation::<T>::take()
+				.ok_or(Error::<T>::InvalidMasterKeyRotation)?;
+			MasterKeyRotationVotes::<T>::kill();
+			Self::deposit_event(Event::<T>::MasterKeyRotationCancelled(rotation_id));
+			Ok(())
+		}
+
+		/// Stops accepting the replaced master pubkey before the end of the transition window
+		///
+		/// Used when the master key is rotated because it was compromised.
+		#[pallet::weight(0)]
+		pub fn revoke_previous_master_pubkey(origin: OriginFor<T>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			let (pubkey, _) =
+				PreviousMasterPubkey::<T>::take().ok_or(Error::<T>::NoPreviousMasterPubkey)?;
+			Self::deposit_event(Event::<T>::PreviousMasterPubkeyRevoked(pubkey));
+			Ok(())
 		}
 
 		/// (called by anyone on behalf of a worker)
@@ -267,14 +899,17 @@ pub mod pallet {
 			// Validate RA report & embedded user data
 			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
 			let runtime_info_hash = crate::hashing::blake2_256(&Encode::encode(&pruntime_info));
+			let tee_type = attestation.tee_type();
 			let fields = T::AttestationValidator::validate(
 				&attestation,
 				&runtime_info_hash,
 				now,
 				T::VerifyPRuntime::get(),
-				PRuntimeAllowList::<T>::get(),
+				Self::pruntime_allowlist_of(tee_type),
+				&Self::trust_store(),
 			)
 			.map_err(Into::<Error<T>>::into)?;
+			ensure!(fields.tee_type == tee_type, Error::<T>::InvalidReport);
 
 			if T::VerifyRelaychainGenesisBlockHash::get() {
 				let genesis_block_hash = pruntime_info.genesis_block_hash;
@@ -287,16 +922,24 @@ pub mod pallet {
 
 			// Update the registry
 			let pubkey = pruntime_info.pubkey;
+			if tee_type == TeeType::Sgx {
+				WorkerTeeTypes::<T>::remove(&pubkey);
+			} else {
+				WorkerTeeTypes::<T>::insert(&pubkey, tee_type);
+			}
+			let was_stale = StaleWorkers::<T>::contains_key(&pubkey);
 			Workers::<T>::mutate(pubkey, |v| {
 				match v {
 					Some(worker_info) => {
 						// Case 1 - Refresh the RA report, optionally update the operator, and redo benchmark
 						worker_info.last_updated = now;
 						worker_info.operator = pruntime_info.operator;
+						worker_info.runtime_version = pruntime_info.version;
 						Self::push_message(SystemEvent::new_worker_event(
 							pubkey,
 							WorkerEvent::Registered(messaging::WorkerInfo {
 								confidence_level: fields.confidence_level,
+								tee_type,
 							}),
 						));
 					}
@@ -316,11 +959,16 @@ pub mod pallet {
 							pubkey,
 							WorkerEvent::Registered(messaging::WorkerInfo {
 								confidence_level: fields.confidence_level,
+								tee_type,
 							}),
 						));
 					}
 				}
 			});
+			// Recheck with the refreshed attestation, in case the policy allows no age at all
+			if Self::update_stale_status(&pubkey, now)?.is_none() && was_stale {
+				Self::deposit_event(Event::<T>::WorkerAttestationRefreshed(pubkey));
+			}
 			// Trigger benchmark anyway
 			let duration = BenchmarkDuration::<T>::get().unwrap_or_default();
 			Self::push_message(SystemEvent::new_worker_event(
@@ -334,49 +982,96 @@ pub mod pallet {
 		#[pallet::weight(0)]
 		pub fn add_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
-
-			let mut allowlist = PRuntimeAllowList::<T>::get();
-			ensure!(
-				!allowlist.contains(&pruntime_hash),
-				Error::<T>::PRuntimeAlreadyExists
-			);
-
-			allowlist.push(pruntime_hash);
-			PRuntimeAllowList::<T>::put(allowlist);
-
-			Ok(())
+			Self::add_pruntime_of(TeeType::Sgx, pruntime_hash)
 		}
 
 		#[pallet::weight(0)]
 		pub fn remove_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
+			Self::remove_pruntime_of(TeeType::Sgx, pruntime_hash)
+		}
 
-			let allowlist = PRuntimeAllowList::<T>::get();
-			ensure!(
-				allowlist.contains(&pruntime_hash),
-				Error::<T>::PRuntimeNotFound
-			);
-
-			let filtered: Vec<_> = allowlist
-				.into_iter()
-				.filter(|h| *h != pruntime_hash)
-				.collect();
-			PRuntimeAllowList::<T>::put(filtered);
-
-			Ok(())
+		/// Registers a pRuntime measurement of a TEE type.
+		#[pallet::weight(0)]
+		pub fn add_tee_pruntime(
+			origin: OriginFor<T>,
+			tee_type: TeeType,
+			measurement: Vec<u8>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			Self::add_pruntime_of(tee_type, measurement)
 		}
 
+		/// Removes a pRuntime measurement of a TEE type.
 		#[pallet::weight(0)]
-		pub fn add_relaychain_genesis_block_hash(
+		pub fn remove_tee_pruntime(
 			origin: OriginFor<T>,
-			genesis_block_hash: H256,
+			tee_type: TeeType,
+			measurement: Vec<u8>,
 		) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
+			Self::remove_pruntime_of(tee_type, measurement)
+		}
 
-			let mut allowlist = RelaychainGenesisBlockHashAllowList::<T>::get();
+		/// Sets the Intel SGX root CA certificates (DER encoded) trusted by DCAP attestations
+		#[pallet::weight(0)]
+		pub fn set_dcap_root_certs(origin: OriginFor<T>, certs: Vec<Vec<u8>>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
 			ensure!(
-				!allowlist.contains(&genesis_block_hash),
-				Error::<T>::GenesisBlockHashAlreadyExists
+				certs.iter().all(|c| attestation::is_valid_root_cert(c)),
+				Error::<T>::InvalidRootCert
+			);
+			DcapRootCerts::<T>::put(certs);
+			Ok(())
+		}
+
+		/// Sets the root CA certificates (DER encoded) of the IAS report signing certificate
+		///
+		/// An empty list restores the built-in roots.
+		#[pallet::weight(0)]
+		pub fn set_ias_root_certs(origin: OriginFor<T>, certs: Vec<Vec<u8>>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				certs.iter().all(|c| attestation::is_valid_root_cert(c)),
+				Error::<T>::InvalidRootCert
+			);
+			let num_certs = certs.len() as u32;
+			IasRootCerts::<T>::put(certs);
+			Self::deposit_event(Event::<T>::IasRootCertsChanged(num_certs));
+			Ok(())
+		}
+
+		/// Sets the certificate revocation lists (DER encoded) checked at registration
+		///
+		/// The lists are trusted as is: their signatures and validity periods are not checked, so
+		/// the governance must only supply lists fetched from Intel and keep them up to date.
+		#[pallet::weight(0)]
+		pub fn set_cert_revocation_lists(
+			origin: OriginFor<T>,
+			crls: Vec<Vec<u8>>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				crls.iter().all(|c| attestation::is_valid_crl(c)),
+				Error::<T>::InvalidCrl
+			);
+			let num_crls = crls.len() as u32;
+			CertRevocationLists::<T>::put(crls);
+			Self::deposit_event(Event::<T>::CertRevocationListsChanged(num_crls));
+			Ok(())
+		}
+
+		#[pallet::weight(0)]
+		pub fn add_relaychain_genesis_block_hash(
+			origin: OriginFor<T>,
+			genesis_block_hash: H256,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let mut allowlist = RelaychainGenesisBlockHashAllowList::<T>::get();
+			ensure!(
+				!allowlist.contains(&genesis_block_hash),
+				Error::<T>::GenesisBlockHashAlreadyExists
 			);
 
 			allowlist.push(genesis_block_hash);
@@ -413,6 +1108,15 @@ pub mod pallet {
 	where
 		T: crate::mq::Config,
 	{
+		/// The governance-managed certificates to validate the attestations
+		fn trust_store() -> TrustStore {
+			TrustStore {
+				dcap_root_certs: DcapRootCerts::<T>::get(),
+				ias_root_certs: IasRootCerts::<T>::get(),
+				crls: CertRevocationLists::<T>::get(),
+			}
+		}
+
 		pub fn check_message(message: &SignedMessage) -> DispatchResult {
 			let pubkey_copy: ContractPublicKey;
 			let pubkey = match &message.message.sender {
@@ -425,13 +1129,225 @@ pub mod pallet {
 					// GatekeeperMasterPubkey should not be None
 					pubkey_copy = GatekeeperMasterPubkey::<T>::get()
 						.ok_or(Error::<T>::MasterKeyUninitialized)?;
-					&pubkey_copy
+					let result = Self::verify_signature(&pubkey_copy, message);
+					if result.is_err() {
+						// The replaced master key is still valid in the transition window
+						if let Some((prev_pubkey, expire_at)) = PreviousMasterPubkey::<T>::get() {
+							let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+							if now < expire_at {
+								return Self::verify_signature(&prev_pubkey, message);
+							}
+						}
+					}
+					return result;
 				}
 				_ => return Err(Error::<T>::CannotHandleUnknownMessage.into()),
 			};
 			Self::verify_signature(pubkey, message)
 		}
 
+		/// Returns the pRuntime allow list of the TEE type
+		pub fn pruntime_allowlist_of(tee_type: TeeType) -> Vec<Vec<u8>> {
+			match tee_type {
+				TeeType::Sgx => PRuntimeAllowList::<T>::get(),
+				_ => TeePRuntimeAllowList::<T>::get(tee_type),
+			}
+		}
+
+		fn put_pruntime_allowlist(tee_type: TeeType, allowlist: Vec<Vec<u8>>) {
+			match tee_type {
+				TeeType::Sgx => PRuntimeAllowList::<T>::put(allowlist),
+				_ => TeePRuntimeAllowList::<T>::insert(tee_type, allowlist),
+			}
+		}
+
+		fn add_pruntime_of(tee_type: TeeType, pruntime_hash: Vec<u8>) -> DispatchResult {
+			let mut allowlist = Self::pruntime_allowlist_of(tee_type);
+			ensure!(
+				!allowlist.contains(&pruntime_hash),
+				Error::<T>::PRuntimeAlreadyExists
+			);
+
+			allowlist.push(pruntime_hash);
+			Self::put_pruntime_allowlist(tee_type, allowlist);
+
+			Ok(())
+		}
+
+		fn remove_pruntime_of(tee_type: TeeType, pruntime_hash: Vec<u8>) -> DispatchResult {
+			let allowlist = Self::pruntime_allowlist_of(tee_type);
+			ensure!(
+				allowlist.contains(&pruntime_hash),
+				Error::<T>::PRuntimeNotFound
+			);
+
+			let filtered: Vec<_> = allowlist
+				.into_iter()
+				.filter(|h| *h != pruntime_hash)
+				.collect();
+			Self::put_pruntime_allowlist(tee_type, filtered);
+
+			Ok(())
+		}
+
+		/// Returns the TEE type of the worker
+		pub fn worker_tee_type(worker: &WorkerPublicKey) -> TeeType {
+			WorkerTeeTypes::<T>::get(worker).unwrap_or(TeeType::Sgx)
+		}
+
+		/// Returns the owner of the longest claimed prefix of the topic, if any
+		pub fn topic_prefix_owner(topic: &[u8]) -> Option<MessageOrigin> {
+			(1..=topic.len().min(MAX_TOPIC_PREFIX_LEN))
+				.rev()
+				.filter(|&len| topic[len - 1] == b'/')
+				.find_map(|len| TopicOwners::<T>::get(&topic[..len]))
+		}
+
+		/// Returns the number of `TopicOwners` reads `topic_prefix_owner` may do for the topic
+		pub fn topic_prefix_lookups(topic: &[u8]) -> u64 {
+			let len = topic.len().min(MAX_TOPIC_PREFIX_LEN);
+			topic[..len].iter().filter(|&&b| b == b'/').count() as u64
+		}
+
+		/// Returns if the sender can push messages to the topic
+		pub fn can_publish(topic: &[u8], sender: &MessageOrigin) -> bool {
+			match Self::topic_prefix_owner(topic) {
+				Some(owner) => owner == *sender,
+				None => true,
+			}
+		}
+
+		fn is_valid_topic_prefix(prefix: &[u8]) -> bool {
+			prefix.len() <= MAX_TOPIC_PREFIX_LEN
+				&& prefix.ends_with(b"/")
+				&& !prefix.starts_with(RESERVED_TOPIC_PREFIX)
+				&& messaging::Topic::new(prefix.to_vec()).is_offchain()
+		}
+
+		/// Returns if the worker runs a pRuntime below the minimum version after the deadline
+		pub fn is_pruntime_outdated(worker_info: &WorkerInfo<T::AccountId>, now: u64) -> bool {
+			match MinimumPRuntimeVersion::<T>::get() {
+				Some((min_version, deadline)) => {
+					now >= deadline && worker_info.runtime_version < min_version
+				}
+				None => false,
+			}
+		}
+
+		fn ensure_topic_prefix_owner(prefix: &[u8], who: T::AccountId) -> DispatchResult
+		where
+			T::AccountId: IntoH256,
+		{
+			let owner = TopicOwners::<T>::get(prefix).ok_or(Error::<T>::TopicPrefixNotFound)?;
+			ensure!(
+				owner == MessageOrigin::AccountId(who.into_h256()),
+				Error::<T>::NotTopicPrefixOwner
+			);
+			Ok(())
+		}
+
+		fn ensure_worker_operator(worker: &WorkerPublicKey, who: &T::AccountId) -> DispatchResult {
+			let worker_info = Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
+			ensure!(
+				worker_info.operator.as_ref() == Some(who),
+				Error::<T>::NotWorkerOperator
+			);
+			Ok(())
+		}
+
+		/// Returns the time (in sec) the attestation of the worker expired, if it's stale
+		pub fn attestation_expired_at(
+			worker_info: &WorkerInfo<T::AccountId>,
+			now: u64,
+		) -> Option<u64> {
+			let (max_age, _) = AttestationRefreshPolicy::<T>::get()?;
+			let expired_at = worker_info.last_updated.saturating_add(max_age);
+			if now >= expired_at {
+				Some(expired_at)
+			} else {
+				None
+			}
+		}
+
+		/// Marks the worker stale if its attestation expired, and returns the expiration time
+		pub(crate) fn update_stale_status(
+			worker: &WorkerPublicKey,
+			now: u64,
+		) -> Result<Option<u64>, Error<T>> {
+			let worker_info = Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
+			let expired_at = Self::attestation_expired_at(&worker_info, now);
+			match expired_at {
+				Some(expired_at) if !StaleWorkers::<T>::contains_key(worker) => {
+					StaleWorkers::<T>::insert(worker, expired_at);
+					Self::deposit_event(Event::<T>::WorkerAttestationStale(*worker, expired_at));
+				}
+				// The policy may be relaxed after the worker was marked
+				None => StaleWorkers::<T>::remove(worker),
+				_ => (),
+			}
+			Ok(expired_at)
+		}
+
+		/// Marks the workers with an expired attestation stale, including the ones not mining
+		///
+		/// The sweep continues from `StaleSweepCursor` in the next block when `max_weight` is not
+		/// enough for one more worker. Returns the consumed weight.
+		fn sweep_stale_workers(max_weight: Weight) -> Weight {
+			let item_weight = T::DbWeight::get().reads_writes(3, 1);
+			let mut used = T::DbWeight::get().reads_writes(2, 1);
+			if used + item_weight > max_weight {
+				return 0;
+			}
+			if AttestationRefreshPolicy::<T>::get().is_none() {
+				return T::DbWeight::get().reads(1);
+			}
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			let mut iter = match StaleSweepCursor::<T>::get() {
+				Some(cursor) => Workers::<T>::iter_keys_from(cursor),
+				None => Workers::<T>::iter_keys(),
+			};
+			let mut last = None;
+			while used + item_weight <= max_weight {
+				let worker = match iter.next() {
+					Some(worker) => worker,
+					None => {
+						StaleSweepCursor::<T>::kill();
+						return used;
+					}
+				};
+				used += item_weight;
+				let _ = Self::update_stale_status(&worker, now);
+				last = Some(worker);
+			}
+			if let Some(worker) = last {
+				StaleSweepCursor::<T>::put(Workers::<T>::hashed_key_for(&worker));
+			}
+			used
+		}
+
+		fn genesis_hash() -> H256 {
+			let hash = frame_system::Pallet::<T>::block_hash(T::BlockNumber::zero());
+			H256::from_slice(hash.as_ref())
+		}
+
+		fn remove_gatekeeper(gatekeeper: WorkerPublicKey) -> DispatchResult {
+			let mut gatekeepers = Gatekeeper::<T>::get();
+			let index = gatekeepers
+				.iter()
+				.position(|k| *k == gatekeeper)
+				.ok_or(Error::<T>::InvalidGatekeeper)?;
+			ensure!(
+				gatekeepers.len() > 1,
+				Error::<T>::CannotRemoveLastGatekeeper
+			);
+			gatekeepers.remove(index);
+			Gatekeeper::<T>::put(gatekeepers);
+			GatekeeperGeneration::<T>::mutate(gatekeeper, |g| *g = g.wrapping_add(1));
+			Self::push_message(GatekeeperChange::gatekeeper_unregistered(gatekeeper));
+			Self::deposit_event(Event::<T>::GatekeeperRemoved(gatekeeper));
+			Ok(())
+		}
+
 		fn verify_signature(pubkey: &WorkerPublicKey, message: &SignedMessage) -> DispatchResult {
 			let raw_sig = &message.signature;
 			ensure!(raw_sig.len() == 64, Error::<T>::InvalidSignatureLength);
@@ -456,23 +1372,35 @@ pub mod pallet {
 					start_time,
 					iterations,
 				} => {
-					let now = T::UnixTime::now().as_millis().saturated_into::<u64>();
-					if now <= start_time {
-						// Oops, should not happen
-						return Err(Error::<T>::InvalidBenchReport.into());
+					// The report may arrive after the worker is deregistered
+					if !Workers::<T>::contains_key(worker_pubkey) {
+						return Ok(());
 					}
-
-					const MAX_SCORE: u32 = 6000;
-					let score = iterations / ((now - start_time) / 1000);
-					let score = score * 6; // iterations per 6s
-					let score = MAX_SCORE.min(score as u32);
+					let now = T::UnixTime::now().as_millis().saturated_into::<u64>();
+					let sample = BenchmarkSample {
+						start_time,
+						end_time: now,
+						iterations,
+					};
+					let score = Self::benchmark_score(&sample)?;
 
 					Workers::<T>::mutate(worker_pubkey, |val| {
 						if let Some(val) = val {
 							val.initial_score = Some(score);
-							val.last_updated = now;
 						}
 					});
+					BenchmarkHistory::<T>::mutate(worker_pubkey, |history| {
+						if history.len() >= MAX_BENCHMARK_HISTORY {
+							history.remove(0);
+						}
+						history.push((now / 1000, score));
+					});
+					BenchmarkSamples::<T>::mutate(worker_pubkey, |samples| {
+						if samples.len() >= MAX_BENCHMARK_HISTORY {
+							samples.remove(0);
+						}
+						samples.push(sample);
+					});
 
 					Self::push_message(SystemEvent::new_worker_event(
 						*worker_pubkey,
@@ -494,13 +1422,104 @@ pub mod pallet {
 						}
 						_ => {
 							GatekeeperMasterPubkey::<T>::put(master_pubkey);
+							let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+							MasterPubkeyHistory::<T>::append((0u64, master_pubkey, now));
 							Self::push_message(GatekeeperLaunch::master_pubkey_on_chain(
 								master_pubkey,
 							));
 						}
 					}
 				}
+				RegistryEvent::RotatedMasterPubkey {
+					rotation_id,
+					master_pubkey,
+				} => {
+					let gatekeepers = Gatekeeper::<T>::get();
+					ensure!(
+						gatekeepers.contains(worker_pubkey),
+						Error::<T>::InvalidGatekeeper
+					);
+					ensure!(
+						PendingMasterKeyRotation::<T>::get() == Some(rotation_id),
+						Error::<T>::InvalidMasterKeyRotation
+					);
+					let mut votes = MasterKeyRotationVotes::<T>::get();
+					votes.retain(|(gk, _)| gk != worker_pubkey);
+					votes.push((*worker_pubkey, master_pubkey));
+					let agreed = votes
+						.iter()
+						.filter(|(gk, key)| gatekeepers.contains(gk) && *key == master_pubkey)
+						.count();
+					if agreed * 2 <= gatekeepers.len() {
+						MasterKeyRotationVotes::<T>::put(votes);
+						return Ok(());
+					}
+
+					let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+					if let Some(old_pubkey) = GatekeeperMasterPubkey::<T>::get() {
+						PreviousMasterPubkey::<T>::put((
+							old_pubkey,
+							now + MASTER_KEY_TRANSITION_WINDOW,
+						));
+					}
+					GatekeeperMasterPubkey::<T>::put(master_pubkey);
+					MasterPubkeyHistory::<T>::append((rotation_id, master_pubkey, now));
+					PendingMasterKeyRotation::<T>::kill();
+					MasterKeyRotationVotes::<T>::kill();
+					Self::push_message(GatekeeperLaunch::master_pubkey_rotated(
+						rotation_id,
+						master_pubkey,
+					));
+					Self::deposit_event(Event::<T>::MasterPubkeyRotated(
+						rotation_id,
+						master_pubkey,
+					));
+				}
+			}
+			Ok(())
+		}
+
+		/// Computes the benchmark score (iterations per 6s) of a report under the current scoring
+		fn benchmark_score(sample: &BenchmarkSample) -> Result<u32, Error<T>> {
+			if sample.end_time <= sample.start_time {
+				// Oops, should not happen
+				return Err(Error::<T>::InvalidBenchReport);
+			}
+			let duration = (sample.end_time - sample.start_time) / 1000;
+			let min_duration =
+				MinBenchmarkDuration::<T>::get().unwrap_or(DEFAULT_MIN_BENCHMARK_DURATION);
+			ensure!(
+				duration >= min_duration.max(1),
+				Error::<T>::BenchReportTooShort
+			);
+			let max_score = MaxBenchmarkScore::<T>::get().unwrap_or(DEFAULT_MAX_BENCHMARK_SCORE);
+			let score = (sample.iterations / duration).saturating_mul(6); // iterations per 6s
+			Ok(max_score.min(score.saturated_into()))
+		}
+
+		/// Asks a registered worker to redo the benchmark
+		///
+		/// The new `initial_score` will be applied once the `BenchReport` arrives. Rate-limited
+		/// by `MIN_REBENCHMARK_INTERVAL` per worker.
+		pub(crate) fn request_benchmark(worker: &WorkerPublicKey) -> DispatchResult {
+			ensure!(
+				Workers::<T>::contains_key(worker),
+				Error::<T>::WorkerNotFound
+			);
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			if let Some(last) = LastBenchmarkRequest::<T>::get(worker) {
+				ensure!(
+					now >= last + MIN_REBENCHMARK_INTERVAL,
+					Error::<T>::BenchmarkRequestTooFrequent
+				);
 			}
+			LastBenchmarkRequest::<T>::insert(worker, now);
+			let duration = BenchmarkDuration::<T>::get().unwrap_or_default();
+			Self::push_message(SystemEvent::new_worker_event(
+				*worker,
+				WorkerEvent::BenchStart { duration },
+			));
+			Self::deposit_event(Event::<T>::BenchmarkRequested(*worker));
 			Ok(())
 		}
 
@@ -562,6 +1581,7 @@ pub mod pallet {
 					*pubkey,
 					WorkerEvent::Registered(messaging::WorkerInfo {
 						confidence_level: 128u8,
+						tee_type: TeeType::Sgx,
 					}),
 				));
 				Pallet::<T>::queue_message(SystemEvent::new_worker_event(
@@ -578,6 +1598,7 @@ pub mod pallet {
 					gatekeepers.push(*gatekeeper);
 					let gatekeeper_count = gatekeepers.len() as u32;
 					Gatekeeper::<T>::put(gatekeepers.clone());
+					GatekeeperGeneration::<T>::mutate(gatekeeper, |g| *g = g.wrapping_add(1));
 					if gatekeeper_count == 1 {
 						Pallet::<T>::queue_message(GatekeeperLaunch::first_gatekeeper(
 							*gatekeeper,
@@ -595,7 +1616,10 @@ pub mod pallet {
 	}
 
 	#[pallet::hooks]
-	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
 		fn on_runtime_upgrade() -> Weight {
 			let mut w = 0;
 			let old = Self::on_chain_storage_version();
@@ -603,28 +1627,89 @@ pub mod pallet {
 
 			if old == 0 {
 				w += migrations::initialize::<T>();
+			}
+			if old < 2 {
+				w += migrations::seed_master_pubkey_history::<T>();
+			}
+			if old < 3 {
+				w += migrations::migrate_last_updated_to_secs::<T>();
 				STORAGE_VERSION.put::<super::Pallet<T>>();
 				w += T::DbWeight::get().writes(1);
 			}
 			w
 		}
+
+		fn on_idle(_n: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+			Self::sweep_stale_workers(remaining_weight)
+		}
 	}
 
 	mod migrations {
-		use super::{BenchmarkDuration, Config};
+		use super::{
+			BenchmarkDuration, Config, GatekeeperMasterPubkey, MasterKeyRotationId,
+			MasterPubkeyHistory, WorkerInfo, Workers,
+		};
 		use frame_support::pallet_prelude::*;
+		use sp_std::vec;
 
 		pub fn initialize<T: Config>() -> Weight {
 			log::info!("phala_pallet::registry: initialize()");
 			BenchmarkDuration::<T>::put(50);
 			T::DbWeight::get().writes(1)
 		}
+
+		/// Records the master pubkey in use before `MasterPubkeyHistory` was introduced
+		///
+		/// The time it was generated is unknown, so it's recorded as in use since 0.
+		pub fn seed_master_pubkey_history<T: Config>() -> Weight {
+			log::info!("phala_pallet::registry: seed_master_pubkey_history()");
+			let history = MasterPubkeyHistory::<T>::get();
+			match GatekeeperMasterPubkey::<T>::get() {
+				Some(pubkey) if history.is_empty() => {
+					let rotation_id = MasterKeyRotationId::<T>::get();
+					MasterPubkeyHistory::<T>::put(vec![(rotation_id, pubkey, 0u64)]);
+					T::DbWeight::get().reads_writes(3, 1)
+				}
+				_ => T::DbWeight::get().reads(2),
+			}
+		}
+
+		/// `WorkerInfo::last_updated` values above it are in millis
+		///
+		/// 1e11 sec is far in the future, while 1e11 ms is back in 1973.
+		const MILLIS_THRESHOLD: u64 = 100_000_000_000;
+
+		/// Converts `WorkerInfo::last_updated` to sec
+		///
+		/// It used to be overwritten in millis by the benchmark reports.
+		pub fn migrate_last_updated_to_secs<T: Config>() -> Weight {
+			log::info!("phala_pallet::registry: migrate_last_updated_to_secs()");
+			let mut n = 0u64;
+			Workers::<T>::translate(|_, mut info: WorkerInfo<T::AccountId>| {
+				n += 1;
+				if info.last_updated > MILLIS_THRESHOLD {
+					info.last_updated /= 1000;
+				}
+				Some(info)
+			});
+			T::DbWeight::get().reads_writes(n, n)
+		}
 	}
 
 	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
 		type Config = T;
 	}
 
+	/// A raw benchmark report
+	#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+	pub struct BenchmarkSample {
+		/// The time (in millis) the benchmark started, reported by the worker
+		pub start_time: u64,
+		/// The time (in millis) the report was received
+		pub end_time: u64,
+		pub iterations: u64,
+	}
+
 	#[derive(Encode, Decode, TypeInfo, Debug, Clone)]
 	pub struct WorkerInfo<AccountId> {
 		// identity
@@ -652,6 +1737,17 @@ pub mod pallet {
 				AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
 				AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
 				AttestationError::InvalidUserDataHash => Self::InvalidRuntimeInfoHash,
+				AttestationError::UnsupportedAttestationType => Self::UnsupportedAttestationType,
+				AttestationError::UnsupportedQuoteFormat => Self::UnsupportedQuoteFormat,
+				AttestationError::MalformedQuote => Self::MalformedQuote,
+				AttestationError::InvalidPckCertChain => Self::InvalidPckCertChain,
+				AttestationError::InvalidQuoteSignature => Self::InvalidQuoteSignature,
+				AttestationError::InvalidQeReport => Self::InvalidQeReport,
+				AttestationError::InvalidCollateral => Self::InvalidCollateral,
+				AttestationError::OutdatedCollateral => Self::OutdatedCollateral,
+				AttestationError::TcbMismatch => Self::TcbMismatch,
+				AttestationError::TcbRevoked => Self::TcbRevoked,
+				AttestationError::CertRevoked => Self::CertRevoked,
 			}
 		}
 	}
@@ -663,7 +1759,8 @@ pub mod pallet {
 		use super::*;
 		use crate::mock::{
 			ecdh_pubkey, elapse_seconds, new_test_ext, set_block_1,
-			setup_relaychain_genesis_allowlist, worker_pubkey, Origin, Test,
+			setup_relaychain_genesis_allowlist, take_events, take_messages, worker_pubkey, Origin,
+			Test,
 		};
 		// Pallets
 		use crate::mock::PhalaRegistry;
@@ -766,6 +1863,777 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_set_dcap_root_certs() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let sample: serde_json::Value =
+					serde_json::from_slice(include_bytes!("../sample/dcap_attestation.json"))
+						.unwrap();
+				let root = hex::decode(sample["rootCaCert"].as_str().unwrap()).unwrap();
+				assert_noop!(
+					PhalaRegistry::set_dcap_root_certs(Origin::signed(1), vec![root.clone()]),
+					DispatchError::BadOrigin
+				);
+				assert_noop!(
+					PhalaRegistry::set_dcap_root_certs(
+						Origin::root(),
+						vec![root.clone(), vec![1, 2, 3]]
+					),
+					Error::<Test>::InvalidRootCert
+				);
+				assert_ok!(PhalaRegistry::set_dcap_root_certs(
+					Origin::root(),
+					vec![root.clone()]
+				));
+				assert_eq!(PhalaRegistry::dcap_root_certs(), vec![root]);
+			});
+		}
+
+		#[test]
+		fn test_set_trust_store() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let sample: serde_json::Value =
+					serde_json::from_slice(include_bytes!("../sample/dcap_attestation.json"))
+						.unwrap();
+				let root = hex::decode(sample["rootCaCert"].as_str().unwrap()).unwrap();
+				let crl = hex::decode(sample["pckCrl"].as_str().unwrap()).unwrap();
+				assert_noop!(
+					PhalaRegistry::set_ias_root_certs(Origin::root(), vec![vec![1, 2, 3]]),
+					Error::<Test>::InvalidRootCert
+				);
+				assert_ok!(PhalaRegistry::set_ias_root_certs(
+					Origin::root(),
+					vec![root.clone()]
+				));
+				assert_noop!(
+					PhalaRegistry::set_cert_revocation_lists(Origin::root(), vec![root.clone()]),
+					Error::<Test>::InvalidCrl
+				);
+				assert_noop!(
+					PhalaRegistry::set_cert_revocation_lists(Origin::signed(1), vec![crl.clone()]),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::set_cert_revocation_lists(
+					Origin::root(),
+					vec![crl.clone()]
+				));
+				let events = take_events();
+				assert!(events.contains(&crate::mock::Event::PhalaRegistry(
+					Event::IasRootCertsChanged(1)
+				)));
+				assert!(events.contains(&crate::mock::Event::PhalaRegistry(
+					Event::CertRevocationListsChanged(1)
+				)));
+				assert_eq!(
+					PhalaRegistry::trust_store(),
+					TrustStore {
+						dcap_root_certs: vec![],
+						ias_root_certs: vec![root],
+						crls: vec![crl],
+					}
+				);
+			});
+		}
+
+		#[test]
+		fn test_unregister_gatekeeper() {
+			use sp_core::Pair;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let pair = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
+				let gk1 = pair.public();
+				let gk2 = worker_pubkey(2);
+				for (gk, i) in vec![(gk1, 1), (gk2, 2)] {
+					assert_ok!(PhalaRegistry::force_register_worker(
+						Origin::root(),
+						gk,
+						ecdh_pubkey(i),
+						None,
+					));
+				}
+				assert_ok!(PhalaRegistry::register_gatekeeper(Origin::root(), gk1));
+				GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw([1u8; 32]));
+				assert_ok!(PhalaRegistry::register_gatekeeper(Origin::root(), gk2));
+				let _ = take_messages();
+
+				let genesis_hash =
+					H256::from_slice(frame_system::Pallet::<Test>::block_hash(0).as_ref());
+				// Bad signature
+				let payload = GatekeeperUnregistration {
+					pubkey: gk1,
+					last_updated: 0,
+					generation: 1,
+					genesis_hash,
+				};
+				let other = sp_core::sr25519::Pair::from_seed(&[2u8; 32]);
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(
+						Origin::signed(1),
+						gk1,
+						other.sign(&payload.signing_payload()).0
+					),
+					Error::<Test>::InvalidSignature
+				);
+				// Unregister by the gatekeeper itself
+				assert_ok!(PhalaRegistry::unregister_gatekeeper(
+					Origin::signed(1),
+					gk1,
+					pair.sign(&payload.signing_payload()).0
+				));
+				assert_eq!(Gatekeeper::<Test>::get(), vec![gk2]);
+				let msgs = take_messages();
+				assert_eq!(
+					msgs[0].decode_payload::<GatekeeperChange>(),
+					Some(GatekeeperChange::gatekeeper_unregistered(gk1))
+				);
+				assert!(take_events().contains(&crate::mock::Event::PhalaRegistry(
+					Event::<Test>::GatekeeperRemoved(gk1)
+				)));
+				// Can't remove twice or remove the last one
+				assert_noop!(
+					PhalaRegistry::force_unregister_gatekeeper(Origin::root(), gk1),
+					Error::<Test>::InvalidGatekeeper
+				);
+				assert_noop!(
+					PhalaRegistry::force_unregister_gatekeeper(Origin::signed(1), gk2),
+					DispatchError::BadOrigin
+				);
+				assert_noop!(
+					PhalaRegistry::force_unregister_gatekeeper(Origin::root(), gk2),
+					Error::<Test>::CannotRemoveLastGatekeeper
+				);
+				// The old signature can't be replayed after the gatekeeper is re-added
+				assert_ok!(PhalaRegistry::register_gatekeeper(Origin::root(), gk1));
+				assert_eq!(GatekeeperGeneration::<Test>::get(gk1), 3);
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(
+						Origin::signed(1),
+						gk1,
+						pair.sign(&payload.signing_payload()).0
+					),
+					Error::<Test>::InvalidSignature
+				);
+				// Nor can a signature for another chain be used
+				let payload = GatekeeperUnregistration {
+					generation: 3,
+					genesis_hash: H256::repeat_byte(0xff),
+					..payload
+				};
+				assert_noop!(
+					PhalaRegistry::unregister_gatekeeper(
+						Origin::signed(1),
+						gk1,
+						pair.sign(&payload.signing_payload()).0
+					),
+					Error::<Test>::InvalidSignature
+				);
+				let payload = GatekeeperUnregistration {
+					genesis_hash,
+					..payload
+				};
+				assert_ok!(PhalaRegistry::unregister_gatekeeper(
+					Origin::signed(1),
+					gk1,
+					pair.sign(&payload.signing_payload()).0
+				));
+			});
+		}
+
+		#[test]
+		fn test_rotate_master_key() {
+			use phala_types::messaging::{Message, Topic};
+			use sp_core::Pair;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let old_key = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
+				let new_key = sp_core::sr25519::Pair::from_seed(&[2u8; 32]);
+				let signed_by = |pair: &sp_core::sr25519::Pair| {
+					let mut message = SignedMessage {
+						message: Message::new(MessageOrigin::Gatekeeper, b"test".to_vec(), vec![]),
+						sequence: 0,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				let report = |gk: u8, rotation_id: u64| {
+					PhalaRegistry::on_message_received(DecodedMessage {
+						sender: MessageOrigin::Worker(worker_pubkey(gk)),
+						destination: Topic::new(*b"^phala/registry/event"),
+						payload: RegistryEvent::RotatedMasterPubkey {
+							rotation_id,
+							master_pubkey: new_key.public(),
+						},
+					})
+				};
+				Gatekeeper::<Test>::put(vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]);
+				assert_noop!(
+					PhalaRegistry::rotate_master_key(Origin::root()),
+					Error::<Test>::MasterKeyUninitialized
+				);
+				GatekeeperMasterPubkey::<Test>::put(old_key.public());
+
+				assert_ok!(PhalaRegistry::rotate_master_key(Origin::root()));
+				assert_noop!(
+					PhalaRegistry::rotate_master_key(Origin::root()),
+					Error::<Test>::MasterKeyRotationInProgress
+				);
+				let msgs = take_messages();
+				assert_eq!(
+					msgs[0].decode_payload::<GatekeeperLaunch>(),
+					Some(GatekeeperLaunch::rotate_master_key(1))
+				);
+				// Wrong epoch or not a gatekeeper
+				assert_noop!(report(1, 2), Error::<Test>::InvalidMasterKeyRotation);
+				assert_noop!(report(4, 1), Error::<Test>::InvalidGatekeeper);
+				// No quorum until 2 of 3 gatekeepers agree
+				assert_ok!(report(1, 1));
+				assert_ok!(report(1, 1));
+				assert_eq!(
+					GatekeeperMasterPubkey::<Test>::get(),
+					Some(old_key.public())
+				);
+				assert_ok!(report(2, 1));
+				assert_eq!(
+					GatekeeperMasterPubkey::<Test>::get(),
+					Some(new_key.public())
+				);
+				assert_eq!(PendingMasterKeyRotation::<Test>::get(), None);
+				assert_eq!(
+					PhalaRegistry::master_pubkey_history(),
+					vec![(1, new_key.public(), 0)]
+				);
+				assert!(take_events().contains(&crate::mock::Event::PhalaRegistry(
+					Event::<Test>::MasterPubkeyRotated(1, new_key.public())
+				)));
+				// A rotation without a quorum can be cancelled
+				assert_noop!(
+					PhalaRegistry::cancel_master_key_rotation(Origin::root()),
+					Error::<Test>::InvalidMasterKeyRotation
+				);
+				assert_ok!(PhalaRegistry::rotate_master_key(Origin::root()));
+				assert_ok!(report(1, 2));
+				assert_noop!(
+					PhalaRegistry::cancel_master_key_rotation(Origin::signed(1)),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::cancel_master_key_rotation(Origin::root()));
+				assert_eq!(PendingMasterKeyRotation::<Test>::get(), None);
+				assert!(MasterKeyRotationVotes::<Test>::get().is_empty());
+				assert!(take_events().contains(&crate::mock::Event::PhalaRegistry(
+					Event::<Test>::MasterKeyRotationCancelled(2)
+				)));
+				assert_noop!(report(2, 2), Error::<Test>::InvalidMasterKeyRotation);
+				assert_eq!(
+					GatekeeperMasterPubkey::<Test>::get(),
+					Some(new_key.public())
+				);
+				// The old key is accepted during the transition window
+				assert_ok!(PhalaRegistry::check_message(&signed_by(&new_key)));
+				assert_ok!(PhalaRegistry::check_message(&signed_by(&old_key)));
+				// Unless revoked by the governance
+				let previous = PreviousMasterPubkey::<Test>::get().unwrap();
+				assert_noop!(
+					PhalaRegistry::revoke_previous_master_pubkey(Origin::signed(1)),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::revoke_previous_master_pubkey(Origin::root()));
+				assert!(take_events().contains(&crate::mock::Event::PhalaRegistry(
+					Event::<Test>::PreviousMasterPubkeyRevoked(old_key.public())
+				)));
+				assert_noop!(
+					PhalaRegistry::check_message(&signed_by(&old_key)),
+					Error::<Test>::InvalidSignature
+				);
+				assert_noop!(
+					PhalaRegistry::revoke_previous_master_pubkey(Origin::root()),
+					Error::<Test>::NoPreviousMasterPubkey
+				);
+				PreviousMasterPubkey::<Test>::put(previous);
+				elapse_seconds(MASTER_KEY_TRANSITION_WINDOW);
+				assert_ok!(PhalaRegistry::check_message(&signed_by(&new_key)));
+				assert_noop!(
+					PhalaRegistry::check_message(&signed_by(&old_key)),
+					Error::<Test>::InvalidSignature
+				);
+			});
+		}
+
+		#[test]
+		fn test_migrate_master_pubkey_history() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let pubkey = MasterPublicKey::from_raw([1u8; 32]);
+				GatekeeperMasterPubkey::<Test>::put(pubkey);
+				StorageVersion::new(1).put::<PhalaRegistry>();
+				PhalaRegistry::on_runtime_upgrade();
+				assert_eq!(PhalaRegistry::on_chain_storage_version(), STORAGE_VERSION);
+				assert_eq!(PhalaRegistry::master_pubkey_history(), vec![(0, pubkey, 0)]);
+			});
+		}
+
+		#[test]
+		fn test_migrate_last_updated_to_secs() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				for i in 1..=2 {
+					assert_ok!(PhalaRegistry::force_register_worker(
+						Origin::root(),
+						worker_pubkey(i),
+						ecdh_pubkey(i),
+						None,
+					));
+				}
+				// Worker 1 was last updated by a benchmark report, in millis
+				Workers::<Test>::mutate(worker_pubkey(1), |info| {
+					info.as_mut().unwrap().last_updated = 1_600_000_000_000;
+				});
+				Workers::<Test>::mutate(worker_pubkey(2), |info| {
+					info.as_mut().unwrap().last_updated = 1_600_000_000;
+				});
+				StorageVersion::new(2).put::<PhalaRegistry>();
+				PhalaRegistry::on_runtime_upgrade();
+				assert_eq!(PhalaRegistry::on_chain_storage_version(), STORAGE_VERSION);
+				for i in 1..=2 {
+					assert_eq!(
+						Workers::<Test>::get(worker_pubkey(i)).unwrap().last_updated,
+						1_600_000_000
+					);
+				}
+				// Both expire by the same policy now
+				assert_ok!(PhalaRegistry::set_attestation_refresh_policy(
+					Origin::root(),
+					Some((1000, 100))
+				));
+				elapse_seconds(1_600_001_000);
+				let _ = take_events();
+				PhalaRegistry::on_idle(1, Weight::MAX);
+				assert_eq!(StaleSweepCursor::<Test>::get(), None);
+				for i in 1..=2 {
+					assert_eq!(
+						PhalaRegistry::stale_workers(worker_pubkey(i)),
+						Some(1_600_001_000)
+					);
+				}
+				assert_eq!(take_events().len(), 2);
+			});
+		}
+
+		#[test]
+		fn test_worker_operator_and_deregistration() {
+			use crate::mock::{setup_workers, PhalaMining};
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				// Operated by account 1
+				setup_workers(2);
+				let worker = worker_pubkey(1);
+				assert_noop!(
+					PhalaRegistry::transfer_worker_operator(Origin::signed(2), worker, Some(2)),
+					Error::<Test>::NotWorkerOperator
+				);
+				assert_ok!(PhalaRegistry::transfer_worker_operator(
+					Origin::signed(1),
+					worker,
+					Some(2)
+				));
+				assert_noop!(
+					PhalaRegistry::accept_worker_operator(Origin::signed(3), worker),
+					Error::<Test>::NoPendingOperator
+				);
+				assert_ok!(PhalaRegistry::accept_worker_operator(
+					Origin::signed(2),
+					worker
+				));
+				assert_eq!(Workers::<Test>::get(worker).unwrap().operator, Some(2));
+				assert_eq!(PhalaRegistry::pending_operator(worker), None);
+				// Can't hand over a bound worker
+				assert_ok!(PhalaRegistry::transfer_worker_operator(
+					Origin::signed(2),
+					worker,
+					Some(3)
+				));
+				assert_ok!(PhalaMining::bind(1, worker));
+				assert_noop!(
+					PhalaRegistry::accept_worker_operator(Origin::signed(3), worker),
+					Error::<Test>::WorkerStillBound
+				);
+				assert_noop!(
+					PhalaRegistry::transfer_worker_operator(Origin::signed(2), worker, Some(3)),
+					Error::<Test>::WorkerStillBound
+				);
+				assert_ok!(PhalaRegistry::transfer_worker_operator(
+					Origin::signed(2),
+					worker,
+					None
+				));
+				// Can't deregister a bound worker
+				assert_noop!(
+					PhalaRegistry::deregister_worker(Origin::signed(1), worker),
+					Error::<Test>::NotWorkerOperator
+				);
+				assert_noop!(
+					PhalaRegistry::deregister_worker(Origin::signed(2), worker),
+					Error::<Test>::WorkerStillBound
+				);
+				// Deregister an unbound worker
+				let _ = take_messages();
+				assert_ok!(PhalaRegistry::deregister_worker(
+					Origin::signed(1),
+					worker_pubkey(2)
+				));
+				assert!(!Workers::<Test>::contains_key(worker_pubkey(2)));
+				let msgs = take_messages();
+				assert!(matches!(
+					msgs[0].decode_payload::<SystemEvent>(),
+					Some(SystemEvent::WorkerEvent(e)) if matches!(e.event, WorkerEvent::Deregistered)
+				));
+			});
+		}
+
+		#[test]
+		fn test_benchmark_scoring() {
+			use crate::mock::{setup_workers, Timestamp};
+			use phala_types::messaging::Topic;
+			fn report(start_time: u64, iterations: u64) -> DispatchResult {
+				PhalaRegistry::on_message_received(DecodedMessage {
+					sender: MessageOrigin::Worker(worker_pubkey(1)),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::BenchReport {
+						start_time,
+						iterations,
+					},
+				})
+			}
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				elapse_seconds(100);
+				let now = Timestamp::get();
+				// Less than a second
+				assert_noop!(report(now - 500, 100), Error::<Test>::BenchReportTooShort);
+				assert_noop!(report(now, 100), Error::<Test>::InvalidBenchReport);
+				// Capped at the default max score
+				assert_ok!(report(now - 10_000, 100_000));
+				assert_eq!(
+					Workers::<Test>::get(worker_pubkey(1))
+						.unwrap()
+						.initial_score,
+					Some(DEFAULT_MAX_BENCHMARK_SCORE)
+				);
+				assert_eq!(
+					PhalaRegistry::benchmark_samples(worker_pubkey(1)),
+					vec![BenchmarkSample {
+						start_time: now - 10_000,
+						end_time: now,
+						iterations: 100_000
+					}]
+				);
+				// Governable scoring
+				assert_noop!(
+					PhalaRegistry::set_benchmark_scoring(Origin::root(), Some(0), None),
+					Error::<Test>::InvalidBenchmarkScoring
+				);
+				// Longer than 2 blocks of 12s
+				assert_ok!(PhalaRegistry::force_set_benchmark_duration(
+					Origin::root(),
+					2
+				));
+				assert_noop!(
+					PhalaRegistry::set_benchmark_scoring(Origin::root(), None, Some(25)),
+					Error::<Test>::InvalidBenchmarkScoring
+				);
+				assert_ok!(PhalaRegistry::set_benchmark_scoring(
+					Origin::root(),
+					Some(100_000),
+					Some(20)
+				));
+				assert_noop!(
+					report(now - 10_000, 100_000),
+					Error::<Test>::BenchReportTooShort
+				);
+				assert_noop!(
+					PhalaRegistry::force_set_benchmark_duration(Origin::root(), 1),
+					Error::<Test>::InvalidBenchmarkScoring
+				);
+				// Recomputed from the kept sample with the new cap
+				assert_ok!(PhalaRegistry::set_benchmark_scoring(
+					Origin::root(),
+					Some(100_000),
+					None
+				));
+				assert_ok!(PhalaRegistry::rescore_worker(
+					Origin::root(),
+					worker_pubkey(1)
+				));
+				assert_eq!(
+					Workers::<Test>::get(worker_pubkey(1))
+						.unwrap()
+						.initial_score,
+					Some(60_000)
+				);
+
+				// The benchmark data is dropped with the worker, and late reports are ignored
+				assert_ok!(PhalaRegistry::deregister_worker(
+					Origin::signed(1),
+					worker_pubkey(1)
+				));
+				assert!(PhalaRegistry::benchmark_samples(worker_pubkey(1)).is_empty());
+				assert_ok!(report(now - 10_000, 100_000));
+				assert!(PhalaRegistry::benchmark_samples(worker_pubkey(1)).is_empty());
+				assert!(!BenchmarkHistory::<Test>::contains_key(worker_pubkey(1)));
+			});
+		}
+
+		#[test]
+		fn test_topic_prefix_ownership() {
+			use crate::mock::{Balances, PhalaMq, DOLLARS};
+			use phala_types::messaging::Message;
+			use sp_core::Pair;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let owner = MessageOrigin::AccountId(H256::from_low_u64_be(1));
+				for prefix in [&b"no/slash"[..], b"phala/mine/", b"^mine/"].iter() {
+					assert_noop!(
+						PhalaRegistry::claim_topic_prefix(Origin::signed(1), prefix.to_vec()),
+						Error::<Test>::InvalidTopicPrefix
+					);
+				}
+				assert_ok!(PhalaRegistry::claim_topic_prefix(
+					Origin::signed(1),
+					b"app/".to_vec()
+				));
+				assert_eq!(PhalaRegistry::topic_owners(b"app/".to_vec()), Some(owner));
+				assert_eq!(Balances::reserved_balance(1), DOLLARS);
+				// Can't claim an owned namespace, except by the owner
+				assert_noop!(
+					PhalaRegistry::claim_topic_prefix(Origin::signed(2), b"app/".to_vec()),
+					Error::<Test>::TopicPrefixClaimed
+				);
+				assert_noop!(
+					PhalaRegistry::claim_topic_prefix(Origin::signed(2), b"app/sub/".to_vec()),
+					Error::<Test>::TopicPrefixClaimed
+				);
+				assert_ok!(PhalaRegistry::claim_topic_prefix(
+					Origin::signed(1),
+					b"app/sub/".to_vec()
+				));
+				assert_eq!(Balances::reserved_balance(1), 2 * DOLLARS);
+				// Can't take over the pubkey registered by the governance
+				assert_ok!(PhalaRegistry::force_register_topic_pubkey(
+					Origin::root(),
+					b"gov/".to_vec(),
+					vec![2]
+				));
+				assert_noop!(
+					PhalaRegistry::claim_topic_prefix(Origin::signed(2), b"gov/".to_vec()),
+					Error::<Test>::TopicKeyRegistered
+				);
+				// Pubkey management
+				assert_noop!(
+					PhalaRegistry::set_topic_prefix_key(
+						Origin::signed(2),
+						b"app/".to_vec(),
+						Some(vec![1])
+					),
+					Error::<Test>::NotTopicPrefixOwner
+				);
+				assert_ok!(PhalaRegistry::set_topic_prefix_key(
+					Origin::signed(1),
+					b"app/".to_vec(),
+					Some(vec![1])
+				));
+				assert_eq!(TopicKey::<Test>::get(b"app/".to_vec()), Some(vec![1]));
+				assert_noop!(
+					PhalaRegistry::set_topic_prefix_key(
+						Origin::signed(1),
+						b"app/".to_vec(),
+						Some(vec![1; MAX_TOPIC_PUBKEY_LEN + 1])
+					),
+					Error::<Test>::TopicKeyTooLong
+				);
+				assert_eq!(PhalaRegistry::topic_prefix_lookups(b"app/a/topic"), 2);
+				assert_eq!(
+					PhalaRegistry::topic_prefix_lookups(&b"a/".repeat(MAX_TOPIC_PREFIX_LEN)),
+					MAX_TOPIC_PREFIX_LEN as u64 / 2
+				);
+				// Only the owner can push
+				assert_noop!(
+					PhalaMq::push_message(Origin::signed(2), b"app/topic".to_vec(), vec![]),
+					crate::mq::Error::<Test>::TopicPrefixNotOwned
+				);
+				assert_ok!(PhalaMq::push_message(
+					Origin::signed(1),
+					b"app/topic".to_vec(),
+					vec![]
+				));
+				assert_ok!(PhalaMq::push_message(
+					Origin::signed(2),
+					b"other/topic".to_vec(),
+					vec![]
+				));
+				// Transfer to a contract, then released by the governance
+				assert_ok!(PhalaRegistry::transfer_topic_prefix(
+					Origin::signed(1),
+					b"app/".to_vec(),
+					MessageOrigin::Contract(H256::from_low_u64_be(9))
+				));
+				assert_noop!(
+					PhalaRegistry::release_topic_prefix(Origin::signed(1), b"app/".to_vec()),
+					Error::<Test>::NotTopicPrefixOwner
+				);
+				assert_ok!(PhalaRegistry::release_topic_prefix(
+					Origin::root(),
+					b"app/".to_vec()
+				));
+				assert_eq!(PhalaRegistry::topic_owners(b"app/".to_vec()), None);
+				assert_eq!(TopicKey::<Test>::get(b"app/".to_vec()), None);
+				// The deposit is returned to the claimer
+				assert_eq!(Balances::reserved_balance(1), DOLLARS);
+				// The offchain messages are checked too
+				let pair = sp_core::sr25519::Pair::from_seed(&[1u8; 32]);
+				assert_ok!(PhalaRegistry::force_register_worker(
+					Origin::root(),
+					pair.public(),
+					ecdh_pubkey(1),
+					None,
+				));
+				let signed_to = |destination: &[u8]| {
+					let mut message = SignedMessage {
+						message: Message::new(
+							MessageOrigin::Worker(pair.public()),
+							destination.to_vec(),
+							vec![],
+						),
+						sequence: 0,
+						signature: vec![],
+					};
+					message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+					message
+				};
+				assert_noop!(
+					PhalaMq::sync_offchain_message(Origin::signed(2), signed_to(b"app/sub/topic")),
+					crate::mq::Error::<Test>::TopicPrefixNotOwned
+				);
+				assert_ok!(PhalaMq::sync_offchain_message(
+					Origin::signed(2),
+					signed_to(b"app/topic")
+				));
+			});
+		}
+
+		#[test]
+		fn test_tee_scoped_registration() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_relaychain_genesis_allowlist();
+				let measurement = vec![1, 2, 3, 4];
+				assert_ok!(PhalaRegistry::add_tee_pruntime(
+					Origin::root(),
+					TeeType::Tdx,
+					measurement.clone()
+				));
+				assert_noop!(
+					PhalaRegistry::add_tee_pruntime(
+						Origin::root(),
+						TeeType::Tdx,
+						measurement.clone()
+					),
+					Error::<Test>::PRuntimeAlreadyExists
+				);
+				// Scoped per TEE type, the SGX list is untouched
+				assert_eq!(
+					PhalaRegistry::pruntime_allowlist_of(TeeType::Tdx),
+					vec![measurement.clone()]
+				);
+				assert!(PRuntimeAllowList::<Test>::get().is_empty());
+
+				let register = |i: u8, attestation: Attestation| {
+					PhalaRegistry::register_worker(
+						Origin::signed(1),
+						WorkerRegistrationInfo::<u64> {
+							version: 1,
+							machine_id: Default::default(),
+							pubkey: worker_pubkey(i),
+							ecdh_pubkey: ecdh_pubkey(i),
+							genesis_block_hash: H256::repeat_byte(1),
+							features: vec![4, 1],
+							operator: Some(1),
+						},
+						attestation,
+					)
+				};
+				// Only the allow list of the TEE type is consulted
+				assert_ok!(PhalaRegistry::add_pruntime(
+					Origin::root(),
+					vec![5, 6, 7, 8]
+				));
+				assert_noop!(
+					register(
+						1,
+						Attestation::Generic {
+							tee_type: TeeType::Tdx,
+							evidence: vec![5, 6, 7, 8],
+						}
+					),
+					Error::<Test>::PRuntimeRejected
+				);
+				assert_noop!(
+					register(
+						1,
+						Attestation::Generic {
+							tee_type: TeeType::SevSnp,
+							evidence: measurement.clone(),
+						}
+					),
+					Error::<Test>::PRuntimeRejected
+				);
+				take_messages();
+				assert_ok!(register(
+					1,
+					Attestation::Generic {
+						tee_type: TeeType::Tdx,
+						evidence: measurement.clone(),
+					}
+				));
+				// The gatekeeper is told the TEE type to apply its confidence scores
+				assert!(take_messages().iter().any(|msg| matches!(
+					msg.decode_payload::<SystemEvent>(),
+					Some(SystemEvent::WorkerEvent(e)) if matches!(
+						e.event,
+						WorkerEvent::Registered(messaging::WorkerInfo {
+							tee_type: TeeType::Tdx,
+							..
+						})
+					)
+				)));
+				assert_ok!(register(
+					2,
+					Attestation::SgxIas {
+						ra_report: Vec::new(),
+						signature: Vec::new(),
+						raw_signing_cert: Vec::new(),
+					}
+				));
+				assert_eq!(
+					PhalaRegistry::worker_tee_type(&worker_pubkey(1)),
+					TeeType::Tdx
+				);
+				assert_eq!(
+					PhalaRegistry::worker_tee_type(&worker_pubkey(2)),
+					TeeType::Sgx
+				);
+
+				assert_ok!(PhalaRegistry::remove_tee_pruntime(
+					Origin::root(),
+					TeeType::Tdx,
+					measurement
+				));
+				assert!(PhalaRegistry::tee_pruntime_allowlist(TeeType::Tdx).is_empty());
+			});
+		}
+
 		#[test]
 		fn test_relaychain_genesis_block_hash_allowlist_works() {
 			new_test_ext().execute_with(|| {
@@ -254,6 +254,11 @@ pub mod pallet {
 			}
 			w
 		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade() -> Result<(), &'static str> {
+			Self::check_invariants()
+		}
 	}
 
 	#[pallet::call]
@@ -636,7 +641,7 @@ pub mod pallet {
 			);
 			let miner: T::AccountId = pool_sub_account(pid, &worker);
 			// Mining::stop_mining will notify us how much it will release by `on_stopped`
-			<mining::pallet::Pallet<T>>::stop_mining(miner)?;
+			<mining::pallet::Pallet<T>>::stop_mining(miner, mining::StopReason::Voluntary)?;
 
 			Ok(())
 		}
@@ -734,8 +739,8 @@ pub mod pallet {
 		/// Called when worker was reclaimed.
 		///
 		/// After the cool down ends, worker was cleaned up, whose contributed balance would be
-		/// reset to zero.
-		fn handle_reclaim(pid: u64, orig_stake: BalanceOf<T>, slashed: BalanceOf<T>) {
+		/// reset to zero. Returns the number of the withdraw requests processed.
+		fn handle_reclaim(pid: u64, orig_stake: BalanceOf<T>, slashed: BalanceOf<T>) -> u32 {
 			let mut pool_info = Self::ensure_pool(pid).expect("Stake pool must exist; qed.");
 
 			let returned = orig_stake - slashed;
@@ -755,8 +760,9 @@ pub mod pallet {
 			pool_info.free_stake.saturating_accrue(returned);
 			pool_info.releasing_stake.saturating_reduce(returned);
 
-			Self::try_process_withdraw_queue(&mut pool_info);
+			let processed = Self::try_process_withdraw_queue(&mut pool_info);
 			StakePools::<T>::insert(&pid, &pool_info);
+			processed
 		}
 
 		/// Tries to withdraw a specific amount from a pool.
@@ -834,16 +840,20 @@ pub mod pallet {
 		}
 
 		/// Tries to fulfill the withdraw queue with the newly freed stake
-		fn try_process_withdraw_queue(pool_info: &mut PoolInfo<T::AccountId, BalanceOf<T>>) {
+		///
+		/// Returns the number of the withdraw requests processed.
+		fn try_process_withdraw_queue(pool_info: &mut PoolInfo<T::AccountId, BalanceOf<T>>) -> u32 {
 			// The share price shouldn't change at any point in this function. So we can calculate
 			// only once at the beginning.
 			let price = match pool_info.share_price() {
 				Some(price) => price,
-				None => return,
+				None => return 0,
 			};
 
+			let mut processed = 0;
 			while is_nondust_balance(pool_info.free_stake) {
 				if let Some(mut withdraw) = pool_info.withdraw_queue.front().cloned() {
+					processed += 1;
 					// Must clear the pending reward before any stake change
 					let info_key = (pool_info.pid, withdraw.user.clone());
 					let mut user_info = match Self::pool_stakers(&info_key) {
@@ -901,6 +911,7 @@ pub mod pallet {
 					break;
 				}
 			}
+			processed
 		}
 
 		/// Updates a user's locked balance. Doesn't check the amount is less than the free amount!
@@ -923,6 +934,38 @@ pub mod pallet {
 			}
 		}
 
+		/// Checks the releasing stake of the pools matches their miners in cool down
+		///
+		/// Iterates over all the pool miners. Only for try-runtime and tests.
+		#[cfg(any(feature = "try-runtime", test))]
+		pub fn check_invariants() -> Result<(), &'static str> {
+			use sp_std::collections::btree_map::BTreeMap;
+			let mut releasing: BTreeMap<u64, BalanceOf<T>> = BTreeMap::new();
+			for (miner, (pid, _worker)) in SubAccountPreimages::<T>::iter() {
+				let info = match mining::Miners::<T>::get(&miner) {
+					Some(info) => info,
+					None => continue,
+				};
+				if info.state != mining::MinerState::MiningCoolingDown {
+					continue;
+				}
+				let orig_stake = mining::Stakes::<T>::get(&miner).unwrap_or_default();
+				let (returned, _slashed) = info.calc_final_stake(orig_stake);
+				releasing
+					.entry(pid)
+					.or_insert_with(Zero::zero)
+					.saturating_accrue(returned);
+			}
+			for (pid, pool_info) in StakePools::<T>::iter() {
+				let expected = releasing.get(&pid).cloned().unwrap_or_default();
+				ensure!(
+					pool_info.releasing_stake == expected,
+					"Pool releasing stake mismatches the miners in cool down"
+				);
+			}
+			Ok(())
+		}
+
 		/// Gets the pool record by `pid`. Returns error if not exist
 		fn ensure_pool(pid: u64) -> Result<PoolInfo<T::AccountId, BalanceOf<T>>, Error<T>> {
 			Self::stake_pools(&pid).ok_or(Error::<T>::PoolDoesNotExist)
@@ -1020,7 +1063,10 @@ pub mod pallet {
 						for worker in pool.workers {
 							let miner: T::AccountId = pool_sub_account(pid, &worker);
 							// TODO: avoid stop mining multiple times?
-							let _ = <mining::pallet::Pallet<T>>::stop_mining(miner);
+							let _ = <mining::pallet::Pallet<T>>::stop_mining(
+								miner,
+								mining::StopReason::ForceWithdrawal,
+							);
 						}
 					}
 				}
@@ -1087,7 +1133,12 @@ pub mod pallet {
 		BalanceOf<T>: FixedPointConvert + Display,
 	{
 		/// Called when a worker is stopped and there is releasing stake
-		fn on_stopped(worker: &WorkerPublicKey, orig_stake: BalanceOf<T>, slashed: BalanceOf<T>) {
+		fn on_stopped(
+			worker: &WorkerPublicKey,
+			orig_stake: BalanceOf<T>,
+			slashed: BalanceOf<T>,
+			_reason: mining::StopReason,
+		) {
 			let pid = WorkerAssignments::<T>::get(worker)
 				.expect("Stopping workers have assignment; qed.");
 			let mut pool_info = Self::ensure_pool(pid).expect("Stake pool must exist; qed.");
@@ -1095,6 +1146,54 @@ pub mod pallet {
 			pool_info.releasing_stake.saturating_accrue(returned);
 			StakePools::<T>::insert(pid, pool_info);
 		}
+
+		/// Called when a late settlement in cool down changes the releasing stake
+		fn on_slash_updated(
+			worker: &WorkerPublicKey,
+			orig_stake: BalanceOf<T>,
+			prev_slashed: BalanceOf<T>,
+			slashed: BalanceOf<T>,
+		) {
+			let pid = match WorkerAssignments::<T>::get(worker) {
+				Some(pid) => pid,
+				None => return,
+			};
+			let mut pool_info = Self::ensure_pool(pid).expect("Stake pool must exist; qed.");
+			// Replace the previously predicted returned stake with the corrected one
+			let prev_returned = orig_stake - prev_slashed;
+			let returned = orig_stake - slashed;
+			pool_info.releasing_stake.saturating_reduce(prev_returned);
+			pool_info.releasing_stake.saturating_accrue(returned);
+			StakePools::<T>::insert(pid, pool_info);
+		}
+	}
+
+	impl<T: Config> mining::OnReclaimed<T::AccountId, BalanceOf<T>> for Pallet<T>
+	where
+		T: mining::Config<Currency = <T as Config>::Currency>,
+		BalanceOf<T>: FixedPointConvert + Display,
+	{
+		/// Only the miners started by a pool can be reclaimed automatically
+		fn can_auto_reclaim(miner: &T::AccountId) -> bool {
+			SubAccountPreimages::<T>::contains_key(miner)
+		}
+
+		/// Called when a pool miner is reclaimed after the cool down, following the same path of
+		/// `reclaim_pool_worker()`
+		///
+		/// Each processed withdraw request updates the staker, its ledger and its lock.
+		fn on_reclaimed(
+			miner: &T::AccountId,
+			orig_stake: BalanceOf<T>,
+			slashed: BalanceOf<T>,
+		) -> Weight {
+			let mut w = T::DbWeight::get().reads_writes(1, 1);
+			if let Some((pid, _worker)) = SubAccountPreimages::<T>::take(miner) {
+				let processed = Self::handle_reclaim(pid, orig_stake, slashed) as Weight;
+				w += T::DbWeight::get().reads_writes(1 + 3 * processed, 1 + 3 * processed);
+			}
+			w
+		}
 	}
 
 	impl<T: Config> Ledger<T::AccountId, BalanceOf<T>> for Pallet<T>
@@ -1447,9 +1546,10 @@ pub mod pallet {
 
 		use super::*;
 		use crate::mock::{
-			ecdh_pubkey, elapse_cool_down, elapse_seconds, new_test_ext, set_block_1,
-			setup_workers, setup_workers_linked_operators, take_events, teleport_to_block,
-			worker_pubkey, Balance, BlockNumber, Event as TestEvent, Origin, Test, DOLLARS,
+			check_invariants, ecdh_pubkey, elapse_cool_down, elapse_seconds, new_test_ext,
+			set_block_1, setup_workers, setup_workers_linked_operators, take_events,
+			teleport_to_block, worker_pubkey, Balance, BlockNumber, Event as TestEvent, Origin,
+			Test, DOLLARS,
 		};
 		// Pallets
 		use crate::mock::{
@@ -1991,6 +2091,159 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_late_settle_in_cool_down() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					500 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					500 * DOLLARS
+				));
+				let sub_account1: u64 = pool_sub_account(0, &worker_pubkey(1));
+				let miner = PhalaMining::miners(sub_account1).unwrap();
+				let ve = FixedPoint::from_bits(miner.ve);
+				// Stop without any slash. The whole stake is releasing.
+				assert_ok!(PhalaStakePool::stop_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 500 * DOLLARS);
+				check_invariants();
+				// A delayed settlement slashes 50% during the cool down
+				let _ = take_events();
+				simulate_v_update(1, (ve / 2).to_bits());
+				let ev = take_events();
+				assert_matches!(
+					ev.as_slice(),
+					[TestEvent::PhalaMining(mining::Event::MinerSettled(_, v, 0))]
+					if FixedPoint::from_bits(*v) == ve / 2
+				);
+				let miner = PhalaMining::miners(sub_account1).unwrap();
+				assert_eq!(miner.state, mining::MinerState::MiningCoolingDown);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 250 * DOLLARS);
+				check_invariants();
+				// V may also recover in a later settlement
+				simulate_v_update(1, ve.to_bits());
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 500 * DOLLARS);
+				simulate_v_update(1, (ve / 2).to_bits());
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 250 * DOLLARS);
+				// Reclaim settles exactly the corrected slash
+				elapse_cool_down();
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::reclaim_pool_worker(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				let ev = take_events();
+				assert_eq!(
+					ev,
+					vec![
+						TestEvent::PhalaMining(mining::Event::MinerReclaimed(
+							sub_account1,
+							500 * DOLLARS,
+							250 * DOLLARS
+						)),
+						TestEvent::PhalaStakePool(Event::PoolSlashed(0, 250 * DOLLARS)),
+					]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 0);
+				assert_eq!(pool.free_stake, 250 * DOLLARS);
+				assert_eq!(pool.total_stake, 250 * DOLLARS);
+				check_invariants();
+			});
+		}
+
+		#[test]
+		fn test_auto_reclaim() {
+			use frame_support::traits::Hooks;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				setup_pool_with_workers(1, &[1, 2]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					1000 * DOLLARS
+				));
+				for i in [1, 2] {
+					assert_ok!(PhalaStakePool::start_mining(
+						Origin::signed(1),
+						0,
+						worker_pubkey(i),
+						500 * DOLLARS
+					));
+				}
+				let sub_account1: u64 = pool_sub_account(0, &worker_pubkey(1));
+				// Stop worker2 later than worker1
+				assert_ok!(PhalaStakePool::stop_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				elapse_seconds(100);
+				assert_ok!(PhalaStakePool::stop_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(2)
+				));
+				assert_eq!(mining::CoolDownQueueRange::<Test>::get(), (0, 2));
+				// Nothing to reclaim before the cool down ends
+				let _ = take_events();
+				<PhalaMining as Hooks<BlockNumber>>::on_idle(1, Weight::MAX);
+				assert_eq!(take_events(), vec![]);
+				// Only worker1 finished the cool down
+				elapse_seconds(PhalaMining::cool_down_period() - 50);
+				// Not enough weight for any reclaim
+				assert_eq!(<PhalaMining as Hooks<BlockNumber>>::on_idle(1, 0), 0);
+				<PhalaMining as Hooks<BlockNumber>>::on_idle(1, Weight::MAX);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaMining(mining::Event::MinerReclaimed(
+						sub_account1,
+						500 * DOLLARS,
+						0
+					))]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 500 * DOLLARS);
+				assert_eq!(pool.free_stake, 500 * DOLLARS);
+				assert!(!SubAccountPreimages::<Test>::contains_key(sub_account1));
+				assert_eq!(mining::CoolDownQueueRange::<Test>::get(), (1, 2));
+				check_invariants();
+				// Outdated entries are skipped after a manual reclaim
+				elapse_seconds(100);
+				assert_ok!(PhalaStakePool::reclaim_pool_worker(
+					Origin::signed(1),
+					0,
+					worker_pubkey(2)
+				));
+				let _ = take_events();
+				<PhalaMining as Hooks<BlockNumber>>::on_idle(1, Weight::MAX);
+				assert_eq!(take_events(), vec![]);
+				assert_eq!(mining::CoolDownQueueRange::<Test>::get(), (2, 2));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 0);
+				assert_eq!(pool.free_stake, 1000 * DOLLARS);
+				check_invariants();
+			});
+		}
+
 		#[test]
 		fn test_no_contribution_to_bankrupt_pool() {
 			new_test_ext().execute_with(|| {
@@ -2396,8 +2649,13 @@ pub mod pallet {
 				// Trigger a force clear by `on_reclaim()`, releasing 100 PHA stake to partially
 				// fulfill staker2's withdraw request, but leaving staker1's untouched.
 				let _ = take_events();
-				PhalaStakePool::on_stopped(&worker_pubkey(2), 100 * DOLLARS, 0);
-				PhalaStakePool::handle_reclaim(0, 100 * DOLLARS, 0);
+				PhalaStakePool::on_stopped(
+					&worker_pubkey(2),
+					100 * DOLLARS,
+					0,
+					mining::StopReason::Voluntary,
+				);
+				assert_eq!(PhalaStakePool::handle_reclaim(0, 100 * DOLLARS, 0), 1);
 				assert_eq!(
 					take_events().as_slice(),
 					[TestEvent::PhalaStakePool(Event::Withdrawal(
@@ -2416,8 +2674,16 @@ pub mod pallet {
 				// Trigger another force clear with 100 PHA slashed, releasing all 400 PHA stake
 				// (100 slashed & 300 free), fulfilling stakers' requests.
 				let _ = take_events();
-				PhalaStakePool::on_stopped(&worker_pubkey(1), 400 * DOLLARS, 100 * DOLLARS);
-				PhalaStakePool::handle_reclaim(0, 400 * DOLLARS, 100 * DOLLARS);
+				PhalaStakePool::on_stopped(
+					&worker_pubkey(1),
+					400 * DOLLARS,
+					100 * DOLLARS,
+					mining::StopReason::Voluntary,
+				);
+				assert_eq!(
+					PhalaStakePool::handle_reclaim(0, 400 * DOLLARS, 100 * DOLLARS),
+					2
+				);
 				assert_eq!(
 					take_events().as_slice(),
 					[
@@ -2852,6 +3118,7 @@ pub mod pallet {
 				let miner2 = PhalaMining::miners(&sub_account2).unwrap();
 				assert_eq!(miner1.state, mining::MinerState::MiningCoolingDown);
 				assert_eq!(miner2.state, mining::MinerState::MiningCoolingDown);
+				check_invariants();
 				// Wait the cool down period
 				elapse_cool_down();
 				assert_ok!(PhalaStakePool::reclaim_pool_worker(
@@ -1,5 +1,14 @@
 use crate::constants::*;
 
+mod dcap;
+mod x509;
+pub use dcap::{is_valid_root_cert, validate_dcap_quote, DcapCollateral};
+
+/// Returns if `der` is a well-formed certificate revocation list
+pub fn is_valid_crl(der: &[u8]) -> bool {
+	x509::parse_crl(der).is_some()
+}
+
 use codec::{Decode, Encode};
 use scale_info::TypeInfo;
 use sp_std::{
@@ -8,6 +17,8 @@ use sp_std::{
 	vec::Vec,
 };
 
+pub use phala_types::TeeType;
+
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
 pub enum Attestation {
 	SgxIas {
@@ -15,17 +26,86 @@ pub enum Attestation {
 		signature: Vec<u8>,
 		raw_signing_cert: Vec<u8>,
 	},
+	SgxDcap {
+		quote: Vec<u8>,
+		collateral: DcapCollateral,
+	},
+	/// The evidence of a TEE without a built-in validator, to be verified by an
+	/// `AttestationValidator` plugged into the runtime
+	///
+	/// None of the runtimes has such a validator yet (`IasValidator` and `DcapValidator` reject
+	/// it), so this path is only exercised by the `MockValidator` of the tests.
+	Generic {
+		tee_type: TeeType,
+		evidence: Vec<u8>,
+	},
+}
+
+impl Attestation {
+	/// Returns the type of the TEE the attestation comes from
+	pub fn tee_type(&self) -> TeeType {
+		match self {
+			Attestation::SgxIas { .. } | Attestation::SgxDcap { .. } => TeeType::Sgx,
+			Attestation::Generic { tee_type, .. } => *tee_type,
+		}
+	}
+}
+
+/// The governance-managed certificates the attestations are validated against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustStore {
+	/// DER encoded root CAs of the DCAP certificate chains
+	pub dcap_root_certs: Vec<Vec<u8>>,
+	/// DER encoded root CAs of the IAS signing certificate, replacing the built-in ones if not
+	/// empty
+	pub ias_root_certs: Vec<Vec<u8>>,
+	/// DER encoded CRLs. The certificates revoked by any of them are rejected.
+	pub crls: Vec<Vec<u8>>,
 }
 
 pub trait AttestationValidator {
 	/// Validates the attestation as well as the user data hash it commits to.
+	///
+	/// `pruntime_allowlist` is the allow list of the TEE type of the attestation.
 	fn validate(
 		attestation: &Attestation,
 		user_data_hash: &[u8; 32],
 		now: u64,
 		verify_pruntime_hash: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
-	) -> Result<IasFields, Error>;
+		trust_store: &TrustStore,
+	) -> Result<ConfidentialReport, Error>;
+}
+
+/// Tries the validators in order, until one of them supports the attestation type
+impl<A: AttestationValidator, B: AttestationValidator> AttestationValidator for (A, B) {
+	fn validate(
+		attestation: &Attestation,
+		user_data_hash: &[u8; 32],
+		now: u64,
+		verify_pruntime: bool,
+		pruntime_allowlist: Vec<Vec<u8>>,
+		trust_store: &TrustStore,
+	) -> Result<ConfidentialReport, Error> {
+		match A::validate(
+			attestation,
+			user_data_hash,
+			now,
+			verify_pruntime,
+			pruntime_allowlist.clone(),
+			trust_store,
+		) {
+			Err(Error::UnsupportedAttestationType) => B::validate(
+				attestation,
+				user_data_hash,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+				trust_store,
+			),
+			result => result,
+		}
+	}
 }
 
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
@@ -38,18 +118,50 @@ pub enum Error {
 	OutdatedIASReport,
 	UnknownQuoteBodyFormat,
 	InvalidUserDataHash,
+	// DCAP related
+	UnsupportedAttestationType,
+	UnsupportedQuoteFormat,
+	MalformedQuote,
+	InvalidPckCertChain,
+	InvalidQuoteSignature,
+	InvalidQeReport,
+	InvalidCollateral,
+	OutdatedCollateral,
+	TcbMismatch,
+	TcbRevoked,
+	CertRevoked,
 }
 
+/// The TEE-agnostic measurement record of an attested pRuntime
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
-pub struct IasFields {
-	pub mr_enclave: [u8; 32],
-	pub mr_signer: [u8; 32],
-	pub isv_prod_id: [u8; 2],
-	pub isv_svn: [u8; 2],
+pub struct ConfidentialReport {
+	pub tee_type: TeeType,
+	/// The measurement of the pRuntime, as listed in the allow list of the TEE type
+	///
+	/// For SGX, it's `mr_enclave ++ isv_prod_id ++ isv_svn ++ mr_signer`.
+	pub measurement: Vec<u8>,
 	pub report_data: [u8; 64],
 	pub confidence_level: u8,
 }
 
+impl ConfidentialReport {
+	fn sgx(
+		mr_enclave: &[u8],
+		mr_signer: &[u8],
+		isv_prod_id: &[u8],
+		isv_svn: &[u8],
+		report_data: &[u8],
+		confidence_level: u8,
+	) -> Self {
+		ConfidentialReport {
+			tee_type: TeeType::Sgx,
+			measurement: extend_mrenclave(mr_enclave, mr_signer, isv_prod_id, isv_svn),
+			report_data: report_data.try_into().unwrap(),
+			confidence_level,
+		}
+	}
+}
+
 /// Attestation validator implementation for IAS
 pub struct IasValidator;
 impl AttestationValidator for IasValidator {
@@ -59,7 +171,8 @@ impl AttestationValidator for IasValidator {
 		now: u64,
 		verify_pruntime: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
-	) -> Result<IasFields, Error> {
+		trust_store: &TrustStore,
+	) -> Result<ConfidentialReport, Error> {
 		let fields = match attestation {
 			Attestation::SgxIas {
 				ra_report,
@@ -72,14 +185,49 @@ impl AttestationValidator for IasValidator {
 				now,
 				verify_pruntime,
 				pruntime_allowlist,
+				trust_store,
 			),
+			_ => Err(Error::UnsupportedAttestationType),
 		}?;
-		let commit = &fields.report_data[..32];
-		if commit != user_data_hash {
-			Err(Error::InvalidUserDataHash)
-		} else {
-			Ok(fields)
-		}
+		check_user_data_hash(fields, user_data_hash)
+	}
+}
+
+/// Attestation validator implementation for DCAP
+pub struct DcapValidator;
+impl AttestationValidator for DcapValidator {
+	fn validate(
+		attestation: &Attestation,
+		user_data_hash: &[u8; 32],
+		now: u64,
+		verify_pruntime: bool,
+		pruntime_allowlist: Vec<Vec<u8>>,
+		trust_store: &TrustStore,
+	) -> Result<ConfidentialReport, Error> {
+		let fields = match attestation {
+			Attestation::SgxDcap { quote, collateral } => validate_dcap_quote(
+				quote,
+				collateral,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+				trust_store,
+			),
+			_ => Err(Error::UnsupportedAttestationType),
+		}?;
+		check_user_data_hash(fields, user_data_hash)
+	}
+}
+
+fn check_user_data_hash(
+	fields: ConfidentialReport,
+	user_data_hash: &[u8; 32],
+) -> Result<ConfidentialReport, Error> {
+	let commit = &fields.report_data[..32];
+	if commit != user_data_hash {
+		Err(Error::InvalidUserDataHash)
+	} else {
+		Ok(fields)
 	}
 }
 
@@ -104,7 +252,8 @@ pub fn validate_ias_report(
 	now: u64,
 	verify_pruntime: bool,
 	pruntime_allowlist: Vec<Vec<u8>>,
-) -> Result<IasFields, Error> {
+	trust_store: &TrustStore,
+) -> Result<ConfidentialReport, Error> {
 	// Validate report
 	let sig_cert = webpki::EndEntityCert::try_from(raw_signing_cert);
 	let sig_cert = sig_cert.or(Err(Error::InvalidIASSigningCert))?;
@@ -114,13 +263,23 @@ pub fn validate_ias_report(
 	// Validate certificate
 	let chain: Vec<&[u8]> = Vec::new();
 	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
-	let tls_server_cert_valid = sig_cert.verify_is_valid_tls_server_cert(
-		SUPPORTED_SIG_ALGS,
-		&IAS_SERVER_ROOTS,
-		&chain,
-		time_now,
-	);
+	// Prefer the governance-managed roots over the built-in ones
+	let anchors: Vec<webpki::TrustAnchor> = trust_store
+		.ias_root_certs
+		.iter()
+		.filter_map(|cert| webpki::TrustAnchor::try_from_cert_der(cert).ok())
+		.collect();
+	let roots = if anchors.is_empty() {
+		webpki::TlsServerTrustAnchors(IAS_SERVER_ROOTS.0)
+	} else {
+		webpki::TlsServerTrustAnchors(&anchors)
+	};
+	let tls_server_cert_valid =
+		sig_cert.verify_is_valid_tls_server_cert(SUPPORTED_SIG_ALGS, &roots, &chain, time_now);
 	tls_server_cert_valid.or(Err(Error::InvalidIASSigningCert))?;
+	if x509::is_revoked(raw_signing_cert, &trust_store.crls) {
+		return Err(Error::CertRevoked);
+	}
 	// Validate related fields
 	let parsed_report: serde_json::Value =
 		serde_json::from_slice(report).or(Err(Error::InvalidReport))?;
@@ -184,14 +343,14 @@ pub fn validate_ias_report(
 		}
 	}
 	// Check the following fields
-	Ok(IasFields {
-		mr_enclave: mr_enclave.try_into().unwrap(),
-		mr_signer: mr_signer.try_into().unwrap(),
-		isv_prod_id: isv_prod_id.try_into().unwrap(),
-		isv_svn: isv_svn.try_into().unwrap(),
-		report_data: (&quote_body[368..432]).try_into().unwrap(),
+	Ok(ConfidentialReport::sgx(
+		mr_enclave,
+		mr_signer,
+		isv_prod_id,
+		isv_svn,
+		&quote_body[368..432],
 		confidence_level,
-	})
+	))
 }
 
 #[cfg(test)]
@@ -219,7 +378,8 @@ mod test {
 				&raw_signing_cert,
 				ATTESTATION_TIMESTAMP + 10000000,
 				false,
-				vec![]
+				vec![],
+				&TrustStore::default()
 			),
 			Err(Error::OutdatedIASReport)
 		);
@@ -231,7 +391,8 @@ mod test {
 				&raw_signing_cert,
 				ATTESTATION_TIMESTAMP,
 				true,
-				vec![]
+				vec![],
+				&TrustStore::default()
 			),
 			Err(Error::PRuntimeRejected)
 		);
@@ -242,7 +403,48 @@ mod test {
 			&raw_signing_cert,
 			ATTESTATION_TIMESTAMP,
 			true,
-			vec![hex::decode(PRUNTIME_HASH).unwrap()]
+			vec![hex::decode(PRUNTIME_HASH).unwrap()],
+			&TrustStore::default()
 		));
 	}
+
+	#[test]
+	fn test_ias_governance_trust_store() {
+		let sample: serde_json::Value = serde_json::from_slice(ATTESTATION_SAMPLE).unwrap();
+		let report = sample["raReport"].as_str().unwrap().as_bytes();
+		let signature = hex::decode(sample["signature"].as_str().unwrap().as_bytes()).unwrap();
+		let raw_signing_cert =
+			hex::decode(sample["rawSigningCert"].as_str().unwrap().as_bytes()).unwrap();
+		let validate = |trust_store: &TrustStore| {
+			validate_ias_report(
+				report,
+				&signature,
+				&raw_signing_cert,
+				ATTESTATION_TIMESTAMP,
+				false,
+				vec![],
+				trust_store,
+			)
+		};
+
+		// The governance roots replace the built-in ones
+		let dcap_sample: serde_json::Value =
+			serde_json::from_slice(include_bytes!("../../sample/dcap_attestation.json")).unwrap();
+		let other_root = hex::decode(dcap_sample["rootCaCert"].as_str().unwrap()).unwrap();
+		let trust_store = TrustStore {
+			ias_root_certs: vec![other_root],
+			..Default::default()
+		};
+		assert_eq!(validate(&trust_store), Err(Error::InvalidIASSigningCert));
+
+		// Revoked by the CRL of the IAS signing CA
+		let crl = include_bytes!("../../sample/ias_revocation_list.der").to_vec();
+		assert!(is_valid_crl(&crl));
+		assert!(!is_valid_crl(&[1, 2, 3]));
+		let trust_store = TrustStore {
+			crls: vec![crl],
+			..Default::default()
+		};
+		assert_eq!(validate(&trust_store), Err(Error::CertRevoked));
+	}
 }
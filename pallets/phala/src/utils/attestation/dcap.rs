@@ -0,0 +1,834 @@
+//! Intel SGX DCAP (ECDSA) quote verification
+//!
+//! Verifies a quote (v3 or v4) against the PCK certificate chain embedded in the quote and the
+//! TCB info / QE identity collateral issued by Intel (fetched from a PCCS by the worker). All the
+//! certificate chains must be rooted at one of the governance-registered root CAs.
+
+use super::{
+	extend_mrenclave,
+	x509::{der_read, der_uint, find_extension, is_revoked},
+	ConfidentialReport, Error, TrustStore,
+};
+use crate::constants::{DCAP_SIG_ALGS, IAS_QUOTE_ADVISORY_ID_WHITELIST};
+
+use codec::{Decode, Encode};
+use scale_info::TypeInfo;
+use sp_std::{
+	convert::{TryFrom, TryInto},
+	vec::Vec,
+};
+
+const HEADER_LEN: usize = 48;
+const REPORT_LEN: usize = 384;
+const ECDSA_SIG_LEN: usize = 64;
+const ECDSA_PUBKEY_LEN: usize = 64;
+
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const TEE_TYPE_SGX: u32 = 0;
+/// Certification data: PEM encoded PCK certificate chain
+const CERT_DATA_PCK_CHAIN: u16 = 5;
+/// Certification data: QE report certification data (quote v4)
+const CERT_DATA_QE_REPORT: u16 = 6;
+
+/// DER encoded OID of the Intel SGX extension (1.2.840.113741.1.13.1)
+const SGX_EXTENSION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01];
+const SGX_TCB_COMPONENTS: usize = 16;
+const TCB_COMPONENT_KEYS: [&str; SGX_TCB_COMPONENTS] = [
+	"sgxtcbcomp01svn",
+	"sgxtcbcomp02svn",
+	"sgxtcbcomp03svn",
+	"sgxtcbcomp04svn",
+	"sgxtcbcomp05svn",
+	"sgxtcbcomp06svn",
+	"sgxtcbcomp07svn",
+	"sgxtcbcomp08svn",
+	"sgxtcbcomp09svn",
+	"sgxtcbcomp10svn",
+	"sgxtcbcomp11svn",
+	"sgxtcbcomp12svn",
+	"sgxtcbcomp13svn",
+	"sgxtcbcomp14svn",
+	"sgxtcbcomp15svn",
+	"sgxtcbcomp16svn",
+];
+
+/// The collateral to verify the TCB status of a DCAP quote
+///
+/// The JSON bodies must be kept byte-to-byte as issued by Intel, since the signatures are over
+/// the raw bytes.
+#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+pub struct DcapCollateral {
+	/// PEM encoded certificate chain of the TCB info signing key
+	pub tcb_info_issuer_chain: Vec<u8>,
+	/// The `tcbInfo` JSON object
+	pub tcb_info: Vec<u8>,
+	/// Raw ECDSA signature (r || s) of `tcb_info`
+	pub tcb_info_signature: Vec<u8>,
+	/// PEM encoded certificate chain of the QE identity signing key
+	pub qe_identity_issuer_chain: Vec<u8>,
+	/// The `enclaveIdentity` JSON object of the Quoting Enclave
+	pub qe_identity: Vec<u8>,
+	/// Raw ECDSA signature (r || s) of `qe_identity`
+	pub qe_identity_signature: Vec<u8>,
+}
+
+/// The parsed fields of a quote we are interested in
+struct Quote<'a> {
+	/// The signed part of the quote (header and the ISV enclave report)
+	signed: &'a [u8],
+	/// The ISV enclave report
+	report: &'a [u8],
+	isv_signature: &'a [u8],
+	attestation_key: &'a [u8],
+	qe_report: &'a [u8],
+	qe_report_signature: &'a [u8],
+	qe_auth_data: &'a [u8],
+	pck_chain: &'a [u8],
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.0.len() < len {
+			return Err(Error::MalformedQuote);
+		}
+		let (head, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(head)
+	}
+	fn u16(&mut self) -> Result<u16, Error> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+	}
+	fn u32(&mut self) -> Result<u32, Error> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+}
+
+impl<'a> Quote<'a> {
+	fn parse(quote: &'a [u8]) -> Result<Self, Error> {
+		let mut reader = Reader(quote);
+		let header = reader.take(HEADER_LEN)?;
+		let version = u16::from_le_bytes([header[0], header[1]]);
+		let key_type = u16::from_le_bytes([header[2], header[3]]);
+		let tee_type = u32::from_le_bytes(header[4..8].try_into().unwrap());
+		if !(version == 3 || version == 4) || key_type != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+			return Err(Error::UnsupportedQuoteFormat);
+		}
+		// The field is reserved (zero) in v3
+		if tee_type != TEE_TYPE_SGX {
+			return Err(Error::UnsupportedQuoteFormat);
+		}
+		let report = reader.take(REPORT_LEN)?;
+		let signed = &quote[..HEADER_LEN + REPORT_LEN];
+
+		let sig_data_len = reader.u32()? as usize;
+		let mut sig_data = Reader(reader.take(sig_data_len)?);
+		let isv_signature = sig_data.take(ECDSA_SIG_LEN)?;
+		let attestation_key = sig_data.take(ECDSA_PUBKEY_LEN)?;
+		// In v4, the QE report is wrapped by another layer of certification data
+		let mut qe_data = if version == 4 {
+			let cert_type = sig_data.u16()?;
+			let cert_len = sig_data.u32()? as usize;
+			if cert_type != CERT_DATA_QE_REPORT {
+				return Err(Error::UnsupportedQuoteFormat);
+			}
+			Reader(sig_data.take(cert_len)?)
+		} else {
+			sig_data
+		};
+		let qe_report = qe_data.take(REPORT_LEN)?;
+		let qe_report_signature = qe_data.take(ECDSA_SIG_LEN)?;
+		let qe_auth_len = qe_data.u16()? as usize;
+		let qe_auth_data = qe_data.take(qe_auth_len)?;
+		let cert_type = qe_data.u16()?;
+		let cert_len = qe_data.u32()? as usize;
+		if cert_type != CERT_DATA_PCK_CHAIN {
+			return Err(Error::UnsupportedQuoteFormat);
+		}
+		let pck_chain = qe_data.take(cert_len)?;
+
+		Ok(Quote {
+			signed,
+			report,
+			isv_signature,
+			attestation_key,
+			qe_report,
+			qe_report_signature,
+			qe_auth_data,
+			pck_chain,
+		})
+	}
+}
+
+/// The TCB of a platform, extracted from the SGX extension of its PCK certificate
+#[derive(Debug, PartialEq, Eq)]
+struct PlatformTcb {
+	components: [u8; SGX_TCB_COMPONENTS],
+	pce_svn: u16,
+	pce_id: Vec<u8>,
+	fmspc: Vec<u8>,
+}
+
+/// Returns if `der` is a certificate accepted as a trust anchor of the DCAP chains
+pub fn is_valid_root_cert(der: &[u8]) -> bool {
+	webpki::TrustAnchor::try_from_cert_der(der).is_ok()
+}
+
+/// Validates a DCAP quote with its collateral
+///
+/// All the certificate chains must chain up to `trust_store.dcap_root_certs`, and none of the
+/// certificates can be revoked by `trust_store.crls`.
+pub fn validate_dcap_quote(
+	quote: &[u8],
+	collateral: &DcapCollateral,
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+	trust_store: &TrustStore,
+) -> Result<ConfidentialReport, Error> {
+	let quote = Quote::parse(quote)?;
+	let anchors: Vec<webpki::TrustAnchor> = trust_store
+		.dcap_root_certs
+		.iter()
+		.filter_map(|cert| webpki::TrustAnchor::try_from_cert_der(cert).ok())
+		.collect();
+	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
+
+	// Validate the PCK certificate chain and the QE report signed by the PCK key
+	let pck_chain = decode_pem_chain(quote.pck_chain).ok_or(Error::InvalidPckCertChain)?;
+	let pck_cert = verify_cert_chain(&pck_chain, &anchors, &trust_store.crls, time_now)
+		.map_err(|err| err.unwrap_or(Error::InvalidPckCertChain))?;
+	let qe_report_signature =
+		der_ecdsa_signature(quote.qe_report_signature).ok_or(Error::InvalidQuoteSignature)?;
+	pck_cert
+		.verify_signature(
+			&webpki::ECDSA_P256_SHA256,
+			quote.qe_report,
+			&qe_report_signature,
+		)
+		.or(Err(Error::InvalidQuoteSignature))?;
+
+	// The QE report commits to the attestation key
+	let mut key_and_auth = quote.attestation_key.to_vec();
+	key_and_auth.extend_from_slice(quote.qe_auth_data);
+	let key_hash = crate::hashing::sha2_256(&key_and_auth);
+	let qe_report_data = &quote.qe_report[320..384];
+	if qe_report_data[..32] != key_hash || qe_report_data[32..].iter().any(|b| *b != 0) {
+		return Err(Error::InvalidQeReport);
+	}
+
+	// The ISV enclave report is signed by the attestation key
+	let mut attestation_key = [4u8; 1 + ECDSA_PUBKEY_LEN];
+	attestation_key[1..].copy_from_slice(quote.attestation_key);
+	ring::signature::UnparsedPublicKey::new(
+		&ring::signature::ECDSA_P256_SHA256_FIXED,
+		&attestation_key[..],
+	)
+	.verify(quote.signed, quote.isv_signature)
+	.or(Err(Error::InvalidQuoteSignature))?;
+
+	// Validate PRuntime
+	let report = quote.report;
+	let mr_enclave = &report[64..96];
+	let mr_signer = &report[128..160];
+	let isv_prod_id = &report[256..258];
+	let isv_svn = &report[258..260];
+	if verify_pruntime {
+		let t_mrenclave = extend_mrenclave(mr_enclave, mr_signer, isv_prod_id, isv_svn);
+		if !pruntime_allowlist.contains(&t_mrenclave) {
+			return Err(Error::PRuntimeRejected);
+		}
+	}
+
+	// Evaluate the TCB status of the platform and the QE
+	let tcb_info = verify_collateral(
+		&collateral.tcb_info_issuer_chain,
+		&collateral.tcb_info,
+		&collateral.tcb_info_signature,
+		&anchors,
+		&trust_store.crls,
+		now,
+	)?;
+	let platform_tcb = pck_chain
+		.first()
+		.and_then(|cert| parse_platform_tcb(cert))
+		.ok_or(Error::InvalidPckCertChain)?;
+	let tcb_level = evaluate_platform_tcb(&tcb_info, &platform_tcb)?;
+
+	let qe_identity = verify_collateral(
+		&collateral.qe_identity_issuer_chain,
+		&collateral.qe_identity,
+		&collateral.qe_identity_signature,
+		&anchors,
+		&trust_store.crls,
+		now,
+	)?;
+	let qe_level = evaluate_qe_identity(&qe_identity, quote.qe_report)?;
+
+	Ok(ConfidentialReport::sgx(
+		mr_enclave,
+		mr_signer,
+		isv_prod_id,
+		isv_svn,
+		&report[320..384],
+		tcb_level.max(qe_level),
+	))
+}
+
+/// Verifies the certificate chain (leaf first) to the anchors, returning the leaf certificate
+///
+/// Fails with `Some(Error::CertRevoked)` if any certificate in the chain is revoked, or `None`
+/// if the chain is invalid.
+fn verify_cert_chain<'a>(
+	chain: &'a [Vec<u8>],
+	anchors: &[webpki::TrustAnchor],
+	crls: &[Vec<u8>],
+	time_now: webpki::Time,
+) -> Result<webpki::EndEntityCert<'a>, Option<Error>> {
+	let leaf = chain.first().ok_or(None)?;
+	let cert = webpki::EndEntityCert::try_from(&leaf[..]).or(Err(None))?;
+	let intermediates: Vec<&[u8]> = chain[1..].iter().map(|c| &c[..]).collect();
+	cert.verify_is_valid_tls_server_cert(
+		DCAP_SIG_ALGS,
+		&webpki::TlsServerTrustAnchors(anchors),
+		&intermediates,
+		time_now,
+	)
+	.or(Err(None))?;
+	if chain.iter().any(|c| is_revoked(c, crls)) {
+		return Err(Some(Error::CertRevoked));
+	}
+	Ok(cert)
+}
+
+/// Verifies the signature and the freshness of a piece of collateral, returning the parsed JSON
+fn verify_collateral(
+	issuer_chain: &[u8],
+	body: &[u8],
+	signature: &[u8],
+	anchors: &[webpki::TrustAnchor],
+	crls: &[Vec<u8>],
+	now: u64,
+) -> Result<serde_json::Value, Error> {
+	let chain = decode_pem_chain(issuer_chain).ok_or(Error::InvalidCollateral)?;
+	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
+	let signer = verify_cert_chain(&chain, anchors, crls, time_now)
+		.map_err(|err| err.unwrap_or(Error::InvalidCollateral))?;
+	let signature = der_ecdsa_signature(signature).ok_or(Error::InvalidCollateral)?;
+	signer
+		.verify_signature(&webpki::ECDSA_P256_SHA256, body, &signature)
+		.or(Err(Error::InvalidCollateral))?;
+
+	let parsed: serde_json::Value =
+		serde_json::from_slice(body).or(Err(Error::InvalidCollateral))?;
+	let next_update = parsed["nextUpdate"]
+		.as_str()
+		.ok_or(Error::InvalidCollateral)?;
+	let next_update = chrono::DateTime::parse_from_rfc3339(next_update)
+		.or(Err(Error::InvalidCollateral))?
+		.timestamp();
+	if now as i64 >= next_update {
+		return Err(Error::OutdatedCollateral);
+	}
+	Ok(parsed)
+}
+
+/// Finds the TCB level of the platform in the TCB info, and converts it to the confidence level
+fn evaluate_platform_tcb(
+	tcb_info: &serde_json::Value,
+	platform: &PlatformTcb,
+) -> Result<u8, Error> {
+	let fmspc = parse_hex_field(&tcb_info["fmspc"])?;
+	let pce_id = parse_hex_field(&tcb_info["pceId"])?;
+	if fmspc != platform.fmspc || pce_id != platform.pce_id {
+		return Err(Error::TcbMismatch);
+	}
+	let levels = tcb_info["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidCollateral)?;
+	// The levels are sorted from the latest to the oldest. Take the first one the platform meets.
+	for level in levels {
+		let tcb = &level["tcb"];
+		let pce_svn = tcb["pcesvn"].as_u64().ok_or(Error::InvalidCollateral)?;
+		let mut components = [0u64; SGX_TCB_COMPONENTS];
+		match tcb["sgxtcbcomponents"].as_array() {
+			// TCB info v3
+			Some(list) => {
+				if list.len() != SGX_TCB_COMPONENTS {
+					return Err(Error::InvalidCollateral);
+				}
+				for (c, item) in components.iter_mut().zip(list) {
+					*c = item["svn"].as_u64().ok_or(Error::InvalidCollateral)?;
+				}
+			}
+			// TCB info v2
+			None => {
+				for (c, key) in components.iter_mut().zip(TCB_COMPONENT_KEYS.iter()) {
+					*c = tcb[*key].as_u64().ok_or(Error::InvalidCollateral)?;
+				}
+			}
+		}
+		let meets = platform
+			.components
+			.iter()
+			.zip(components.iter())
+			.all(|(platform_svn, level_svn)| *platform_svn as u64 >= *level_svn)
+			&& platform.pce_svn as u64 >= pce_svn;
+		if meets {
+			return tcb_status_level(level);
+		}
+	}
+	Err(Error::TcbMismatch)
+}
+
+/// Checks the QE report against the QE identity, returning the confidence level of its TCB
+fn evaluate_qe_identity(qe_identity: &serde_json::Value, qe_report: &[u8]) -> Result<u8, Error> {
+	let misc_select = u32::from_le_bytes(qe_report[16..20].try_into().unwrap());
+	let attributes = &qe_report[48..64];
+	let mr_signer = &qe_report[128..160];
+	let isv_prod_id = u16::from_le_bytes([qe_report[256], qe_report[257]]);
+	let isv_svn = u16::from_le_bytes([qe_report[258], qe_report[259]]);
+
+	let expected_mr_signer = parse_hex_field(&qe_identity["mrsigner"])?;
+	let expected_prod_id = qe_identity["isvprodid"]
+		.as_u64()
+		.ok_or(Error::InvalidCollateral)?;
+	let expected_misc_select = parse_u32_field(&qe_identity["miscselect"])?;
+	let misc_select_mask = parse_u32_field(&qe_identity["miscselectMask"])?;
+	let expected_attributes = parse_hex_field(&qe_identity["attributes"])?;
+	let attributes_mask = parse_hex_field(&qe_identity["attributesMask"])?;
+	if attributes_mask.len() != attributes.len() || expected_attributes.len() != attributes.len() {
+		return Err(Error::InvalidCollateral);
+	}
+	let attributes_match = attributes
+		.iter()
+		.zip(attributes_mask.iter())
+		.map(|(a, mask)| a & mask)
+		.eq(expected_attributes.iter().cloned());
+	if expected_mr_signer != mr_signer
+		|| expected_prod_id != isv_prod_id as u64
+		|| misc_select & misc_select_mask != expected_misc_select
+		|| !attributes_match
+	{
+		return Err(Error::InvalidQeReport);
+	}
+
+	let levels = qe_identity["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidCollateral)?;
+	for level in levels {
+		let level_svn = level["tcb"]["isvsvn"]
+			.as_u64()
+			.ok_or(Error::InvalidCollateral)?;
+		if isv_svn as u64 >= level_svn {
+			return tcb_status_level(level);
+		}
+	}
+	Err(Error::TcbMismatch)
+}
+
+/// Converts the status of a TCB level to the confidence level, in the same way as IAS
+fn tcb_status_level(level: &serde_json::Value) -> Result<u8, Error> {
+	let status = level["tcbStatus"].as_str().unwrap_or("UNKNOWN");
+	let mut confidence_level = match status {
+		"UpToDate" => 1,
+		"SWHardeningNeeded" => 2,
+		"ConfigurationNeeded" | "ConfigurationAndSWHardeningNeeded" => 3,
+		"OutOfDate" | "OutOfDateConfigurationNeeded" => 5,
+		"Revoked" => return Err(Error::TcbRevoked),
+		_ => return Err(Error::InvalidQuoteStatus),
+	};
+	if confidence_level < 5 {
+		// Filter advisory ids. `advisoryIDs` is optional
+		if let Some(advisory_ids) = level["advisoryIDs"].as_array() {
+			for advisory_id in advisory_ids {
+				let advisory_id = advisory_id.as_str().ok_or(Error::InvalidCollateral)?;
+				if !IAS_QUOTE_ADVISORY_ID_WHITELIST.contains(&advisory_id) {
+					confidence_level = 4;
+				}
+			}
+		}
+	}
+	Ok(confidence_level)
+}
+
+fn parse_hex_field(value: &serde_json::Value) -> Result<Vec<u8>, Error> {
+	let s = value.as_str().ok_or(Error::InvalidCollateral)?;
+	hex::decode(s).or(Err(Error::InvalidCollateral))
+}
+
+fn parse_u32_field(value: &serde_json::Value) -> Result<u32, Error> {
+	let bytes = parse_hex_field(value)?;
+	let bytes: [u8; 4] = bytes.try_into().or(Err(Error::InvalidCollateral))?;
+	Ok(u32::from_be_bytes(bytes))
+}
+
+/// Decodes the PEM certificates in a chain to DER
+///
+/// A trailing NUL (as in the certification data of a quote) is allowed.
+fn decode_pem_chain(pem: &[u8]) -> Option<Vec<Vec<u8>>> {
+	const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+	const END: &str = "-----END CERTIFICATE-----";
+	let pem = core::str::from_utf8(pem).ok()?.trim_end_matches('\0');
+	let mut certs = Vec::new();
+	let mut rest = pem;
+	while let Some(start) = rest.find(BEGIN) {
+		let body = &rest[start + BEGIN.len()..];
+		let end = body.find(END)?;
+		let b64: Vec<u8> = body[..end]
+			.bytes()
+			.filter(|c| !c.is_ascii_whitespace())
+			.collect();
+		certs.push(base64::decode(&b64).ok()?);
+		rest = &body[end + END.len()..];
+	}
+	if certs.is_empty() {
+		None
+	} else {
+		Some(certs)
+	}
+}
+
+/// Converts a raw ECDSA signature (r || s) to the ASN.1 DER form accepted by webpki
+fn der_ecdsa_signature(raw: &[u8]) -> Option<Vec<u8>> {
+	if raw.len() != ECDSA_SIG_LEN {
+		return None;
+	}
+	fn encode_uint(bytes: &[u8]) -> Vec<u8> {
+		let first_nonzero = bytes
+			.iter()
+			.position(|b| *b != 0)
+			.unwrap_or(bytes.len() - 1);
+		let bytes = &bytes[first_nonzero..];
+		let mut out = Vec::with_capacity(bytes.len() + 3);
+		out.push(0x02);
+		if bytes[0] & 0x80 != 0 {
+			out.push(bytes.len() as u8 + 1);
+			out.push(0);
+		} else {
+			out.push(bytes.len() as u8);
+		}
+		out.extend_from_slice(bytes);
+		out
+	}
+	let r = encode_uint(&raw[..32]);
+	let s = encode_uint(&raw[32..]);
+	let mut out = Vec::with_capacity(r.len() + s.len() + 2);
+	out.push(0x30);
+	out.push((r.len() + s.len()) as u8);
+	out.extend_from_slice(&r);
+	out.extend_from_slice(&s);
+	Some(out)
+}
+
+/// Extracts the platform TCB from the SGX extension of a PCK certificate
+fn parse_platform_tcb(pck_cert: &[u8]) -> Option<PlatformTcb> {
+	let ext = find_extension(pck_cert, SGX_EXTENSION_OID)?;
+	let (_, mut items, _) = der_read(ext)?;
+	let mut tcb = None;
+	let mut pce_id = None;
+	let mut fmspc = None;
+	while !items.is_empty() {
+		let (_, item, rest) = der_read(items)?;
+		items = rest;
+		let (_, oid, value) = der_read(item)?;
+		let (_, value, _) = der_read(value)?;
+		if oid.len() != SGX_EXTENSION_OID.len() + 1 || !oid.starts_with(SGX_EXTENSION_OID) {
+			continue;
+		}
+		match oid[SGX_EXTENSION_OID.len()] {
+			2 => tcb = Some(parse_tcb_components(value)?),
+			3 => pce_id = Some(value.to_vec()),
+			4 => fmspc = Some(value.to_vec()),
+			_ => {}
+		}
+	}
+	let (components, pce_svn) = tcb?;
+	Some(PlatformTcb {
+		components,
+		pce_svn,
+		pce_id: pce_id?,
+		fmspc: fmspc?,
+	})
+}
+
+/// Parses the TCB sequence of the SGX extension as `(component_svns, pce_svn)`
+fn parse_tcb_components(mut items: &[u8]) -> Option<([u8; SGX_TCB_COMPONENTS], u16)> {
+	let mut components = [0u8; SGX_TCB_COMPONENTS];
+	let mut pce_svn = None;
+	while !items.is_empty() {
+		let (_, item, rest) = der_read(items)?;
+		items = rest;
+		let (_, oid, value) = der_read(item)?;
+		let (tag, value, _) = der_read(value)?;
+		// The OIDs of the TCB items are `SGX_EXTENSION_OID.2.n`
+		if oid.len() != SGX_EXTENSION_OID.len() + 2 || tag != 0x02 {
+			continue;
+		}
+		match oid[SGX_EXTENSION_OID.len() + 1] as usize {
+			n @ 1..=SGX_TCB_COMPONENTS => {
+				components[n - 1] = der_uint(value)?.try_into().ok()?;
+			}
+			17 => pce_svn = Some(der_uint(value)?),
+			_ => {}
+		}
+	}
+	Some((components, pce_svn?))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// A quote and its collateral generated with a test root CA (the platform is at TCB
+	/// `SWHardeningNeeded` with a non-whitelisted advisory, and the QE is `UpToDate`)
+	///
+	/// It only agrees with the generator that wrote it, so it's used for the negative cases. The
+	/// acceptance of real quotes is checked by `test_dcap_validator_intel_sample`.
+	pub const DCAP_SAMPLE: &[u8] = include_bytes!("../../../sample/dcap_attestation.json");
+	/// A v3 quote recorded on a real SGX platform, with the collateral fetched from the Intel PCS
+	/// and the Intel SGX Root CA certificate
+	///
+	/// The keys are the ones of `DCAP_SAMPLE` plus `verifiedAt` (the fixed `now` of the check),
+	/// `pruntimeHash` and `confidenceLevel`.
+	const DCAP_INTEL_SAMPLE_PATH: &str = "sample/dcap_intel_v3_attestation.json";
+	pub const DCAP_TIMESTAMP: u64 = 1654819200; // 2022-06-10T00:00:00Z
+	pub const PRUNTIME_HASH: &str = "518422fa769d2d55982015a0e0417c6a8521fdfc7308f5ec18aaa1b6924bd0f300000000815f42f11cf64430c30bab7816ba596a1da0130c3b028b673133a66cf9a3e0e6";
+
+	fn hex_field(sample: &serde_json::Value, key: &str) -> Vec<u8> {
+		hex::decode(sample[key].as_str().unwrap()).unwrap()
+	}
+
+	fn str_field(sample: &serde_json::Value, key: &str) -> Vec<u8> {
+		sample[key].as_str().unwrap().as_bytes().to_vec()
+	}
+
+	fn load_sample() -> (serde_json::Value, DcapCollateral) {
+		collateral_of(serde_json::from_slice(DCAP_SAMPLE).unwrap())
+	}
+
+	fn collateral_of(sample: serde_json::Value) -> (serde_json::Value, DcapCollateral) {
+		let collateral = DcapCollateral {
+			tcb_info_issuer_chain: str_field(&sample, "tcbInfoIssuerChain"),
+			tcb_info: str_field(&sample, "tcbInfo"),
+			tcb_info_signature: hex_field(&sample, "tcbInfoSignature"),
+			qe_identity_issuer_chain: str_field(&sample, "qeIdentityIssuerChain"),
+			qe_identity: str_field(&sample, "qeIdentity"),
+			qe_identity_signature: hex_field(&sample, "qeIdentitySignature"),
+		};
+		(sample, collateral)
+	}
+
+	fn sample_trust_store(sample: &serde_json::Value) -> TrustStore {
+		TrustStore {
+			dcap_root_certs: vec![hex_field(sample, "rootCaCert")],
+			..Default::default()
+		}
+	}
+
+	#[test]
+	#[ignore = "needs sample/dcap_intel_v3_attestation.json recorded on an SGX platform"]
+	fn test_dcap_validator_intel_sample() {
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(DCAP_INTEL_SAMPLE_PATH);
+		let (sample, collateral) =
+			collateral_of(serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap());
+		let trust_store = sample_trust_store(&sample);
+		let now = sample["verifiedAt"].as_u64().unwrap();
+		let allowlist = vec![hex_field(&sample, "pruntimeHash")];
+		assert!(is_valid_root_cert(&trust_store.dcap_root_certs[0]));
+
+		let quote = hex_field(&sample, "quoteV3");
+		let fields = validate_dcap_quote(
+			&quote,
+			&collateral,
+			now,
+			true,
+			allowlist.clone(),
+			&trust_store,
+		)
+		.unwrap();
+		assert_eq!(
+			fields.confidence_level as u64,
+			sample["confidenceLevel"].as_u64().unwrap()
+		);
+		assert_eq!(
+			&fields.report_data[..32],
+			&hex_field(&sample, "userDataHash")[..]
+		);
+		assert_eq!(
+			validate_dcap_quote(&quote, &collateral, now, true, vec![], &trust_store),
+			Err(Error::PRuntimeRejected)
+		);
+	}
+
+	#[test]
+	fn test_dcap_validator() {
+		let (sample, collateral) = load_sample();
+		let trust_store = sample_trust_store(&sample);
+		let allowlist = vec![hex::decode(PRUNTIME_HASH).unwrap()];
+		assert!(is_valid_root_cert(&trust_store.dcap_root_certs[0]));
+
+		for key in ["quoteV3", "quoteV4"] {
+			let quote = hex_field(&sample, key);
+			// The untouched sample is accepted, so the rejections below are caused by the changes
+			assert!(validate_dcap_quote(
+				&quote,
+				&collateral,
+				DCAP_TIMESTAMP,
+				true,
+				allowlist.clone(),
+				&trust_store,
+			)
+			.is_ok());
+
+			assert_eq!(
+				validate_dcap_quote(
+					&quote,
+					&collateral,
+					DCAP_TIMESTAMP,
+					true,
+					vec![],
+					&trust_store
+				),
+				Err(Error::PRuntimeRejected)
+			);
+			// Not chained to the governance roots
+			assert_eq!(
+				validate_dcap_quote(
+					&quote,
+					&collateral,
+					DCAP_TIMESTAMP,
+					false,
+					vec![],
+					&TrustStore::default()
+				),
+				Err(Error::InvalidPckCertChain)
+			);
+			// Collateral expired
+			assert_eq!(
+				validate_dcap_quote(
+					&quote,
+					&collateral,
+					DCAP_TIMESTAMP + 30 * 24 * 3600,
+					false,
+					vec![],
+					&trust_store
+				),
+				Err(Error::OutdatedCollateral)
+			);
+		}
+	}
+
+	#[test]
+	fn test_dcap_tampered() {
+		let (sample, collateral) = load_sample();
+		let trust_store = sample_trust_store(&sample);
+		let quote = hex_field(&sample, "quoteV3");
+
+		// Tampered report data
+		let mut tampered = quote.clone();
+		tampered[HEADER_LEN + 320] ^= 1;
+		assert_eq!(
+			validate_dcap_quote(
+				&tampered,
+				&collateral,
+				DCAP_TIMESTAMP,
+				false,
+				vec![],
+				&trust_store
+			),
+			Err(Error::InvalidQuoteSignature)
+		);
+		// Tampered collateral
+		let mut bad_collateral = collateral.clone();
+		bad_collateral.tcb_info[10] ^= 1;
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&bad_collateral,
+				DCAP_TIMESTAMP,
+				false,
+				vec![],
+				&trust_store
+			),
+			Err(Error::InvalidCollateral)
+		);
+		// Truncated or unknown quotes
+		assert_eq!(
+			validate_dcap_quote(
+				&quote[..500],
+				&collateral,
+				DCAP_TIMESTAMP,
+				false,
+				vec![],
+				&trust_store
+			),
+			Err(Error::MalformedQuote)
+		);
+		let mut v2 = quote;
+		v2[0] = 2;
+		assert_eq!(
+			validate_dcap_quote(
+				&v2,
+				&collateral,
+				DCAP_TIMESTAMP,
+				false,
+				vec![],
+				&trust_store
+			),
+			Err(Error::UnsupportedQuoteFormat)
+		);
+	}
+
+	#[test]
+	fn test_dcap_revoked() {
+		let (sample, collateral) = load_sample();
+		let mut trust_store = sample_trust_store(&sample);
+		let quote = hex_field(&sample, "quoteV4");
+		// The root CA CRL doesn't revoke any certificate in use
+		trust_store.crls = vec![hex_field(&sample, "rootCaCrl")];
+		assert!(validate_dcap_quote(
+			&quote,
+			&collateral,
+			DCAP_TIMESTAMP,
+			false,
+			vec![],
+			&trust_store
+		)
+		.is_ok());
+		// The PCK CRL revokes the PCK certificate of the platform
+		trust_store.crls.push(hex_field(&sample, "pckCrl"));
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&collateral,
+				DCAP_TIMESTAMP,
+				false,
+				vec![],
+				&trust_store
+			),
+			Err(Error::CertRevoked)
+		);
+	}
+
+	#[test]
+	fn test_parse_platform_tcb() {
+		let (sample, _) = load_sample();
+		let quote = hex_field(&sample, "quoteV3");
+		let quote = Quote::parse(&quote).unwrap();
+		let chain = decode_pem_chain(quote.pck_chain).unwrap();
+		assert_eq!(chain.len(), 3);
+		assert_eq!(
+			parse_platform_tcb(&chain[0]),
+			Some(PlatformTcb {
+				components: [10, 10, 2, 2, 2, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0],
+				pce_svn: 11,
+				pce_id: vec![0, 0],
+				fmspc: hex::decode("00906ed50000").unwrap(),
+			})
+		);
+		// No SGX extension in a CA certificate
+		assert_eq!(parse_platform_tcb(&chain[1]), None);
+	}
+}
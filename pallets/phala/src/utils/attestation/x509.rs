@@ -0,0 +1,160 @@
+//! Minimal DER parsing of X.509 certificates and CRLs
+//!
+//! Only the fields required by the attestation validators are extracted. The certificate
+//! signatures are verified by `webpki`, not here.
+//!
+//! The CRLs are trusted as supplied by the governance: their signatures, issuers and
+//! `thisUpdate`/`nextUpdate` are not checked, so an outdated CRL stays in effect until replaced.
+
+use sp_std::vec::Vec;
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+
+/// Reads a DER TLV, returning `(tag, value, rest)`
+pub(super) fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+	let (&tag, input) = input.split_first()?;
+	let (&len, mut input) = input.split_first()?;
+	let len = if len < 0x80 {
+		len as usize
+	} else {
+		let n = (len & 0x7f) as usize;
+		if n == 0 || n > 4 || input.len() < n {
+			return None;
+		}
+		let len = input[..n]
+			.iter()
+			.fold(0usize, |acc, b| (acc << 8) | *b as usize);
+		input = &input[n..];
+		len
+	};
+	if input.len() < len {
+		return None;
+	}
+	Some((tag, &input[..len], &input[len..]))
+}
+
+/// Reads a small unsigned DER INTEGER
+pub(super) fn der_uint(value: &[u8]) -> Option<u16> {
+	if value.is_empty() || value[0] & 0x80 != 0 {
+		return None;
+	}
+	let value = match value.iter().position(|b| *b != 0) {
+		Some(i) => &value[i..],
+		None => return Some(0),
+	};
+	match value.len() {
+		1 => Some(value[0] as u16),
+		2 => Some(u16::from_be_bytes([value[0], value[1]])),
+		_ => None,
+	}
+}
+
+/// Returns the value of the extension with the given OID in a DER encoded certificate
+pub(super) fn find_extension<'a>(cert: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+	const EXTENSIONS: u8 = 0xa3;
+	let (cert, _) = der_expect(cert, SEQUENCE)?;
+	let (mut tbs, _) = der_expect(cert, SEQUENCE)?;
+	while !tbs.is_empty() {
+		let (tag, value, rest) = der_read(tbs)?;
+		tbs = rest;
+		if tag != EXTENSIONS {
+			continue;
+		}
+		let (mut extensions, _) = der_expect(value, SEQUENCE)?;
+		while !extensions.is_empty() {
+			let (extension, rest) = der_expect(extensions, SEQUENCE)?;
+			extensions = rest;
+			let (_, ext_oid, mut ext_rest) = der_read(extension)?;
+			if ext_oid != oid {
+				continue;
+			}
+			// Skip the optional `critical` flag
+			loop {
+				let (tag, value, rest) = der_read(ext_rest)?;
+				if tag == OCTET_STRING {
+					return Some(value);
+				}
+				ext_rest = rest;
+			}
+		}
+	}
+	None
+}
+
+/// Reads a DER TLV with the expected tag, returning `(value, rest)`
+fn der_expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+	match der_read(input)? {
+		(t, value, rest) if t == tag => Some((value, rest)),
+		_ => None,
+	}
+}
+
+/// Returns the `(issuer, serial_number)` of a DER encoded certificate
+///
+/// The issuer is the DER content of the `Name`, comparable with `Crl::issuer`.
+pub(super) fn issuer_and_serial(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+	const VERSION: u8 = 0xa0;
+	let (cert, _) = der_expect(cert, SEQUENCE)?;
+	let (mut tbs, _) = der_expect(cert, SEQUENCE)?;
+	if let Some((_, rest)) = der_expect(tbs, VERSION) {
+		tbs = rest;
+	}
+	let (serial, tbs) = der_expect(tbs, INTEGER)?;
+	let (_signature_alg, tbs) = der_expect(tbs, SEQUENCE)?;
+	let (issuer, _) = der_expect(tbs, SEQUENCE)?;
+	Some((issuer, serial))
+}
+
+/// A parsed certificate revocation list
+pub(super) struct Crl<'a> {
+	pub issuer: &'a [u8],
+	pub revoked_serials: Vec<&'a [u8]>,
+}
+
+/// Parses a DER encoded CRL (RFC 5280 `CertificateList`)
+pub(super) fn parse_crl(crl: &[u8]) -> Option<Crl<'_>> {
+	let (crl, _) = der_expect(crl, SEQUENCE)?;
+	let (mut tbs, _) = der_expect(crl, SEQUENCE)?;
+	if let Some((_, rest)) = der_expect(tbs, INTEGER) {
+		tbs = rest;
+	}
+	let (_signature_alg, tbs) = der_expect(tbs, SEQUENCE)?;
+	let (issuer, tbs) = der_expect(tbs, SEQUENCE)?;
+	// thisUpdate, followed by the optional nextUpdate
+	let (_, _, mut tbs) = der_read(tbs)?;
+	let mut revoked_serials = Vec::new();
+	while !tbs.is_empty() {
+		let (tag, value, rest) = der_read(tbs)?;
+		tbs = rest;
+		if tag != SEQUENCE {
+			continue;
+		}
+		let mut entries = value;
+		while !entries.is_empty() {
+			let (entry, rest) = der_expect(entries, SEQUENCE)?;
+			entries = rest;
+			let (serial, _) = der_expect(entry, INTEGER)?;
+			revoked_serials.push(serial);
+		}
+	}
+	Some(Crl {
+		issuer,
+		revoked_serials,
+	})
+}
+
+/// Returns if the certificate is revoked by any of the CRLs
+///
+/// Malformed certificates are considered revoked. Malformed CRLs are ignored, as they are
+/// checked when set by the governance. The CRLs are not authenticated, see the module doc.
+pub(super) fn is_revoked(cert: &[u8], crls: &[Vec<u8>]) -> bool {
+	let (issuer, serial) = match issuer_and_serial(cert) {
+		Some(fields) => fields,
+		None => return true,
+	};
+	crls.iter()
+		.filter_map(|crl| parse_crl(crl))
+		.any(|crl| crl.issuer == issuer && crl.revoked_serials.contains(&serial))
+}
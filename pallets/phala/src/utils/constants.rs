@@ -26,6 +26,9 @@ pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	&webpki::RSA_PKCS1_3072_8192_SHA384,
 ];
 
+/// The signature algorithms of the Intel SGX DCAP certificates
+pub static DCAP_SIG_ALGS: SignatureAlgorithms = &[&webpki::ECDSA_P256_SHA256];
+
 pub static IAS_SERVER_ROOTS: webpki::TlsServerTrustAnchors = webpki::TlsServerTrustAnchors(&[
     /*
      * -----BEGIN CERTIFICATE-----
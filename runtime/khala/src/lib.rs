@@ -1452,16 +1452,20 @@ parameter_types! {
     pub const MaxPoolWorkers: u32 = 200;
     pub const VerifyPRuntime: bool = true;
     pub const VerifyRelaychainGenesisBlockHash: bool = true;
+    pub const TopicPrefixDeposit: Balance = deposit(1, 128);
 }
 
 impl pallet_registry::Config for Runtime {
     type Event = Event;
     type Currency = Balances;
-    type AttestationValidator = pallet_registry::IasValidator;
+    type AttestationValidator = (pallet_registry::IasValidator, pallet_registry::DcapValidator);
     type UnixTime = Timestamp;
     type VerifyPRuntime = VerifyPRuntime;
     type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
     type GovernanceOrigin = EnsureRootOrHalfCouncil;
+    type BoundWorkers = PhalaMining;
+    type ExpectedBlockTimeSec = ExpectedBlockTimeSec;
+    type TopicPrefixDeposit = TopicPrefixDeposit;
 }
 impl pallet_mq::Config for Runtime {
     type QueueNotifyConfig = msg_routing::MessageRouteConfig;
@@ -1476,6 +1480,7 @@ impl pallet_mining::Config for Runtime {
     type OnReward = PhalaStakePool;
     type OnUnbound = PhalaStakePool;
     type OnStopped = PhalaStakePool;
+    type OnReclaimed = PhalaStakePool;
     type OnTreasurySettled = Treasury;
     type UpdateTokenomicOrigin = EnsureRootOrHalfCouncil;
 }
@@ -1629,6 +1634,20 @@ impl_runtime_apis! {
         }
     }
 
+    impl pallet_mining_runtime_api::MiningApi<Block, AccountId, Balance> for Runtime {
+        fn performance_history(miner: AccountId) -> Vec<u32> {
+            PhalaMining::performance_history(miner)
+        }
+
+        fn halving_period() -> u32 {
+            PhalaMining::halving_period()
+        }
+
+        fn subsidy_stats(period: u32) -> phala_types::SubsidyPeriodStats<Balance> {
+            PhalaMining::subsidy_stats(period)
+        }
+    }
+
     impl cumulus_primitives_core::CollectCollationInfo<Block> for Runtime {
         fn collect_collation_info(header: &<Block as BlockT>::Header) -> cumulus_primitives_core::CollationInfo {
             ParachainSystem::collect_collation_info(header)
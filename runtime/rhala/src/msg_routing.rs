@@ -33,6 +33,8 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
             PhalaRegistry::on_message_received,
             PhalaMining::on_gk_message_received,
             PhalaMining::on_mining_message_received,
+            PhalaFatContracts::on_contract_message_received,
+            PhalaFatContracts::on_worker_contract_message_received,
         };
         Ok(())
     }
@@ -102,7 +102,9 @@ use xcm_executor::{Config, XcmExecutor};
 pub use parachains_common::Index;
 pub use parachains_common::*;
 
-pub use phala_pallets::{pallet_mining, pallet_mq, pallet_registry, pallet_stakepool};
+pub use phala_pallets::{
+    pallet_fat, pallet_mining, pallet_mq, pallet_registry, pallet_stakepool,
+};
 
 pub use xtransfer_pallets::{
     pallet_assets_wrapper, pallet_bridge, pallet_bridge_transfer, pallet_xcm_transfer, xcm_helper,
@@ -266,6 +268,7 @@ construct_runtime! {
         PhalaStakePool: pallet_stakepool::{Pallet, Call, Event<T>, Storage} = 88,
         Assets: pallet_assets::{Pallet, Call, Storage, Event<T>} = 89,
         AssetsWrapper: pallet_assets_wrapper::{Pallet, Call, Storage, Event<T>} = 90,
+        PhalaFatContracts: pallet_fat::{Pallet, Call, Event<T>, Storage, Config} = 91,
 
         Sudo: pallet_sudo::{Pallet, Call, Storage, Config<T>, Event<T>} = 99,
         // `OTT` was used in Khala, we avoid to use the index
@@ -340,7 +343,8 @@ impl Contains<Call> for BaseCallFilter {
             Call::Lottery { .. } | Call::Tips { .. } |
             // Phala
             Call::PhalaMq { .. } | Call::PhalaRegistry { .. } |
-            Call::PhalaMining { .. } | Call::PhalaStakePool { .. }
+            Call::PhalaMining { .. } | Call::PhalaStakePool { .. } |
+            Call::PhalaFatContracts { .. }
         )
     }
 }
@@ -1477,16 +1481,20 @@ parameter_types! {
     pub const MaxPoolWorkers: u32 = 200;
     pub const VerifyPRuntime: bool = false;
     pub const VerifyRelaychainGenesisBlockHash: bool = false;
+    pub const TopicPrefixDeposit: Balance = deposit(1, 128);
 }
 
 impl pallet_registry::Config for Runtime {
     type Event = Event;
     type Currency = Balances;
-    type AttestationValidator = pallet_registry::IasValidator;
+    type AttestationValidator = (pallet_registry::IasValidator, pallet_registry::DcapValidator);
     type UnixTime = Timestamp;
     type VerifyPRuntime = VerifyPRuntime;
     type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
     type GovernanceOrigin = EnsureRootOrHalfCouncil;
+    type BoundWorkers = PhalaMining;
+    type ExpectedBlockTimeSec = ExpectedBlockTimeSec;
+    type TopicPrefixDeposit = TopicPrefixDeposit;
 }
 impl pallet_mq::Config for Runtime {
     type QueueNotifyConfig = msg_routing::MessageRouteConfig;
@@ -1501,6 +1509,7 @@ impl pallet_mining::Config for Runtime {
     type OnReward = PhalaStakePool;
     type OnUnbound = PhalaStakePool;
     type OnStopped = PhalaStakePool;
+    type OnReclaimed = PhalaStakePool;
     type OnTreasurySettled = Treasury;
     type UpdateTokenomicOrigin = EnsureRootOrHalfCouncil;
 }
@@ -1515,6 +1524,15 @@ impl pallet_stakepool::Config for Runtime {
     type MiningSwitchOrigin = EnsureRootOrHalfCouncil;
     type BackfillOrigin = EnsureRootOrHalfCouncil;
 }
+parameter_types! {
+    pub const CodeDepositPerByte: Balance = deposit(0, 1);
+}
+impl pallet_fat::Config for Runtime {
+    type Event = Event;
+    type Currency = Balances;
+    type CodeDepositPerByte = CodeDepositPerByte;
+    type GovernanceOrigin = EnsureRootOrHalfCouncil;
+}
 
 #[cfg(feature = "runtime-benchmarks")]
 #[macro_use]
@@ -1654,6 +1672,20 @@ impl_runtime_apis! {
         }
     }
 
+    impl pallet_mining_runtime_api::MiningApi<Block, AccountId, Balance> for Runtime {
+        fn performance_history(miner: AccountId) -> Vec<u32> {
+            PhalaMining::performance_history(miner)
+        }
+
+        fn halving_period() -> u32 {
+            PhalaMining::halving_period()
+        }
+
+        fn subsidy_stats(period: u32) -> phala_types::SubsidyPeriodStats<Balance> {
+            PhalaMining::subsidy_stats(period)
+        }
+    }
+
     impl cumulus_primitives_core::CollectCollationInfo<Block> for Runtime {
         fn collect_collation_info(header: &<Block as BlockT>::Header) -> cumulus_primitives_core::CollationInfo {
             ParachainSystem::collect_collation_info(header)
@@ -33,7 +33,84 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
             PhalaRegistry::on_message_received,
             PhalaMining::on_gk_message_received,
             PhalaMining::on_mining_message_received,
+            PhalaFatContracts::on_contract_message_received,
+            PhalaFatContracts::on_worker_contract_message_received,
         };
         Ok(())
     }
 }
+
+// Rhala routes the fat contract messages the same way, so they are only tested here
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pallet_mq::QueueNotifyConfig;
+    use crate::{pallet_fat, pallet_registry, Event, Runtime, System};
+    use codec::Encode;
+    use frame_support::traits::GenesisBuild;
+    use phala_types::messaging::{MessageOrigin, WorkerContractReport};
+    use sp_core::{sr25519, H256};
+
+    fn new_test_ext() -> sp_io::TestExternalities {
+        let storage = frame_system::GenesisConfig::default()
+            .build_storage::<Runtime>()
+            .unwrap();
+        let mut ext = sp_io::TestExternalities::new(storage);
+        ext.execute_with(|| System::set_block_number(1));
+        ext
+    }
+
+    fn message<Msg: Encode + BindTopic>(sender: MessageOrigin, payload: Msg) -> Message {
+        Message::new(sender, Msg::topic(), payload.encode())
+    }
+
+    #[test]
+    fn routes_contract_registry_event() {
+        new_test_ext().execute_with(|| {
+            let contract = H256::repeat_byte(1);
+            let pubkey = sr25519::Public::from_raw([2u8; 32]);
+            let event = pallet_fat::ContractRegistryEvent::PubkeyAvailable { contract, pubkey };
+            assert_eq!(
+                MessageRouteConfig::on_message_received(&message(MessageOrigin::Gatekeeper, event)),
+                Ok(())
+            );
+            assert_eq!(
+                pallet_registry::ContractKeys::<Runtime>::get(contract),
+                Some(pubkey)
+            );
+            assert!(System::events().iter().any(|r| r.event
+                == Event::PhalaFatContracts(pallet_fat::Event::PubkeyAvailable {
+                    contract,
+                    pubkey
+                })));
+        });
+    }
+
+    #[test]
+    fn routes_worker_contract_report() {
+        new_test_ext().execute_with(|| {
+            let report = || WorkerContractReport::ContractInstantiationFailed {
+                id: H256::repeat_byte(1),
+                cluster_id: H256::repeat_byte(2),
+                deployer: H256::zero(),
+            };
+            // Rejected by the fat pallet, so it's routed there
+            assert_eq!(
+                MessageRouteConfig::on_message_received(&message(
+                    MessageOrigin::Gatekeeper,
+                    report()
+                )),
+                Err(pallet_fat::Error::<Runtime>::InvalidSender.into())
+            );
+            // The report of an unknown contract is ignored
+            let worker = sr25519::Public::from_raw([3u8; 32]);
+            assert_eq!(
+                MessageRouteConfig::on_message_received(&message(
+                    MessageOrigin::Worker(worker),
+                    report()
+                )),
+                Ok(())
+            );
+        });
+    }
+}